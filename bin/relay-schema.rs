@@ -2,11 +2,16 @@ use relay_auth::AuthRequest;
 use relay_core::events::client_event::ClientExternalEvent;
 use relay_core::events::master_event::MasterExternalEvent;
 use relay_core::model::client_metadata::ClientMetadata;
+use relay_core::model::client_role::ClientRole;
 use relay_core::model::external_error::ExternalError;
 use relay_core::model::master_metadata::MasterMetadata;
+use relay_core::model::patch_format::PatchFormat;
+use relay_core::model::raw_payload;
+use relay_core::model::session_filter::SessionFilter;
 use relay_core::CLIENT;
 use relay_core::MASTER;
 use serde::Serialize;
+use serde_json::json;
 use std::error::Error;
 use std::fmt::Debug;
 
@@ -41,6 +46,7 @@ fn main() {
             error: Some(ExternalError {
                 error_code: 1,
                 error_reason: format!("Some message"),
+                undelivered: Vec::new(),
             }),
         },
     );
@@ -61,6 +67,8 @@ fn main() {
         ClientExternalEvent::Join {
             transaction_id: format!("123"),
             session_id: format!("Hello-world-session"),
+            role: ClientRole::Player,
+            waitlist: false,
         },
     );
 
@@ -70,6 +78,21 @@ fn main() {
         ClientExternalEvent::MessageFromClient {
             transaction_id: format!("123"),
             data: format!("hello"),
+            payload: None,
+            ack: false,
+            unreliable: false,
+        },
+    );
+
+    // Send a message to the master as structured JSON instead of a string
+    trace(
+        CLIENT,
+        ClientExternalEvent::MessageFromClient {
+            transaction_id: format!("123"),
+            data: String::new(),
+            payload: Some(raw_payload::to_raw(&json!({"move": [1, 2]})).unwrap()),
+            ack: false,
+            unreliable: false,
         },
     );
 
@@ -78,6 +101,40 @@ fn main() {
         CLIENT,
         ClientExternalEvent::MessageToClient {
             data: format!("hello"),
+            payload: None,
+            sequence: 1,
+            ack: true,
+        },
+    );
+
+    // Confirm that a message from the master was processed
+    trace(
+        CLIENT,
+        ClientExternalEvent::AckMessage {
+            transaction_id: format!("123"),
+            sequence: 1,
+        },
+    );
+
+    // Ask the master something and wait for its answer
+    trace(
+        CLIENT,
+        ClientExternalEvent::RequestToMaster {
+            transaction_id: format!("123"),
+            request_id: format!("req-1"),
+            data: format!("{{\"buy\": \"sword\"}}"),
+            timeout_ms: Some(5000),
+        },
+    );
+
+    // The master's answer to a request, or the error if it timed out
+    // This is a notification event, not an action by the client.
+    trace(
+        CLIENT,
+        ClientExternalEvent::ResponseToClient {
+            request_id: format!("req-1"),
+            data: format!("{{\"gold\": 90}}"),
+            error: None,
         },
     );
 
@@ -90,6 +147,180 @@ fn main() {
         },
     );
 
+    // The master removed this client from the session
+    trace(
+        CLIENT,
+        ClientExternalEvent::Kicked {
+            reason: format!("Spamming"),
+        },
+    );
+
+    // The groups this client belongs to changed
+    trace(
+        CLIENT,
+        ClientExternalEvent::GroupMembership {
+            groups: vec![format!("red-team")],
+        },
+    );
+
+    // Send a message to another client in the session
+    trace(
+        CLIENT,
+        ClientExternalEvent::MessageToPeer {
+            transaction_id: format!("123"),
+            client_id: format!("123123-213123123"),
+            data: format!("hello"),
+        },
+    );
+
+    // Send a message to every other client in the session
+    trace(
+        CLIENT,
+        ClientExternalEvent::BroadcastToPeers {
+            transaction_id: format!("123"),
+            data: format!("hello"),
+        },
+    );
+
+    // Recv a message from another client in the session
+    trace(
+        CLIENT,
+        ClientExternalEvent::MessageFromPeer {
+            client_id: format!("123123-213123123"),
+            data: format!("hello"),
+        },
+    );
+
+    // Join whichever active session best matches a filter
+    trace(
+        CLIENT,
+        ClientExternalEvent::JoinAny {
+            transaction_id: format!("123"),
+            filter: SessionFilter {
+                tags: vec![format!("ranked")],
+                properties: vec![(format!("region"), format!("eu"))].into_iter().collect(),
+                min_free_slots: 2,
+            },
+            role: ClientRole::Player,
+        },
+    );
+
+    // Join a masterless peer session, creating it if required
+    trace(
+        CLIENT,
+        ClientExternalEvent::JoinPeerSession {
+            transaction_id: format!("123"),
+            session_id: format!("cursors"),
+        },
+    );
+
+    // Leave the current session, keeping the connection open
+    trace(
+        CLIENT,
+        ClientExternalEvent::Leave {
+            transaction_id: format!("123"),
+        },
+    );
+
+    // List the sessions this client can join
+    trace(
+        CLIENT,
+        ClientExternalEvent::ListSessions {
+            transaction_id: format!("123"),
+        },
+    );
+
+    // The sessions this client can join
+    trace(
+        CLIENT,
+        ClientExternalEvent::SessionList {
+            transaction_id: format!("123"),
+            sessions: vec![format!("Lobby"), format!("Ranked")],
+        },
+    );
+
+    // Take back a slot in a session after the connection dropped
+    trace(
+        CLIENT,
+        ClientExternalEvent::Resume {
+            transaction_id: format!("123"),
+            session_id: format!("123123-2131231244"),
+            resume_token: format!("0f1e2d3c4b5a69788796a5b4c3d2e1f0"),
+        },
+    );
+
+    // The token to resume a session with if the connection drops
+    trace(
+        CLIENT,
+        ClientExternalEvent::ResumeToken {
+            token: format!("0f1e2d3c4b5a69788796a5b4c3d2e1f0"),
+        },
+    );
+
+    // The master moved this client to another session
+    trace(
+        CLIENT,
+        ClientExternalEvent::SessionTransferred {
+            session_id: format!("game-1"),
+            success: true,
+            error: None,
+        },
+    );
+
+    // Another client joined the peer session
+    trace(
+        CLIENT,
+        ClientExternalEvent::PeerJoined {
+            client_id: format!("123123-213123123"),
+            name: format!("Doug"),
+        },
+    );
+
+    // Another client left the peer session
+    trace(
+        CLIENT,
+        ClientExternalEvent::PeerLeft {
+            client_id: format!("123123-213123123"),
+            reason: format!("Connection closed"),
+        },
+    );
+
+    // The session is full and this client is waiting for a slot
+    trace(
+        CLIENT,
+        ClientExternalEvent::WaitlistPosition {
+            transaction_id: format!("123"),
+            position: 2,
+        },
+    );
+
+    // Ask for the full shared session state
+    trace(
+        CLIENT,
+        ClientExternalEvent::RequestStateSnapshot {
+            transaction_id: format!("123"),
+        },
+    );
+
+    // The master changed the shared session state
+    trace(
+        CLIENT,
+        ClientExternalEvent::StatePatch {
+            format: PatchFormat::MergePatch,
+            patch: json!({"round": 2, "scores": {"Player 1": 10}}),
+            version: 3,
+        },
+    );
+
+    // The full shared session state
+    trace(
+        CLIENT,
+        ClientExternalEvent::StateSnapshot {
+            state: json!({"round": 2, "scores": {"Player 1": 10, "Player 2": 5}}),
+            version: 3,
+        },
+    );
+
     // Sent by the client application to initialize a new session
     trace(
         MASTER,
@@ -98,6 +329,7 @@ fn main() {
             metadata: MasterMetadata {
                 master_id: format!("Some master"),
                 max_clients: 4,
+                ..Default::default()
             },
         },
     );
@@ -108,6 +340,7 @@ fn main() {
         MasterExternalEvent::ClientJoined {
             client_id: format!("123123-213123123"),
             name: format!("some person"),
+            role: ClientRole::Player,
         },
     );
 
@@ -120,6 +353,7 @@ fn main() {
             error: Some(ExternalError {
                 error_code: 1,
                 error_reason: format!("Some message"),
+                undelivered: Vec::new(),
             }),
         },
     );
@@ -143,12 +377,55 @@ fn main() {
         },
     );
 
+    // A disconnected client came back with a new client id
+    trace(
+        MASTER,
+        MasterExternalEvent::ClientResumed {
+            client_id: format!("123123-213123124"),
+            previous_client_id: format!("123123-213123123"),
+        },
+    );
+
     // Send a message to the external master
     trace(
         MASTER,
         MasterExternalEvent::MessageFromClient {
             client_id: format!("123123-213123123"),
             data: format!("Hello"),
+            payload: None,
+            sequence: 1,
+            ack: true,
+        },
+    );
+
+    // Confirm that a message from a client was processed
+    trace(
+        MASTER,
+        MasterExternalEvent::AckMessage {
+            transaction_id: format!("123123-2131231244"),
+            client_id: format!("123123-213123123"),
+            sequence: 1,
+        },
+    );
+
+    // A client asked something and is waiting for an answer
+    trace(
+        MASTER,
+        MasterExternalEvent::RequestFromClient {
+            client_id: format!("123123-213123123"),
+            request_id: format!("req-1"),
+            data: format!("{{\"buy\": \"sword\"}}"),
+        },
+    );
+
+    // Answer a request from a client
+    trace(
+        MASTER,
+        MasterExternalEvent::ResponseToClient {
+            transaction_id: format!("123123-2131231244"),
+            client_id: format!("123123-213123123"),
+            request_id: format!("req-1"),
+            data: format!("{{\"gold\": 90}}"),
         },
     );
 
@@ -159,6 +436,230 @@ fn main() {
             client_id: format!("123123-213123123"),
             transaction_id: format!("123123-2131231244"),
             data: format!("Hello"),
+            payload: None,
+            ack: false,
+            unreliable: false,
+        },
+    );
+
+    // Recv a message for a client as structured JSON instead of a string
+    trace(
+        MASTER,
+        MasterExternalEvent::MessageToClient {
+            client_id: format!("123123-213123123"),
+            transaction_id: format!("123123-2131231244"),
+            data: String::new(),
+            payload: Some(raw_payload::to_raw(&json!({"score": [1, 0]})).unwrap()),
+            ack: false,
+            unreliable: false,
+        },
+    );
+
+    // A client asked to join a session that requires approval
+    trace(
+        MASTER,
+        MasterExternalEvent::ClientJoinRequested {
+            client_id: format!("123123-213123123"),
+            name: format!("some person"),
+            metadata: ClientMetadata {
+                name: format!("some person"),
+            },
+            role: ClientRole::Spectator,
+        },
+    );
+
+    // A join request was not answered in time
+    trace(
+        MASTER,
+        MasterExternalEvent::JoinRequestExpired {
+            client_id: format!("123123-213123123"),
+        },
+    );
+
+    // Admit a client that is waiting for approval
+    trace(
+        MASTER,
+        MasterExternalEvent::ApproveJoin {
+            transaction_id: format!("123123-2131231244"),
+            client_id: format!("123123-213123123"),
+        },
+    );
+
+    // Refuse a client that is waiting for approval
+    trace(
+        MASTER,
+        MasterExternalEvent::RejectJoin {
+            transaction_id: format!("123123-2131231244"),
+            client_id: format!("123123-213123123"),
+            reason: format!("Session is private"),
+        },
+    );
+
+    // Remove a client from the session
+    trace(
+        MASTER,
+        MasterExternalEvent::KickClient {
+            transaction_id: format!("123123-2131231244"),
+            client_id: format!("123123-213123123"),
+            reason: format!("Spamming"),
+            ban: true,
+            ban_key: false,
+        },
+    );
+
+    // Send a message to every client in the session
+    trace(
+        MASTER,
+        MasterExternalEvent::BroadcastToClients {
+            transaction_id: format!("123123-2131231244"),
+            data: format!("hello"),
+            exclude: vec![format!("123123-213123123")],
+            retain: true,
+        },
+    );
+
+    // Send a message to a set of clients
+    trace(
+        MASTER,
+        MasterExternalEvent::MulticastToClients {
+            transaction_id: format!("123123-2131231244"),
+            client_ids: vec![format!("123123-213123123")],
+            data: format!("hello"),
+        },
+    );
+
+    // Create a named group of clients
+    trace(
+        MASTER,
+        MasterExternalEvent::CreateGroup {
+            transaction_id: format!("123123-2131231244"),
+            group: format!("red-team"),
+        },
+    );
+
+    // Delete a named group of clients
+    trace(
+        MASTER,
+        MasterExternalEvent::DeleteGroup {
+            transaction_id: format!("123123-2131231244"),
+            group: format!("red-team"),
+        },
+    );
+
+    // Add a client to a group
+    trace(
+        MASTER,
+        MasterExternalEvent::AddToGroup {
+            transaction_id: format!("123123-2131231244"),
+            group: format!("red-team"),
+            client_id: format!("123123-213123123"),
+        },
+    );
+
+    // Remove a client from a group
+    trace(
+        MASTER,
+        MasterExternalEvent::RemoveFromGroup {
+            transaction_id: format!("123123-2131231244"),
+            group: format!("red-team"),
+            client_id: format!("123123-213123123"),
+        },
+    );
+
+    // Send a message to every client in a group
+    trace(
+        MASTER,
+        MasterExternalEvent::MessageToGroup {
+            transaction_id: format!("123123-2131231244"),
+            group: format!("red-team"),
+            data: format!("hello"),
+        },
+    );
+
+    // Move a connected client into another session
+    trace(
+        MASTER,
+        MasterExternalEvent::TransferClient {
+            transaction_id: format!("123123-2131231244"),
+            client_id: format!("123123-213123123"),
+            target_session_id: format!("game-1"),
+        },
+    );
+
+    // Hold a player slot for a client name or auth key
+    trace(
+        MASTER,
+        MasterExternalEvent::ReserveSeat {
+            transaction_id: format!("123123-2131231244"),
+            reservation: format!("Player 1"),
+            expires_ms: 60000,
+        },
+    );
+
+    // Release a reserved slot
+    trace(
+        MASTER,
+        MasterExternalEvent::CancelReservation {
+            transaction_id: format!("123123-2131231244"),
+            reservation: format!("Player 1"),
+        },
+    );
+
+    // Send a message to the master of another session
+    trace(
+        MASTER,
+        MasterExternalEvent::MessageToMaster {
+            transaction_id: format!("123123-2131231244"),
+            session_id: format!("game-1"),
+            data: format!("hello"),
+        },
+    );
+
+    // A message from the master of another session
+    trace(
+        MASTER,
+        MasterExternalEvent::MessageFromMaster {
+            session_id: format!("lobby"),
+            data: format!("hello"),
+        },
+    );
+
+    // Send a message to every connected spectator
+    trace(
+        MASTER,
+        MasterExternalEvent::MessageToSpectators {
+            transaction_id: format!("123123-2131231244"),
+            data: format!("hello"),
+        },
+    );
+
+    // Change the shared session state with a merge patch
+    trace(
+        MASTER,
+        MasterExternalEvent::PatchState {
+            transaction_id: format!("123123-2131231244"),
+            format: PatchFormat::MergePatch,
+            patch: json!({"round": 2, "scores": {"Player 1": 10}}),
+        },
+    );
+
+    // Change the shared session state with a list of patch operations
+    trace(
+        MASTER,
+        MasterExternalEvent::PatchState {
+            transaction_id: format!("123123-2131231244"),
+            format: PatchFormat::JsonPatch,
+            patch: json!([{"op": "replace", "path": "/round", "value": 3}]),
+        },
+    );
+
+    // A copy of a message sent between clients
+    trace(
+        MASTER,
+        MasterExternalEvent::PeerMessage {
+            client_id: format!("123123-213123123"),
+            recipients: vec![format!("123123-213123124")],
+            data: format!("hello"),
         },
     );
 }
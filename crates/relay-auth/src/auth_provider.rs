@@ -1,11 +1,11 @@
 use crate::infrastructure::validator::AuthValidator;
-use crate::{AuthProviderConfig, AuthRequest};
+use crate::{AuthError, AuthProviderConfig, AuthRequest};
 use relay_logging::RelayLogger;
 use std::error::Error;
 
 pub enum AuthResponse {
     Failed,
-    Passed { expires: i64 },
+    Passed { expires: i64, key: String },
 }
 
 pub struct AuthProvider {
@@ -39,6 +39,16 @@ impl AuthProvider {
         }
     }
 
+    /// Check a request that was already decoded, for connections that use a binary encoding
+    pub fn authorize_request(&self, request: AuthRequest) -> AuthResponse {
+        self.process_authorize_request(request)
+    }
+
+    /// Check a transaction id sent by an authorized connection is well formed
+    pub fn validate_transaction_id(&self, transaction_id: &str) -> Result<(), AuthError> {
+        self.validator.validate_transaction_id(transaction_id)
+    }
+
     /// Process an auth event and return a result or an error
     /// Returns an event to send to the client, and true/false for 'should keep connection'
     /// If 'should keep connection' is false,
@@ -49,7 +59,7 @@ impl AuthProvider {
             Ok(_) => {
                 self.logger
                     .info(format!("Auth success: key {}, expires: {}", key, expires));
-                AuthResponse::Passed { expires }
+                AuthResponse::Passed { expires, key }
             }
             Err(err) => {
                 self.logger.warn(format!("Auth attempt failed: {:?}", err));
@@ -123,7 +133,8 @@ mod tests {
         // Setup auth provider and check the request
         let auth = AuthProvider::new(mocks);
         match auth.authorize(&raw_event) {
-            AuthResponse::Passed { expires } => {
+            AuthResponse::Passed { expires, key } => {
+                assert_eq!(key, "12345678");
                 assert!(expires > Utc::now().timestamp());
             }
             _ => unreachable!(),
@@ -27,6 +27,14 @@ impl AuthHasher {
         return Ok(format!("{:x}", hasher.result()));
     }
 
+    /// A stable name for a key that doesn't reveal the key itself, eg. for tenants that aren't configured
+    pub fn hash_key(&self, key: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.input(key);
+        let digest = format!("{:x}", hasher.result());
+        digest[..16].to_string()
+    }
+
     /// Validate the hash on a request.
     /// The result is either Ok(()) or a failure reason.
     pub fn validate(&self, request: &AuthRequest, secret_store: &dyn AuthSecretProvider) -> Result<(), AuthError> {
@@ -70,6 +78,16 @@ mod tests {
         assert!(hash.len() > 0);
     }
 
+    #[test]
+    fn test_hash_key() {
+        let hasher = AuthHasher::new();
+        let hash = hasher.hash_key("12345678");
+        assert_eq!(hash.len(), 16);
+        assert_eq!(hash, hasher.hash_key("12345678"));
+        assert_ne!(hash, hasher.hash_key("12345679"));
+        assert!(!hash.contains("12345678"));
+    }
+
     #[test]
     fn test_validate_hash() {
         let mut request = AuthRequest {
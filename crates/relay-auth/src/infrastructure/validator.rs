@@ -4,6 +4,9 @@ use crate::AuthError;
 use crate::AuthProviderConfig;
 use chrono::Utc;
 
+/// The longest transaction id a connection may use
+const MAX_TRANSACTION_ID_LENGTH: usize = 128;
+
 pub struct AuthValidator {
     hasher: AuthHasher,
 }
@@ -29,6 +32,18 @@ impl AuthValidator {
         Ok(())
     }
 
+    /// Validate a transaction id from an authorized connection.
+    /// It must be non-empty, reasonably short and printable, since it is used to deduplicate retried requests.
+    pub fn validate_transaction_id(&self, transaction_id: &str) -> Result<(), AuthError> {
+        if transaction_id.is_empty()
+            || transaction_id.len() > MAX_TRANSACTION_ID_LENGTH
+            || transaction_id.chars().any(|c| c.is_control())
+        {
+            return Err(AuthError::InvalidTransactionId);
+        }
+        Ok(())
+    }
+
     fn validate_hash(
         &self,
         request: &AuthRequest,
@@ -135,6 +150,18 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_validate_transaction_id() {
+        let validator = AuthValidator::new();
+        assert!(validator.validate_transaction_id("5f1c1f43-8ad1-4a4e-9a87-0c0e2b5e0d7a").is_ok());
+        for invalid in vec!["".to_string(), "a\nb".to_string(), "x".repeat(129)] {
+            match validator.validate_transaction_id(&invalid) {
+                Err(AuthError::InvalidTransactionId) => {}
+                _ => unreachable!(),
+            }
+        }
+    }
+
     #[test]
     fn test_create_hasher() {
         let _ = AuthValidator::new();
@@ -2,7 +2,7 @@ use relay_client::ClientEvent;
 use relay_client::ClientOptions;
 use relay_client::ClientTyped;
 use relay_client::RelayError;
-use relay_client::{AuthOptions, BackendType};
+use relay_client::{AuthOptions, BackendType, WireEncoding};
 use serde::{Deserialize, Serialize};
 use std::thread;
 use std::time::Duration;
@@ -28,6 +28,9 @@ async fn run() -> Result<(), RelayError> {
         session_id: "EchoMaster".to_string(),
         remote: "ws://127.0.0.1:9977".to_string(),
         backend: BackendType::WebSocket,
+        encoding: WireEncoding::Json,
+        compression_threshold: None,
+        payload_only: false,
         auth: AuthOptions {
             key: "key1234567890".to_string(),
             secret: "secret1234567890".to_string(),
@@ -1,6 +1,6 @@
 use relay_client::MasterOptions;
 use relay_client::MasterTyped;
-use relay_client::{AuthOptions, BackendType};
+use relay_client::{AuthOptions, BackendType, MasterMetadata, WireEncoding};
 use relay_client::{MasterEvent, RelayError};
 use serde::{Deserialize, Serialize};
 
@@ -21,10 +21,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
 async fn run() -> Result<(), RelayError> {
     let master = MasterTyped::<EchoEvent>::new(MasterOptions {
-        master_id: "EchoMaster".to_string(),
-        max_clients: 10,
+        metadata: MasterMetadata {
+            master_id: "EchoMaster".to_string(),
+            max_clients: 10,
+            ..Default::default()
+        },
         remote: "ws://127.0.0.1:9977".to_string(),
         backend: BackendType::WebSocket,
+        encoding: WireEncoding::Json,
+        compression_threshold: None,
+        payload_only: false,
         auth: AuthOptions {
             key: "key1234567890".to_string(),
             secret: "secret1234567890".to_string(),
@@ -7,6 +7,7 @@ use crate::ClientOptions;
 use relay_core::events::client_event::ClientExternalEvent;
 
 use relay_core::model::client_metadata::ClientMetadata;
+use relay_core::model::client_role::ClientRole;
 
 use std::future::Future;
 use uuid::Uuid;
@@ -21,6 +22,8 @@ impl Client {
             auth: AuthHelper::generate_auth(&options.auth),
             remote: options.remote.clone(),
             target: options.backend,
+            encoding: options.encoding,
+            compression_threshold: options.compression_threshold,
             transaction_manager: TransactionManager::new(),
         })
         .await?;
@@ -36,6 +39,8 @@ impl Client {
             .send(RelayEvent::Client(ClientExternalEvent::Join {
                 transaction_id: Uuid::new_v4().to_string(),
                 session_id: options.session_id.clone(),
+                role: ClientRole::Player,
+                waitlist: false,
             }))
             .await?;
 
@@ -49,13 +54,18 @@ impl Client {
     pub fn send(&self, event: ClientExternalEvent) -> impl Future<Output = Result<(), RelayError>> + '_ {
         self.connection.send(RelayEvent::Client(event))
     }
+
+    /// Send several events in a single frame; the server processes them in order
+    pub fn send_batch(&self, events: Vec<ClientExternalEvent>) -> impl Future<Output = Result<(), RelayError>> + '_ {
+        self.connection.send_batch(events.into_iter().map(RelayEvent::Client).collect())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::client::ClientOptions;
     use crate::infrastructure::testing::block_on_future;
-    use crate::{AuthOptions, BackendType, Client};
+    use crate::{AuthOptions, BackendType, Client, WireEncoding};
 
     #[test]
     fn test_create_master() {
@@ -64,6 +74,9 @@ mod tests {
             session_id: "Master".to_string(),
             remote: "123".to_string(),
             backend: BackendType::Mock,
+            encoding: WireEncoding::Json,
+            compression_threshold: None,
+            payload_only: false,
             auth: AuthOptions {
                 key: "1234567890".to_string(),
                 secret: "1234567890".to_string(),
@@ -3,33 +3,69 @@ use crate::errors::relay_error::RelayError;
 use crate::infrastructure::relay_event::RelayEvent;
 use crate::ClientOptions;
 
+use futures::channel::oneshot;
 use relay_core::events::client_event::ClientExternalEvent;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
+use serde_json::value::RawValue;
+use std::collections::HashMap;
 use std::fmt::Debug;
 use std::io::BufReader;
+use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
 use uuid::Uuid;
 
+/// How long the relay waits for an answer to a request if it isn't told
+const DEFAULT_REQUEST_TIMEOUT_MS: u64 = 30000;
+
+/// The longest timeout the relay accepts for a request
+const MAX_REQUEST_TIMEOUT_MS: u64 = 10 * 60 * 1000;
+
+/// How much longer than the relay a request waits before giving up on the answer itself
+const REQUEST_TIMEOUT_GRACE_MS: u64 = 5000;
+
+/// How often the event loop checks for requests that were never answered
+const REQUEST_POLL_INTERVAL_MS: u64 = 100;
+
+struct PendingRequest {
+    promise: oneshot::Sender<Result<String, RelayError>>,
+    deadline: Instant,
+}
+
+type PendingRequests = Arc<Mutex<HashMap<String, PendingRequest>>>;
+
 #[derive(Debug)]
 pub enum ClientEvent<TEvent> {
     External(ClientExternalEvent),
     Internal(TEvent),
+
+    /// An event the master wants acknowledged; once it has been processed, send it back or call ack() with the sequence
+    Acknowledge { sequence: u64, event: TEvent },
 }
 
 pub struct ClientTyped<TEvent> {
     client: Client,
     input: crossbeam::Receiver<ClientEvent<TEvent>>,
+    pending: PendingRequests,
+    payload_only: bool,
 }
 
 impl<TEvent: Send + Serialize + DeserializeOwned + Debug + 'static> ClientTyped<TEvent> {
     /// Create a new instance
     pub async fn new(options: ClientOptions) -> Result<ClientTyped<TEvent>, RelayError> {
+        let payload_only = options.payload_only;
         let client = Client::new(options).await?;
         let (sx, rx) = crossbeam::unbounded();
         let reader = client.channel().clone();
-        ClientTyped::<TEvent>::event_loop(reader, sx);
-        Ok(ClientTyped { client, input: rx })
+        let pending: PendingRequests = Arc::new(Mutex::new(HashMap::new()));
+        ClientTyped::<TEvent>::event_loop(reader, sx, pending.clone());
+        Ok(ClientTyped {
+            client,
+            input: rx,
+            pending,
+            payload_only,
+        })
     }
 
     /// Return a receiver channel for this connection
@@ -41,43 +77,155 @@ impl<TEvent: Send + Serialize + DeserializeOwned + Debug + 'static> ClientTyped<
     pub async fn send(&self, event: ClientEvent<TEvent>) -> Result<(), RelayError> {
         match event {
             ClientEvent::External(ext) => self.client.send(ext).await,
-            ClientEvent::Internal(event) => self.send_to_master(event).await,
+            ClientEvent::Internal(event) => self.send_to_master(event, false, false).await,
+            ClientEvent::Acknowledge { sequence, event: _ } => self.ack(sequence).await,
         }
     }
 
-    async fn send_to_master(&self, event: TEvent) -> Result<(), RelayError> {
-        let raw = match self.serialize(event) {
-            Ok(r) => r,
-            Err(e) => return Err(e),
-        };
-        let event = ClientExternalEvent::MessageFromClient {
-            transaction_id: Uuid::new_v4().to_string(),
-            data: raw,
+    /// Send several events in a single frame; the master gets them in order
+    pub async fn send_batch(&self, events: Vec<ClientEvent<TEvent>>) -> Result<(), RelayError> {
+        let mut batch = Vec::new();
+        for event in events {
+            batch.push(match event {
+                ClientEvent::External(ext) => ext,
+                ClientEvent::Internal(event) => self.message_to_master(event, false, false)?,
+                ClientEvent::Acknowledge { sequence, event: _ } => Self::ack_message(sequence),
+            });
+        }
+        self.client.send_batch(batch).await
+    }
+
+    /// Send an event to the master, resolving only once the master application acknowledges it.
+    /// If this fails it is safe to send again; the master may see the event more than once, but never loses it.
+    pub async fn send_acknowledged(&self, event: TEvent) -> Result<(), RelayError> {
+        self.send_to_master(event, true, false).await
+    }
+
+    /// Send an event to the master without waiting for a result; use this for high rate updates where
+    /// a lost event will be superseded by the next one anyway.
+    pub async fn send_unreliable(&self, event: TEvent) -> Result<(), RelayError> {
+        self.send_to_master(event, false, true).await
+    }
+
+    /// Confirm that an event from the master that asked for an ack has been processed
+    pub async fn ack(&self, sequence: u64) -> Result<(), RelayError> {
+        self.client.send(Self::ack_message(sequence)).await
+    }
+
+    /// Send a request to the master and wait for its answer.
+    /// If the master doesn't answer within timeout_ms (or the relay default) this fails with a RequestTimeout error.
+    /// If the answer is lost on the way back this fails with TransactionExpired a little later, and if the
+    /// connection drops while waiting it fails with ConnectionFailed.
+    pub async fn request<TRequest: Serialize, TResponse: DeserializeOwned>(
+        &self,
+        request: TRequest,
+        timeout_ms: Option<u64>,
+    ) -> Result<TResponse, RelayError> {
+        let data = serde_json::to_string(&request)?;
+        let request_id = Uuid::new_v4().to_string();
+        let (sx, rx) = oneshot::channel();
+        let wait_ms = timeout_ms.unwrap_or(DEFAULT_REQUEST_TIMEOUT_MS).min(MAX_REQUEST_TIMEOUT_MS) + REQUEST_TIMEOUT_GRACE_MS;
+        match self.pending.lock() {
+            Ok(mut pending) => {
+                pending.insert(
+                    request_id.clone(),
+                    PendingRequest {
+                        promise: sx,
+                        deadline: Instant::now() + Duration::from_millis(wait_ms),
+                    },
+                );
+            }
+            Err(_e) => return Err(RelayError::ArcMutexFailure),
         };
+
+        let sent = self
+            .client
+            .send(ClientExternalEvent::RequestToMaster {
+                transaction_id: Uuid::new_v4().to_string(),
+                request_id: request_id.clone(),
+                data,
+                timeout_ms,
+            })
+            .await;
+        if let Err(e) = sent {
+            if let Ok(mut pending) = self.pending.lock() {
+                pending.remove(&request_id);
+            }
+            return Err(e);
+        }
+
+        let raw = rx.await??;
+        Ok(serde_json::from_str(&raw)?)
+    }
+
+    async fn send_to_master(&self, event: TEvent, ack: bool, unreliable: bool) -> Result<(), RelayError> {
+        let event = self.message_to_master(event, ack, unreliable)?;
         self.client.send(event).await
     }
 
-    fn serialize(&self, event: TEvent) -> Result<String, RelayError> {
-        let raw = serde_json::to_string(&event)?;
-        Ok(raw)
+    fn ack_message(sequence: u64) -> ClientExternalEvent {
+        ClientExternalEvent::AckMessage {
+            transaction_id: Uuid::new_v4().to_string(),
+            sequence,
+        }
+    }
+
+    fn message_to_master(&self, event: TEvent, ack: bool, unreliable: bool) -> Result<ClientExternalEvent, RelayError> {
+        let (data, payload) = self.serialize(event)?;
+        Ok(ClientExternalEvent::MessageFromClient {
+            transaction_id: Uuid::new_v4().to_string(),
+            data,
+            payload,
+            ack,
+            unreliable,
+        })
+    }
+
+    /// Typed events go in data unless payload_only was asked for
+    fn serialize(&self, event: TEvent) -> Result<(String, Option<Box<RawValue>>), RelayError> {
+        if self.payload_only {
+            return Ok((String::new(), Some(serde_json::value::to_raw_value(&event)?)));
+        }
+        Ok((serde_json::to_string(&event)?, None))
     }
 
-    fn event_loop(receiver: crossbeam::Receiver<RelayEvent>, sender: crossbeam::Sender<ClientEvent<TEvent>>) {
+    fn event_loop(receiver: crossbeam::Receiver<RelayEvent>, sender: crossbeam::Sender<ClientEvent<TEvent>>, pending: PendingRequests) {
         thread::spawn(move || loop {
-            match receiver.recv() {
+            match receiver.recv_timeout(Duration::from_millis(REQUEST_POLL_INTERVAL_MS)) {
                 Ok(relay_event) => {
                     match relay_event {
+                        RelayEvent::Client(ClientExternalEvent::ResponseToClient { request_id, data, error }) => {
+                            let waiting = match pending.lock() {
+                                Ok(mut pending) => pending.remove(&request_id),
+                                Err(_) => None,
+                            };
+                            match waiting {
+                                Some(request) => {
+                                    let _ = request.promise.send(match error {
+                                        Some(e) => Err(RelayError::ExternalError(e)),
+                                        None => Ok(data),
+                                    });
+                                }
+                                None => {
+                                    let _ = sender.send(ClientEvent::External(ClientExternalEvent::ResponseToClient { request_id, data, error }));
+                                }
+                            }
+                        }
                         RelayEvent::Client(event) => {
                             let should_deserialize = match &event {
-                                ClientExternalEvent::MessageToClient { data: _ } => true,
+                                ClientExternalEvent::MessageToClient { data: _, payload: _, sequence: _, ack: _ } => true,
                                 _ => false,
                             };
                             if should_deserialize {
                                 match event {
-                                    ClientExternalEvent::MessageToClient { data } => {
-                                        match Self::deserialize(data) {
+                                    ClientExternalEvent::MessageToClient { data, payload, sequence, ack } => {
+                                        match Self::deserialize(data, payload) {
                                             Ok(internal_event) => {
-                                                match sender.send(ClientEvent::Internal(internal_event)) {
+                                                let output = match ack {
+                                                    true => ClientEvent::Acknowledge { sequence, event: internal_event },
+                                                    false => ClientEvent::Internal(internal_event),
+                                                };
+                                                match sender.send(output) {
                                                     Ok(_) => {}
                                                     Err(_) => {
                                                         // TODO: Log it
@@ -100,24 +248,60 @@ impl<TEvent: Send + Serialize + DeserializeOwned + Debug + 'static> ClientTyped<
                         _ => {}
                     }
                 }
-                Err(_) => {
+                Err(crossbeam::RecvTimeoutError::Timeout) => {
+                    Self::expire_requests(&pending);
+                }
+                Err(crossbeam::RecvTimeoutError::Disconnected) => {
+                    Self::fail_requests(&pending);
                     break;
                 }
             }
         });
     }
 
-    fn deserialize(raw: String) -> Result<TEvent, RelayError> {
-        Ok(serde_json::from_reader(BufReader::new(raw.as_bytes()))?)
+    /// Give up on requests the relay should have answered by now
+    fn expire_requests(pending: &PendingRequests) {
+        if let Ok(mut pending) = pending.lock() {
+            let now = Instant::now();
+            let expired: Vec<String> = pending.iter().filter(|(_, v)| v.deadline < now).map(|(k, _)| k.to_string()).collect();
+            for request_id in expired {
+                if let Some(request) = pending.remove(&request_id) {
+                    let _ = request.promise.send(Err(RelayError::TransactionExpired));
+                }
+            }
+        }
+    }
+
+    /// The connection is gone, so nothing waiting on it will ever be answered
+    fn fail_requests(pending: &PendingRequests) {
+        if let Ok(mut pending) = pending.lock() {
+            for (_, request) in pending.drain() {
+                let _ = request.promise.send(Err(RelayError::ConnectionFailed("Connection closed".to_string())));
+            }
+        }
+    }
+
+    /// Masters that send a structured payload skip the string form entirely
+    fn deserialize(raw: String, payload: Option<Box<RawValue>>) -> Result<TEvent, RelayError> {
+        match payload {
+            Some(payload) => Ok(serde_json::from_str(payload.get())?),
+            None => Ok(serde_json::from_reader(BufReader::new(raw.as_bytes()))?),
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::{PendingRequest, PendingRequests};
+    use crate::errors::relay_error::RelayError;
     use crate::infrastructure::testing::block_on_future;
-    use crate::{AuthOptions, BackendType};
+    use crate::{AuthOptions, BackendType, WireEncoding};
     use crate::{ClientOptions, ClientTyped};
+    use futures::channel::oneshot;
     use serde::{Deserialize, Serialize};
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
 
     #[derive(Serialize, Deserialize, Debug)]
     #[serde(tag = "object_type")]
@@ -133,6 +317,9 @@ mod tests {
             session_id: "Master".to_string(),
             remote: "123".to_string(),
             backend: BackendType::Mock,
+            encoding: WireEncoding::Json,
+            compression_threshold: None,
+            payload_only: false,
             auth: AuthOptions {
                 key: "1234567890".to_string(),
                 secret: "1234567890".to_string(),
@@ -141,4 +328,35 @@ mod tests {
         }))
         .unwrap();
     }
+
+    fn pending_request(pending: &PendingRequests, request_id: &str, deadline: Instant) -> oneshot::Receiver<Result<String, RelayError>> {
+        let (sx, rx) = oneshot::channel();
+        pending.lock().unwrap().insert(request_id.to_string(), PendingRequest { promise: sx, deadline });
+        rx
+    }
+
+    #[test]
+    fn test_requests_expire_after_deadline() {
+        let pending: PendingRequests = Arc::new(Mutex::new(HashMap::new()));
+        let expired = pending_request(&pending, "1", Instant::now());
+        let _waiting = pending_request(&pending, "2", Instant::now() + Duration::from_secs(60));
+        ClientTyped::<TestEventType>::expire_requests(&pending);
+        match block_on_future(expired).unwrap() {
+            Err(RelayError::TransactionExpired) => {}
+            _ => unreachable!(),
+        }
+        assert!(pending.lock().unwrap().contains_key("2"));
+    }
+
+    #[test]
+    fn test_requests_fail_when_connection_closes() {
+        let pending: PendingRequests = Arc::new(Mutex::new(HashMap::new()));
+        let waiting = pending_request(&pending, "1", Instant::now() + Duration::from_secs(60));
+        ClientTyped::<TestEventType>::fail_requests(&pending);
+        match block_on_future(waiting).unwrap() {
+            Err(RelayError::ConnectionFailed(_)) => {}
+            _ => unreachable!(),
+        }
+        assert!(pending.lock().unwrap().is_empty());
+    }
 }
@@ -8,6 +8,7 @@ use crate::infrastructure::transaction_manager::TransactionManager;
 use crossbeam::crossbeam_channel;
 use futures::Future;
 use relay_auth::AuthRequest;
+use relay_core::model::wire_encoding::WireEncoding;
 
 pub(crate) mod mock_backend;
 pub(crate) mod websocket_backend;
@@ -27,6 +28,8 @@ pub struct Backend {
 pub struct BackendOptions {
     pub auth: Result<AuthRequest, RelayError>,
     pub target: BackendType,
+    pub encoding: WireEncoding,
+    pub compression_threshold: Option<usize>,
     pub remote: String,
     pub transaction_manager: TransactionManager,
 }
@@ -37,7 +40,7 @@ impl Backend {
         let (sx, rx) = crossbeam_channel::unbounded();
         let backend = match options.target {
             BackendType::Mock => MockBackend::new(options.transaction_manager.clone(), true).await,
-            BackendType::WebSocket => WebSocketBackend::new(&options.remote, options.transaction_manager.clone(), sx, options.auth.clone()).await,
+            BackendType::WebSocket => WebSocketBackend::new(&options.remote, options.transaction_manager.clone(), sx, options.auth.clone(), options.encoding, options.compression_threshold).await,
         }?;
         Ok(Backend {
             channel: rx,
@@ -61,6 +64,16 @@ impl Backend {
     pub fn send(&self, event: RelayEvent) -> impl Future<Output = Result<(), RelayError>> + '_ {
         self.connection.send(event)
     }
+
+    /// Send an external event and return the event that answered it
+    pub fn request(&self, event: RelayEvent) -> impl Future<Output = Result<Option<RelayEvent>, RelayError>> + '_ {
+        self.connection.request(event)
+    }
+
+    /// Send several external events in a single frame
+    pub fn send_batch(&self, events: Vec<RelayEvent>) -> impl Future<Output = Result<(), RelayError>> + '_ {
+        self.connection.send_batch(events)
+    }
 }
 
 #[cfg(test)]
@@ -68,7 +81,7 @@ mod tests {
     use crate::infrastructure::backend::{Backend, BackendOptions};
     use crate::infrastructure::testing::block_on_future;
     use crate::infrastructure::transaction_manager::TransactionManager;
-    use crate::{BackendType, RelayError};
+    use crate::{BackendType, RelayError, WireEncoding};
 
     #[test]
     fn test_create_mock_backend() {
@@ -76,6 +89,8 @@ mod tests {
             auth: Err(RelayError::InternalError("Not implemented".to_string())),
             remote: format!("localhost:9977"),
             target: BackendType::Mock,
+            encoding: WireEncoding::Json,
+            compression_threshold: None,
             transaction_manager: TransactionManager::new(),
         }))
         .unwrap();
@@ -23,25 +23,19 @@ impl MockBackend {
 }
 
 impl ManagedConnectionHandler for MockBackend {
-    fn send(&self, event: RelayEvent) -> Result<(), ()> {
+    fn send(&self, event: RelayEvent) -> Result<(), RelayError> {
         let _raw = serde_json::to_string(&event).unwrap();
         let transaction_id = event.transaction_id();
         if !self.auto_resolve {
             return Ok(());
         }
         match transaction_id.as_ref() {
-            Some(id) => match self.transaction_manager.resolve(id, Ok(())) {
-                Ok(_) => {
-                    println!("MOCK: resolved transaction {}", id);
-
-                    Ok(())
-                }
-                Err(e) => {
-                    println!("MOCK: {:?}", e);
-                    Err(())
-                }
-            },
-            None => Err(()),
+            Some(id) => {
+                self.transaction_manager.resolve(id, Ok(()))?;
+                println!("MOCK: resolved transaction {}", id);
+                Ok(())
+            }
+            None => Err(RelayError::InvalidEvent(format!("No transaction id found"))),
         }
     }
 }
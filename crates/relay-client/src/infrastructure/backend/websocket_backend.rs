@@ -7,13 +7,15 @@ use futures::channel::oneshot;
 use relay_auth::AuthRequest;
 use relay_core::events::client_event::ClientExternalEvent;
 use relay_core::events::master_event::MasterExternalEvent;
-use std::error::Error;
+use relay_core::model::wire_compression::{WireCompression, WireCompressionError, DEFAULT_COMPRESSION_LEVEL, DEFAULT_MAX_MESSAGE_BYTES};
+use relay_core::model::wire_encoding::WireEncoding;
 use std::sync::{Arc, Mutex};
 use std::thread;
-use ws::{connect, CloseCode};
+use ws::{connect, CloseCode, Frame, OpCode};
 
 pub struct WebSocketBackend {
     out: ws::Sender,
+    encoding: WireEncoding,
 }
 
 struct WebSocketHandler {
@@ -22,6 +24,8 @@ struct WebSocketHandler {
     transaction_manager: TransactionManager,
     channel: Option<crossbeam::Sender<RelayEvent>>,
     out: Option<ws::Sender>,
+    encoding: WireEncoding,
+    compression: Option<WireCompression>,
 }
 
 impl WebSocketBackend {
@@ -30,15 +34,20 @@ impl WebSocketBackend {
         transaction_manager: TransactionManager,
         channel: crossbeam::Sender<RelayEvent>,
         auth: Result<AuthRequest, RelayError>,
+        encoding: WireEncoding,
+        compression_threshold: Option<usize>,
     ) -> Result<Box<dyn ManagedConnectionHandler + Send + 'static>, RelayError> {
         let (resolve, promise) = oneshot::channel();
         let resolve_sharable = Arc::new(Mutex::new(Some(resolve)));
 
         // Resolve auth token
-        let token = WebSocketBackend::get_token(auth)?;
+        let token = WebSocketBackend::get_token(auth, encoding)?;
 
-        // Spawn the websocket worker function
-        let auth_uri = format!("{}/?token={}", remote, token);
+        // Spawn the websocket worker function; JSON is the default, so only ask for anything else
+        let auth_uri = match encoding {
+            WireEncoding::Json => format!("{}/?token={}", remote, token),
+            _ => format!("{}/?token={}&encoding={}", remote, token, encoding.name()),
+        };
 
         thread::spawn(move || {
             let err_reporter = resolve_sharable.clone();
@@ -49,6 +58,8 @@ impl WebSocketBackend {
                     resolver: resolve_sharable.clone(),
                     channel: Some(channel.clone()),
                     out: Some(out),
+                    encoding,
+                    compression: compression_threshold.map(|t| WireCompression::new(t, DEFAULT_COMPRESSION_LEVEL, DEFAULT_MAX_MESSAGE_BYTES)),
                 };
             }) {
                 Err(_) => {
@@ -66,37 +77,67 @@ impl WebSocketBackend {
         };
     }
 
-    fn get_token(auth: Result<AuthRequest, RelayError>) -> Result<String, RelayError> {
+    /// The auth request is written in the same encoding as everything else on the connection
+    fn get_token(auth: Result<AuthRequest, RelayError>, encoding: WireEncoding) -> Result<String, RelayError> {
         match auth {
             Ok(event) => {
-                let as_string = serde_json::to_string(&event)?;
-                let as_base64 = BASE64.encode(as_string.as_bytes());
+                let encoded = encoding.encode(&event).map_err(RelayError::SerializationError)?;
+                let as_base64 = BASE64.encode(&encoded);
                 return Ok(as_base64);
             }
             Err(err) => Err(err),
         }
     }
+
+    fn serialize(&self, event: &RelayEvent) -> Result<Vec<u8>, String> {
+        match event {
+            RelayEvent::Client(e) => self.encoding.encode(e),
+            RelayEvent::Master(e) => self.encoding.encode(e),
+        }
+    }
+
+    /// A connection is either a client or a master, so a batch is all one kind of event
+    fn serialize_batch(&self, events: &[RelayEvent]) -> Result<Vec<u8>, String> {
+        let clients: Vec<&ClientExternalEvent> = events
+            .iter()
+            .filter_map(|e| match e {
+                RelayEvent::Client(e) => Some(e),
+                _ => None,
+            })
+            .collect();
+        let masters: Vec<&MasterExternalEvent> = events
+            .iter()
+            .filter_map(|e| match e {
+                RelayEvent::Master(e) => Some(e),
+                _ => None,
+            })
+            .collect();
+        match (clients.len(), masters.len()) {
+            (_, 0) => self.encoding.encode(&clients),
+            (0, _) => self.encoding.encode(&masters),
+            _ => Err(format!("Can't send client and master events in one batch")),
+        }
+    }
+
+    fn send_frame(&self, raw: Result<Vec<u8>, String>) -> Result<(), RelayError> {
+        let frame = raw
+            .and_then(|data| match self.encoding.is_binary() {
+                true => Ok(ws::Message::Binary(data)),
+                false => String::from_utf8(data).map(ws::Message::Text).map_err(|e| e.to_string()),
+            })
+            .map_err(RelayError::SerializationError)?;
+        Ok(self.out.send(frame)?)
+    }
 }
 
 impl ManagedConnectionHandler for WebSocketBackend {
-    fn send(&self, event: RelayEvent) -> Result<(), ()> {
-        let raw = match event {
-            RelayEvent::Client(e) => serde_json::to_string(&e),
-            RelayEvent::Master(e) => serde_json::to_string(&e),
-        };
-        match raw {
-            Ok(data) => match self.out.send(data) {
-                Ok(_) => Ok(()),
-                Err(err) => {
-                    println!("Error sending message: {}", err);
-                    Err(())
-                }
-            },
-            Err(e) => {
-                println!("ERROR!: {}", e.description());
-                Err(())
-            }
-        }
+    fn send(&self, event: RelayEvent) -> Result<(), RelayError> {
+        self.send_frame(self.serialize(&event))
+    }
+
+    /// A batch goes out as an array of events
+    fn send_batch(&self, events: Vec<RelayEvent>) -> Result<(), RelayError> {
+        self.send_frame(self.serialize_batch(&events))
     }
 }
 
@@ -104,6 +145,7 @@ impl WebSocketHandler {
     fn on_connected(&mut self) {
         let connected = Box::new(WebSocketBackend {
             out: self.out.as_ref().unwrap().clone(),
+            encoding: self.encoding,
         }) as Box<dyn ManagedConnectionHandler + Send + 'static>;
         self.connected = true;
         match WebSocketHandler::resolve(&self.resolver, Ok(connected)) {
@@ -117,22 +159,52 @@ impl WebSocketHandler {
         }
     }
 
-    pub fn as_event(&self, raw: ws::Message) -> Result<RelayEvent, RelayError> {
-        match raw {
-            ws::Message::Text(raw_string) => {
-                // Try read this as a master event.
-                match serde_json::from_str::<MasterExternalEvent>(&raw_string) {
-                    Ok(master_event) => Ok(RelayEvent::Master(master_event)),
-                    Err(_) => {
-                        // Fallback; attempt as a client event?
-                        match serde_json::from_str::<ClientExternalEvent>(&raw_string) {
-                            Ok(client_event) => Ok(RelayEvent::Client(client_event)),
-                            Err(_) => Err(RelayError::InvalidEvent(format!("Unknown event: {}", raw_string))),
-                        }
-                    }
+    /// Read a frame, which is either a single event or an array of events
+    pub fn as_events(&self, raw: ws::Message) -> Result<Vec<RelayEvent>, RelayError> {
+        let raw = match (raw, self.encoding.is_binary()) {
+            (ws::Message::Text(raw_string), false) => raw_string.into_bytes(),
+            (ws::Message::Binary(raw_bytes), true) => raw_bytes,
+            (ws::Message::Text(_), true) => return Err(RelayError::InvalidEvent(format!("Text chunk not supported"))),
+            (ws::Message::Binary(_), false) => return Err(RelayError::InvalidEvent(format!("Binary chunk not supported"))),
+        };
+
+        if self.encoding.is_array(&raw) {
+            // Try read this as a batch of master events, then client events
+            return match self.encoding.decode_events::<MasterExternalEvent>(&raw) {
+                Ok(events) => Ok(events.into_iter().map(RelayEvent::Master).collect()),
+                Err(_) => match self.encoding.decode_events::<ClientExternalEvent>(&raw) {
+                    Ok(events) => Ok(events.into_iter().map(RelayEvent::Client).collect()),
+                    Err(_) => Err(RelayError::InvalidEvent(format!("Unknown batch: {}", self.encoding.describe(&raw)))),
+                },
+            };
+        }
+
+        // Try read this as a master event.
+        match self.encoding.decode_event::<MasterExternalEvent>(&raw) {
+            Ok(master_event) => Ok(vec![RelayEvent::Master(master_event)]),
+            Err(_) => {
+                // Fallback; attempt as a client event?
+                match self.encoding.decode_event::<ClientExternalEvent>(&raw) {
+                    Ok(client_event) => Ok(vec![RelayEvent::Client(client_event)]),
+                    Err(_) => Err(RelayError::InvalidEvent(format!("Unknown event: {}", self.encoding.describe(&raw)))),
                 }
             }
-            ws::Message::Binary(_) => Err(RelayError::InvalidEvent(format!("Binary chunk not supported"))),
+        }
+    }
+
+    /// Resolve the transaction an event answers, or pass it on to the application.
+    /// This only fails if the transaction manager is poisoned, and then every send fails with ArcMutexFailure too.
+    fn on_event(&mut self, e: RelayEvent) {
+        match e.transaction_id() {
+            Some(transaction_id) => {
+                let _ = self.transaction_manager.answer(&transaction_id, e);
+            }
+            None => match self.channel.as_ref() {
+                Some(channel) => {
+                    let _ = channel.send(e);
+                }
+                None => {}
+            },
         }
     }
 
@@ -156,34 +228,67 @@ impl WebSocketHandler {
 }
 
 impl ws::Handler for WebSocketHandler {
+    fn build_request(&mut self, url: &ws::util::Url) -> ws::Result<ws::Request> {
+        let mut request = ws::Request::from_url(url)?;
+        if let Some(compression) = &self.compression {
+            request.add_extension(compression.offer());
+        }
+        Ok(request)
+    }
+
+    fn on_response(&mut self, res: &ws::Response) -> ws::Result<()> {
+        if let Some(compression) = self.compression.as_mut() {
+            compression.confirm(&res.extensions()?);
+        }
+        Ok(())
+    }
+
+    /// Inflate compressed messages; one split over several frames is held back until the last frame arrives
+    fn on_frame(&mut self, frame: Frame) -> ws::Result<Option<Frame>> {
+        let compression = match self.compression.as_mut() {
+            Some(c) if c.is_negotiated() => c,
+            _ => return Ok(Some(frame)),
+        };
+        match frame.opcode() {
+            OpCode::Text | OpCode::Binary if frame.has_rsv1() => {}
+            OpCode::Continue if compression.is_reading() => {}
+            _ => return Ok(Some(frame)),
+        }
+        match compression.read(frame.opcode().into(), frame.payload(), frame.is_final()) {
+            None => Ok(None),
+            Some(Ok((opcode, data))) => Ok(Some(Frame::message(data, OpCode::from(opcode), true))),
+            // ws closes the connection with CloseCode::Size for capacity errors
+            Some(Err(WireCompressionError::MessageTooLarge)) => Err(ws::Error::new(ws::ErrorKind::Capacity, "Compressed message is too large")),
+            Some(Err(WireCompressionError::Corrupt(e))) => Err(ws::Error::new(ws::ErrorKind::Protocol, e)),
+        }
+    }
+
+    /// Compress outbound messages that are big enough to be worth it
+    fn on_send_frame(&mut self, frame: Frame) -> ws::Result<Option<Frame>> {
+        let is_message = match frame.opcode() {
+            OpCode::Text | OpCode::Binary => frame.is_final(),
+            _ => false,
+        };
+        if is_message {
+            if let Some(compressed) = self.compression.as_mut().and_then(|c| c.compress(frame.payload())) {
+                let mut compressed_frame = Frame::message(compressed, frame.opcode(), true);
+                compressed_frame.set_rsv1(true);
+                return Ok(Some(compressed_frame));
+            }
+        }
+        Ok(Some(frame))
+    }
+
     fn on_open(&mut self, _: ws::Handshake) -> ws::Result<()> {
         self.on_connected();
         Ok(())
     }
 
+    /// A frame that isn't a relay event answers nothing and can't be passed on, so it is dropped rather
+    /// than closing the connection
     fn on_message(&mut self, msg: ws::Message) -> ws::Result<()> {
-        let event = self.as_event(msg);
-        match event {
-            Ok(e) => match e.transaction_id() {
-                Some(transaction_id) => {
-                    let result = e.transaction_result();
-                    match self.transaction_manager.resolve(&transaction_id, result) {
-                        Ok(_) => {}
-                        Err(e) => {
-                            println!("Failed to resolve transaction: {:?}", e);
-                        }
-                    }
-                }
-                None => match self.channel.as_ref() {
-                    Some(channel) => {
-                        let _ = channel.send(e);
-                    }
-                    None => {}
-                },
-            },
-            Err(e) => {
-                println!("Discarded message: {:?}", e);
-            }
+        if let Ok(events) = self.as_events(msg) {
+            events.into_iter().for_each(|e| self.on_event(e));
         }
         Ok(())
     }
@@ -1,11 +1,17 @@
 use crate::errors::relay_error::RelayError;
 use crate::infrastructure::relay_event::RelayEvent;
 use crate::infrastructure::transaction_manager::TransactionManager;
-use futures::{future, Future};
+use futures::future;
 use std::sync::{Arc, Mutex};
 
 pub trait ManagedConnectionHandler {
-    fn send(&self, event: RelayEvent) -> Result<(), ()>;
+    fn send(&self, event: RelayEvent) -> Result<(), RelayError>;
+
+    /// Send several events in a single frame; the remote processes them in order.
+    /// Backends that can't batch send them one at a time.
+    fn send_batch(&self, events: Vec<RelayEvent>) -> Result<(), RelayError> {
+        events.into_iter().map(|event| self.send(event)).collect()
+    }
 }
 
 #[derive(Clone)]
@@ -23,24 +29,61 @@ impl ManagedConnection {
     }
 
     pub async fn send(&self, event: RelayEvent) -> Result<(), RelayError> {
+        if event.is_unreliable() {
+            return self.send_unreliable(event);
+        }
         match event.transaction_id() {
             Some(s) => self.send_internal(&s, event).await,
             None => Err(RelayError::InvalidEvent(format!("No transaction id found"))),
         }
     }
 
+    /// Send several events in a single frame; this resolves once every event that expects a result has one.
+    pub async fn send_batch(&self, events: Vec<RelayEvent>) -> Result<(), RelayError> {
+        let mut pending = Vec::new();
+        for event in events.iter().filter(|e| !e.is_unreliable()) {
+            match event.transaction_id() {
+                Some(s) => pending.push(s),
+                None => return Err(RelayError::InvalidEvent(format!("No transaction id found"))),
+            }
+        }
+        match self.internal.lock() {
+            Ok(internal) => internal.send_batch(events)?,
+            Err(_) => return Err(RelayError::ArcMutexFailure),
+        };
+        future::join_all(pending.iter().map(|transaction_id| self.transactions.defer(transaction_id)))
+            .await
+            .into_iter()
+            .collect()
+    }
+
+    /// Send an event and wait for the event that answered it, for results that carry more than success or failure
+    pub async fn request(&self, event: RelayEvent) -> Result<Option<RelayEvent>, RelayError> {
+        let transaction_id = match event.transaction_id() {
+            Some(s) => s,
+            None => return Err(RelayError::InvalidEvent(format!("No transaction id found"))),
+        };
+        match self.internal.lock() {
+            Ok(internal) => internal.send(event)?,
+            Err(_) => return Err(RelayError::ArcMutexFailure),
+        };
+        self.transactions.defer_answer(&transaction_id).await
+    }
+
     async fn send_internal(&self, transaction_id: &str, event: RelayEvent) -> Result<(), RelayError> {
         match self.internal.lock() {
-            Ok(internal) => match internal.send(event) {
-                Ok(_) => return self.transactions.defer(transaction_id).await,
-                Err(_) => return ManagedConnection::internal_error().await,
-            },
-            Err(_) => return ManagedConnection::internal_error().await,
-        }
+            Ok(internal) => internal.send(event)?,
+            Err(_) => return Err(RelayError::ArcMutexFailure),
+        };
+        self.transactions.defer(transaction_id).await
     }
 
-    fn internal_error() -> impl Future<Output = Result<(), RelayError>> {
-        return future::err::<(), RelayError>(RelayError::InternalError(format!("Send failed")));
+    /// Send without waiting for a transaction result; the server never sends one for unreliable events
+    fn send_unreliable(&self, event: RelayEvent) -> Result<(), RelayError> {
+        match self.internal.lock() {
+            Ok(internal) => internal.send(event),
+            Err(_) => Err(RelayError::ArcMutexFailure),
+        }
     }
 }
 
@@ -84,6 +127,7 @@ mod tests {
             metadata: MasterMetadata {
                 master_id: "hello".to_string(),
                 max_clients: 123,
+                ..Default::default()
             },
         }));
 
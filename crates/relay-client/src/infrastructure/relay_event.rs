@@ -11,38 +11,199 @@ pub enum RelayEvent {
 }
 
 impl RelayEvent {
+    /// Fire-and-forget events never get a TransactionResult, so nothing should wait for one
+    pub fn is_unreliable(&self) -> bool {
+        match self {
+            RelayEvent::Master(MasterExternalEvent::MessageToClient { unreliable, .. }) => *unreliable,
+            RelayEvent::Client(ClientExternalEvent::MessageFromClient { unreliable, .. }) => *unreliable,
+            _ => false,
+        }
+    }
+
     pub fn transaction_id(&self) -> Option<String> {
         match self {
             RelayEvent::Master(m) => match m {
                 MasterExternalEvent::ClientDisconnected { client_id: _, reason: _ } => None,
+                MasterExternalEvent::ClientResumed { client_id: _, previous_client_id: _ } => None,
                 MasterExternalEvent::InitializeMaster { transaction_id, metadata: _ } => Some(transaction_id.to_string()),
                 MasterExternalEvent::MessageToClient {
                     transaction_id,
                     client_id: _,
                     data: _,
+                    payload: _,
+                    ack: _,
+                    unreliable: _,
                 } => Some(transaction_id.to_string()),
                 MasterExternalEvent::TransactionResult {
                     transaction_id,
                     success: _,
                     error: _,
+                    join_code: _,
+                } => Some(transaction_id.to_string()),
+                MasterExternalEvent::ClientJoined {
+                    client_id: _,
+                    name: _,
+                    role: _,
+                } => None,
+                MasterExternalEvent::MessageFromClient { client_id: _, data: _, payload: _, sequence: _, ack: _ } => None,
+                MasterExternalEvent::AckMessage {
+                    transaction_id,
+                    client_id: _,
+                    sequence: _,
+                } => Some(transaction_id.to_string()),
+                MasterExternalEvent::RequestFromClient {
+                    client_id: _,
+                    request_id: _,
+                    data: _,
+                } => None,
+                MasterExternalEvent::ResponseToClient {
+                    transaction_id,
+                    client_id: _,
+                    request_id: _,
+                    data: _,
+                } => Some(transaction_id.to_string()),
+                MasterExternalEvent::ClientJoinRequested {
+                    client_id: _,
+                    name: _,
+                    metadata: _,
+                    role: _,
+                } => None,
+                MasterExternalEvent::JoinRequestExpired { client_id: _ } => None,
+                MasterExternalEvent::ApproveJoin { transaction_id, client_id: _ } => Some(transaction_id.to_string()),
+                MasterExternalEvent::RejectJoin {
+                    transaction_id,
+                    client_id: _,
+                    reason: _,
+                } => Some(transaction_id.to_string()),
+                MasterExternalEvent::KickClient {
+                    transaction_id,
+                    client_id: _,
+                    reason: _,
+                    ban: _,
+                    ban_key: _,
+                } => Some(transaction_id.to_string()),
+                MasterExternalEvent::BroadcastToClients {
+                    transaction_id,
+                    data: _,
+                    exclude: _,
+                    retain: _,
+                } => Some(transaction_id.to_string()),
+                MasterExternalEvent::MulticastToClients {
+                    transaction_id,
+                    client_ids: _,
+                    data: _,
+                } => Some(transaction_id.to_string()),
+                MasterExternalEvent::CreateGroup { transaction_id, group: _ } => Some(transaction_id.to_string()),
+                MasterExternalEvent::DeleteGroup { transaction_id, group: _ } => Some(transaction_id.to_string()),
+                MasterExternalEvent::AddToGroup {
+                    transaction_id,
+                    group: _,
+                    client_id: _,
+                } => Some(transaction_id.to_string()),
+                MasterExternalEvent::RemoveFromGroup {
+                    transaction_id,
+                    group: _,
+                    client_id: _,
+                } => Some(transaction_id.to_string()),
+                MasterExternalEvent::MessageToGroup {
+                    transaction_id,
+                    group: _,
+                    data: _,
+                } => Some(transaction_id.to_string()),
+                MasterExternalEvent::PeerMessage {
+                    client_id: _,
+                    recipients: _,
+                    data: _,
+                } => None,
+                MasterExternalEvent::MessageToSpectators { transaction_id, data: _ } => Some(transaction_id.to_string()),
+                MasterExternalEvent::TransferClient {
+                    transaction_id,
+                    client_id: _,
+                    target_session_id: _,
+                } => Some(transaction_id.to_string()),
+                MasterExternalEvent::MessageToMaster {
+                    transaction_id,
+                    session_id: _,
+                    data: _,
+                } => Some(transaction_id.to_string()),
+                MasterExternalEvent::MessageFromMaster { session_id: _, data: _ } => None,
+                MasterExternalEvent::ReserveSeat {
+                    transaction_id,
+                    reservation: _,
+                    expires_ms: _,
+                } => Some(transaction_id.to_string()),
+                MasterExternalEvent::CancelReservation { transaction_id, reservation: _ } => Some(transaction_id.to_string()),
+                MasterExternalEvent::PatchState {
+                    transaction_id,
+                    format: _,
+                    patch: _,
                 } => Some(transaction_id.to_string()),
-                MasterExternalEvent::ClientJoined { client_id: _, name: _ } => None,
-                MasterExternalEvent::MessageFromClient { client_id: _, data: _ } => None,
             },
             RelayEvent::Client(c) => match c {
                 ClientExternalEvent::InitializeClient { transaction_id, metadata: _ } => Some(transaction_id.to_string()),
                 ClientExternalEvent::Join {
                     transaction_id,
                     session_id: _,
+                    role: _,
+                    waitlist: _,
+                } => Some(transaction_id.to_string()),
+                ClientExternalEvent::MessageFromClient {
+                    transaction_id,
+                    data: _,
+                    payload: _,
+                    ack: _,
+                    unreliable: _,
                 } => Some(transaction_id.to_string()),
-                ClientExternalEvent::MessageFromClient { transaction_id, data: _ } => Some(transaction_id.to_string()),
                 ClientExternalEvent::TransactionResult {
                     transaction_id,
                     success: _,
                     error: _,
                 } => Some(transaction_id.to_string()),
-                ClientExternalEvent::MessageToClient { data: _ } => None,
+                ClientExternalEvent::MessageToClient { data: _, payload: _, sequence: _, ack: _ } => None,
+                ClientExternalEvent::AckMessage { transaction_id, sequence: _ } => Some(transaction_id.to_string()),
                 ClientExternalEvent::MasterDisconnected { reason: _ } => None,
+                ClientExternalEvent::Kicked { reason: _ } => None,
+                ClientExternalEvent::GroupMembership { groups: _ } => None,
+                ClientExternalEvent::MessageToPeer {
+                    transaction_id,
+                    client_id: _,
+                    data: _,
+                } => Some(transaction_id.to_string()),
+                ClientExternalEvent::BroadcastToPeers { transaction_id, data: _ } => Some(transaction_id.to_string()),
+                ClientExternalEvent::MessageFromPeer { client_id: _, data: _ } => None,
+                ClientExternalEvent::JoinPeerSession { transaction_id, session_id: _ } => Some(transaction_id.to_string()),
+                ClientExternalEvent::JoinAny {
+                    transaction_id,
+                    filter: _,
+                    role: _,
+                } => Some(transaction_id.to_string()),
+                ClientExternalEvent::Leave { transaction_id } => Some(transaction_id.to_string()),
+                ClientExternalEvent::ListSessions { transaction_id } => Some(transaction_id.to_string()),
+                ClientExternalEvent::SessionList { transaction_id: _, sessions: _ } => None,
+                ClientExternalEvent::Resume {
+                    transaction_id,
+                    session_id: _,
+                    resume_token: _,
+                } => Some(transaction_id.to_string()),
+                ClientExternalEvent::ResumeToken { token: _ } => None,
+                ClientExternalEvent::SessionTransferred {
+                    session_id: _,
+                    success: _,
+                    error: _,
+                } => None,
+                ClientExternalEvent::PeerJoined { client_id: _, name: _ } => None,
+                ClientExternalEvent::PeerLeft { client_id: _, reason: _ } => None,
+                ClientExternalEvent::WaitlistPosition { transaction_id: _, position: _ } => None,
+                ClientExternalEvent::RequestStateSnapshot { transaction_id } => Some(transaction_id.to_string()),
+                ClientExternalEvent::StatePatch { format: _, patch: _, version: _ } => None,
+                ClientExternalEvent::StateSnapshot { state: _, version: _ } => None,
+                ClientExternalEvent::RequestToMaster {
+                    transaction_id,
+                    request_id: _,
+                    data: _,
+                    timeout_ms: _,
+                } => Some(transaction_id.to_string()),
+                ClientExternalEvent::ResponseToClient { request_id: _, data: _, error: _ } => None,
             },
         }
     }
@@ -51,16 +212,21 @@ impl RelayEvent {
         match self {
             RelayEvent::Master(m) => match m {
                 MasterExternalEvent::ClientDisconnected { client_id: _, reason: _ } => Err(ExternalError::from(ErrorCode::Unknown)),
+                MasterExternalEvent::ClientResumed { client_id: _, previous_client_id: _ } => Err(ExternalError::from(ErrorCode::Unknown)),
                 MasterExternalEvent::InitializeMaster { transaction_id: _, metadata: _ } => Err(ExternalError::from(ErrorCode::Unknown)),
                 MasterExternalEvent::MessageToClient {
                     transaction_id: _,
                     client_id: _,
                     data: _,
+                    payload: _,
+                    ack: _,
+                    unreliable: _,
                 } => Err(ExternalError::from(ErrorCode::Unknown)),
                 MasterExternalEvent::TransactionResult {
                     transaction_id: _,
                     success,
                     error,
+                    join_code: _,
                 } => {
                     if *success {
                         Ok(())
@@ -69,16 +235,135 @@ impl RelayEvent {
                         Err(err.unwrap_or(ExternalError::from(ErrorCode::Unknown)))
                     }
                 }
-                MasterExternalEvent::ClientJoined { client_id: _, name: _ } => Err(ExternalError::from(ErrorCode::Unknown)),
-                MasterExternalEvent::MessageFromClient { client_id: _, data: _ } => Err(ExternalError::from(ErrorCode::Unknown)),
+                MasterExternalEvent::ClientJoined {
+                    client_id: _,
+                    name: _,
+                    role: _,
+                } => Err(ExternalError::from(ErrorCode::Unknown)),
+                MasterExternalEvent::MessageFromClient { client_id: _, data: _, payload: _, sequence: _, ack: _ } => Err(ExternalError::from(ErrorCode::Unknown)),
+                MasterExternalEvent::AckMessage {
+                    transaction_id: _,
+                    client_id: _,
+                    sequence: _,
+                } => Err(ExternalError::from(ErrorCode::Unknown)),
+                MasterExternalEvent::RequestFromClient {
+                    client_id: _,
+                    request_id: _,
+                    data: _,
+                } => Err(ExternalError::from(ErrorCode::Unknown)),
+                MasterExternalEvent::ResponseToClient {
+                    transaction_id: _,
+                    client_id: _,
+                    request_id: _,
+                    data: _,
+                } => Err(ExternalError::from(ErrorCode::Unknown)),
+                MasterExternalEvent::ClientJoinRequested {
+                    client_id: _,
+                    name: _,
+                    metadata: _,
+                    role: _,
+                } => Err(ExternalError::from(ErrorCode::Unknown)),
+                MasterExternalEvent::JoinRequestExpired { client_id: _ } => Err(ExternalError::from(ErrorCode::Unknown)),
+                MasterExternalEvent::ApproveJoin {
+                    transaction_id: _,
+                    client_id: _,
+                } => Err(ExternalError::from(ErrorCode::Unknown)),
+                MasterExternalEvent::RejectJoin {
+                    transaction_id: _,
+                    client_id: _,
+                    reason: _,
+                } => Err(ExternalError::from(ErrorCode::Unknown)),
+                MasterExternalEvent::KickClient {
+                    transaction_id: _,
+                    client_id: _,
+                    reason: _,
+                    ban: _,
+                    ban_key: _,
+                } => Err(ExternalError::from(ErrorCode::Unknown)),
+                MasterExternalEvent::BroadcastToClients {
+                    transaction_id: _,
+                    data: _,
+                    exclude: _,
+                    retain: _,
+                } => Err(ExternalError::from(ErrorCode::Unknown)),
+                MasterExternalEvent::MulticastToClients {
+                    transaction_id: _,
+                    client_ids: _,
+                    data: _,
+                } => Err(ExternalError::from(ErrorCode::Unknown)),
+                MasterExternalEvent::CreateGroup {
+                    transaction_id: _,
+                    group: _,
+                } => Err(ExternalError::from(ErrorCode::Unknown)),
+                MasterExternalEvent::DeleteGroup {
+                    transaction_id: _,
+                    group: _,
+                } => Err(ExternalError::from(ErrorCode::Unknown)),
+                MasterExternalEvent::AddToGroup {
+                    transaction_id: _,
+                    group: _,
+                    client_id: _,
+                } => Err(ExternalError::from(ErrorCode::Unknown)),
+                MasterExternalEvent::RemoveFromGroup {
+                    transaction_id: _,
+                    group: _,
+                    client_id: _,
+                } => Err(ExternalError::from(ErrorCode::Unknown)),
+                MasterExternalEvent::MessageToGroup {
+                    transaction_id: _,
+                    group: _,
+                    data: _,
+                } => Err(ExternalError::from(ErrorCode::Unknown)),
+                MasterExternalEvent::PeerMessage {
+                    client_id: _,
+                    recipients: _,
+                    data: _,
+                } => Err(ExternalError::from(ErrorCode::Unknown)),
+                MasterExternalEvent::MessageToSpectators {
+                    transaction_id: _,
+                    data: _,
+                } => Err(ExternalError::from(ErrorCode::Unknown)),
+                MasterExternalEvent::TransferClient {
+                    transaction_id: _,
+                    client_id: _,
+                    target_session_id: _,
+                } => Err(ExternalError::from(ErrorCode::Unknown)),
+                MasterExternalEvent::MessageToMaster {
+                    transaction_id: _,
+                    session_id: _,
+                    data: _,
+                } => Err(ExternalError::from(ErrorCode::Unknown)),
+                MasterExternalEvent::MessageFromMaster { session_id: _, data: _ } => Err(ExternalError::from(ErrorCode::Unknown)),
+                MasterExternalEvent::ReserveSeat {
+                    transaction_id: _,
+                    reservation: _,
+                    expires_ms: _,
+                } => Err(ExternalError::from(ErrorCode::Unknown)),
+                MasterExternalEvent::CancelReservation {
+                    transaction_id: _,
+                    reservation: _,
+                } => Err(ExternalError::from(ErrorCode::Unknown)),
+                MasterExternalEvent::PatchState {
+                    transaction_id: _,
+                    format: _,
+                    patch: _,
+                } => Err(ExternalError::from(ErrorCode::Unknown)),
             },
             RelayEvent::Client(c) => match c {
                 ClientExternalEvent::InitializeClient { transaction_id: _, metadata: _ } => Err(ExternalError::from(ErrorCode::Unknown)),
                 ClientExternalEvent::Join {
                     transaction_id: _,
                     session_id: _,
+                    role: _,
+                    waitlist: _,
+                } => Err(ExternalError::from(ErrorCode::Unknown)),
+                ClientExternalEvent::MessageFromClient {
+                    transaction_id: _,
+                    data: _,
+                    payload: _,
+                    ack: _,
+                    unreliable: _,
                 } => Err(ExternalError::from(ErrorCode::Unknown)),
-                ClientExternalEvent::MessageFromClient { transaction_id: _, data: _ } => Err(ExternalError::from(ErrorCode::Unknown)),
                 ClientExternalEvent::TransactionResult {
                     transaction_id: _,
                     success,
@@ -91,8 +376,54 @@ impl RelayEvent {
                         Err(err.unwrap_or(ExternalError::from(ErrorCode::Unknown)))
                     }
                 }
-                ClientExternalEvent::MessageToClient { data: _ } => Err(ExternalError::from(ErrorCode::Unknown)),
+                ClientExternalEvent::MessageToClient { data: _, payload: _, sequence: _, ack: _ } => Err(ExternalError::from(ErrorCode::Unknown)),
+                ClientExternalEvent::AckMessage { transaction_id: _, sequence: _ } => Err(ExternalError::from(ErrorCode::Unknown)),
                 ClientExternalEvent::MasterDisconnected { reason: _ } => Err(ExternalError::from(ErrorCode::Unknown)),
+                ClientExternalEvent::Kicked { reason: _ } => Err(ExternalError::from(ErrorCode::Unknown)),
+                ClientExternalEvent::GroupMembership { groups: _ } => Err(ExternalError::from(ErrorCode::Unknown)),
+                ClientExternalEvent::MessageToPeer {
+                    transaction_id: _,
+                    client_id: _,
+                    data: _,
+                } => Err(ExternalError::from(ErrorCode::Unknown)),
+                ClientExternalEvent::BroadcastToPeers { transaction_id: _, data: _ } => Err(ExternalError::from(ErrorCode::Unknown)),
+                ClientExternalEvent::MessageFromPeer { client_id: _, data: _ } => Err(ExternalError::from(ErrorCode::Unknown)),
+                ClientExternalEvent::JoinPeerSession {
+                    transaction_id: _,
+                    session_id: _,
+                } => Err(ExternalError::from(ErrorCode::Unknown)),
+                ClientExternalEvent::JoinAny {
+                    transaction_id: _,
+                    filter: _,
+                    role: _,
+                } => Err(ExternalError::from(ErrorCode::Unknown)),
+                ClientExternalEvent::PeerJoined { client_id: _, name: _ } => Err(ExternalError::from(ErrorCode::Unknown)),
+                ClientExternalEvent::Leave { transaction_id: _ } => Err(ExternalError::from(ErrorCode::Unknown)),
+                ClientExternalEvent::ListSessions { transaction_id: _ } => Err(ExternalError::from(ErrorCode::Unknown)),
+                ClientExternalEvent::SessionList { transaction_id: _, sessions: _ } => Err(ExternalError::from(ErrorCode::Unknown)),
+                ClientExternalEvent::Resume {
+                    transaction_id: _,
+                    session_id: _,
+                    resume_token: _,
+                } => Err(ExternalError::from(ErrorCode::Unknown)),
+                ClientExternalEvent::ResumeToken { token: _ } => Err(ExternalError::from(ErrorCode::Unknown)),
+                ClientExternalEvent::SessionTransferred {
+                    session_id: _,
+                    success: _,
+                    error: _,
+                } => Err(ExternalError::from(ErrorCode::Unknown)),
+                ClientExternalEvent::PeerLeft { client_id: _, reason: _ } => Err(ExternalError::from(ErrorCode::Unknown)),
+                ClientExternalEvent::WaitlistPosition { transaction_id: _, position: _ } => Err(ExternalError::from(ErrorCode::Unknown)),
+                ClientExternalEvent::RequestStateSnapshot { transaction_id: _ } => Err(ExternalError::from(ErrorCode::Unknown)),
+                ClientExternalEvent::StatePatch { format: _, patch: _, version: _ } => Err(ExternalError::from(ErrorCode::Unknown)),
+                ClientExternalEvent::StateSnapshot { state: _, version: _ } => Err(ExternalError::from(ErrorCode::Unknown)),
+                ClientExternalEvent::RequestToMaster {
+                    transaction_id: _,
+                    request_id: _,
+                    data: _,
+                    timeout_ms: _,
+                } => Err(ExternalError::from(ErrorCode::Unknown)),
+                ClientExternalEvent::ResponseToClient { request_id: _, data: _, error: _ } => Err(ExternalError::from(ErrorCode::Unknown)),
             },
         }
     }
@@ -1,4 +1,5 @@
 use crate::errors::relay_error::RelayError;
+use crate::infrastructure::relay_event::RelayEvent;
 use crate::infrastructure::transaction_manager::transaction_manager_inner::TransactionManagerInner;
 use futures::channel::oneshot;
 use relay_core::model::external_error::ExternalError;
@@ -61,7 +62,19 @@ impl TransactionManager {
     pub fn resolve(&self, transaction_id: &str, result: Result<(), ExternalError>) -> Result<(), RelayError> {
         match self.inner.lock() {
             Ok(mut inner) => {
-                inner.resolve_pending(transaction_id, result.map_err(|e| RelayError::ExternalError(e)));
+                inner.resolve_pending(transaction_id, result.map(|_| None).map_err(|e| RelayError::ExternalError(e)));
+                Ok(())
+            }
+            Err(_e) => Err(RelayError::ArcMutexFailure),
+        }
+    }
+
+    /// Resolve a transaction with the event that answered it, so the caller can read anything else it carries
+    pub fn answer(&self, transaction_id: &str, event: RelayEvent) -> Result<(), RelayError> {
+        match self.inner.lock() {
+            Ok(mut inner) => {
+                let result = event.transaction_result().map(|_| Some(event)).map_err(|e| RelayError::ExternalError(e));
+                inner.resolve_pending(transaction_id, result);
                 Ok(())
             }
             Err(_e) => Err(RelayError::ArcMutexFailure),
@@ -69,6 +82,11 @@ impl TransactionManager {
     }
 
     pub async fn defer(&self, transaction_id: &str) -> Result<(), RelayError> {
+        self.defer_answer(transaction_id).await.map(|_| ())
+    }
+
+    /// Wait for a transaction, and return the event that answered it; transactions resolved without one return None
+    pub async fn defer_answer(&self, transaction_id: &str) -> Result<Option<RelayEvent>, RelayError> {
         let rx = match self.inner.lock() {
             Ok(mut inner) => {
                 let (sx, rx) = oneshot::channel();
@@ -78,10 +96,7 @@ impl TransactionManager {
             Err(_e) => return Err(RelayError::ArcMutexFailure),
         };
         match rx.await {
-            Ok(result) => match result {
-                Ok(_) => Ok(()),
-                Err(e) => Err(e),
-            },
+            Ok(result) => result,
             Err(e) => Err(RelayError::SyncError(format!("{}", e))),
         }
     }
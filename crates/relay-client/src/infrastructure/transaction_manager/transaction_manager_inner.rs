@@ -1,12 +1,13 @@
 use futures::channel::oneshot::Sender;
 
 use crate::errors::relay_error::RelayError;
+use crate::infrastructure::relay_event::RelayEvent;
 use chrono::Utc;
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 
 struct PendingTransaction {
-    promise: Sender<Result<(), RelayError>>,
+    promise: Sender<Result<Option<RelayEvent>, RelayError>>,
     started: i64,
 }
 
@@ -33,7 +34,7 @@ impl TransactionManagerInner {
         }
     }
 
-    pub fn resolve_pending(&mut self, transaction_id: &str, result: Result<(), RelayError>) {
+    pub fn resolve_pending(&mut self, transaction_id: &str, result: Result<Option<RelayEvent>, RelayError>) {
         match self.pending.remove(transaction_id) {
             Some(t) => {
                 let _ = t.promise.send(result);
@@ -42,7 +43,7 @@ impl TransactionManagerInner {
         }
     }
 
-    pub fn save_pending_transaction(&mut self, transaction_id: &str, promise: Sender<Result<(), RelayError>>) {
+    pub fn save_pending_transaction(&mut self, transaction_id: &str, promise: Sender<Result<Option<RelayEvent>, RelayError>>) {
         self.pending.insert(
             transaction_id.to_string(),
             PendingTransaction {
@@ -23,6 +23,9 @@ pub use client::Client;
 pub use client_typed::ClientEvent;
 pub use client_typed::ClientTyped;
 
+pub use relay_core::model::master_metadata::MasterMetadata;
+pub use relay_core::model::wire_encoding::WireEncoding;
+
 // For testing
 pub use infrastructure::backend::BackendType;
 
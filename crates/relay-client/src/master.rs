@@ -6,12 +6,12 @@ use crate::infrastructure::transaction_manager::TransactionManager;
 use crate::MasterOptions;
 
 use relay_core::events::master_event::MasterExternalEvent;
-use relay_core::model::master_metadata::MasterMetadata;
 use std::future::Future;
 use uuid::Uuid;
 
 pub struct Master {
     connection: Backend,
+    join_code: Option<String>,
 }
 
 impl Master {
@@ -20,21 +20,29 @@ impl Master {
             auth: AuthHelper::generate_auth(&options.auth),
             remote: options.remote.clone(),
             target: options.backend,
+            encoding: options.encoding,
+            compression_threshold: options.compression_threshold,
             transaction_manager: TransactionManager::new(),
         })
         .await?;
 
-        backend
-            .send(RelayEvent::Master(MasterExternalEvent::InitializeMaster {
+        let answer = backend
+            .request(RelayEvent::Master(MasterExternalEvent::InitializeMaster {
                 transaction_id: Uuid::new_v4().to_string(),
-                metadata: MasterMetadata {
-                    max_clients: options.max_clients,
-                    master_id: options.master_id,
-                },
+                metadata: options.metadata,
             }))
             .await?;
+        let join_code = match answer {
+            Some(RelayEvent::Master(MasterExternalEvent::TransactionResult { join_code, .. })) => join_code,
+            _ => None,
+        };
 
-        Ok(Master { connection: backend })
+        Ok(Master { connection: backend, join_code })
+    }
+
+    /// The code clients can join with, if the session asked for one
+    pub fn join_code(&self) -> Option<&str> {
+        self.join_code.as_deref()
     }
 
     pub fn channel(&self) -> crossbeam::Receiver<RelayEvent> {
@@ -44,6 +52,11 @@ impl Master {
     pub fn send(&self, event: MasterExternalEvent) -> impl Future<Output = Result<(), RelayError>> + '_ {
         self.connection.send(RelayEvent::Master(event))
     }
+
+    /// Send several events in a single frame; the server processes them in order
+    pub fn send_batch(&self, events: Vec<MasterExternalEvent>) -> impl Future<Output = Result<(), RelayError>> + '_ {
+        self.connection.send_batch(events.into_iter().map(RelayEvent::Master).collect())
+    }
 }
 
 #[cfg(test)]
@@ -52,14 +65,22 @@ mod tests {
     use crate::infrastructure::testing::block_on_future;
     use crate::master::{Master, MasterOptions};
     use crate::AuthOptions;
+    use crate::MasterMetadata;
+    use crate::WireEncoding;
 
     #[test]
     fn test_create_master() {
         let _ = block_on_future(Master::new(MasterOptions {
-            master_id: "Master".to_string(),
-            max_clients: 10,
+            metadata: MasterMetadata {
+                master_id: "Master".to_string(),
+                max_clients: 10,
+                ..Default::default()
+            },
             remote: "123".to_string(),
             backend: BackendType::Mock,
+            encoding: WireEncoding::Json,
+            compression_threshold: None,
+            payload_only: false,
             auth: AuthOptions {
                 key: "1234567890".to_string(),
                 secret: "1234567890".to_string(),
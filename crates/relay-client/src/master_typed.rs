@@ -5,6 +5,7 @@ use crate::MasterOptions;
 use relay_core::events::master_event::MasterExternalEvent;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
+use serde_json::value::RawValue;
 use std::io::BufReader;
 use std::thread;
 use uuid::Uuid;
@@ -13,21 +14,35 @@ use uuid::Uuid;
 pub enum MasterEvent<TEvent> {
     External(MasterExternalEvent),
     Internal { client_id: String, event: TEvent },
+
+    /// An event a client wants acknowledged; once it has been processed, send it back or call ack() with the client id and sequence
+    Acknowledge { client_id: String, sequence: u64, event: TEvent },
 }
 
 pub struct MasterTyped<TEvent> {
     master: Master,
     input: crossbeam::Receiver<MasterEvent<TEvent>>,
+    payload_only: bool,
 }
 
 impl<TEvent: Send + Serialize + DeserializeOwned + 'static> MasterTyped<TEvent> {
     /// Create a new instance
     pub async fn new(options: MasterOptions) -> Result<MasterTyped<TEvent>, RelayError> {
+        let payload_only = options.payload_only;
         let master = Master::new(options).await?;
         let (sx, rx) = crossbeam::unbounded();
         let reader = master.channel().clone();
         MasterTyped::<TEvent>::event_loop(reader, sx);
-        Ok(MasterTyped { master, input: rx })
+        Ok(MasterTyped {
+            master,
+            input: rx,
+            payload_only,
+        })
+    }
+
+    /// The code clients can join with, if the session asked for one
+    pub fn join_code(&self) -> Option<&str> {
+        self.master.join_code()
     }
 
     /// Return a receiver channel for this connection
@@ -39,25 +54,85 @@ impl<TEvent: Send + Serialize + DeserializeOwned + 'static> MasterTyped<TEvent>
     pub async fn send(&self, event: MasterEvent<TEvent>) -> Result<(), RelayError> {
         match event {
             MasterEvent::External(ext) => self.master.send(ext).await,
-            MasterEvent::Internal { client_id, event } => self.send_to_client(client_id, event).await,
+            MasterEvent::Internal { client_id, event } => self.send_to_client(client_id, event, false, false).await,
+            MasterEvent::Acknowledge { client_id, sequence, event: _ } => self.ack(client_id, sequence).await,
         }
     }
 
-    async fn send_to_client(&self, client_id: String, event: TEvent) -> Result<(), RelayError> {
-        let raw = match self.serialize(event) {
-            Ok(r) => r,
-            Err(e) => return Err(e),
-        };
-        let event = MasterExternalEvent::MessageToClient {
+    /// Send several events in a single frame, for example an update for every client on each tick
+    pub async fn send_batch(&self, events: Vec<MasterEvent<TEvent>>) -> Result<(), RelayError> {
+        let mut batch = Vec::new();
+        for event in events {
+            batch.push(match event {
+                MasterEvent::External(ext) => ext,
+                MasterEvent::Internal { client_id, event } => self.message_to_client(client_id, event, false, false)?,
+                MasterEvent::Acknowledge { client_id, sequence, event: _ } => Self::ack_message(client_id, sequence),
+            });
+        }
+        self.master.send_batch(batch).await
+    }
+
+    /// Send an event to a client, resolving only once the client application acknowledges it.
+    /// If this fails it is safe to send again; the client may see the event more than once, but never loses it.
+    pub async fn send_acknowledged(&self, client_id: String, event: TEvent) -> Result<(), RelayError> {
+        self.send_to_client(client_id, event, true, false).await
+    }
+
+    /// Send an event to a client without waiting for a result; use this for high rate updates where
+    /// a lost event will be superseded by the next one anyway.
+    pub async fn send_unreliable(&self, client_id: String, event: TEvent) -> Result<(), RelayError> {
+        self.send_to_client(client_id, event, false, true).await
+    }
+
+    /// Confirm that an event from a client that asked for an ack has been processed
+    pub async fn ack(&self, client_id: String, sequence: u64) -> Result<(), RelayError> {
+        self.master.send(Self::ack_message(client_id, sequence)).await
+    }
+
+    /// Answer a RequestFromClient event; the request_id must match the one on the request
+    pub async fn respond<TResponse: Serialize>(&self, client_id: String, request_id: String, response: TResponse) -> Result<(), RelayError> {
+        let data = serde_json::to_string(&response)?;
+        self.master
+            .send(MasterExternalEvent::ResponseToClient {
+                transaction_id: Uuid::new_v4().to_string(),
+                client_id,
+                request_id,
+                data,
+            })
+            .await
+    }
+
+    async fn send_to_client(&self, client_id: String, event: TEvent, ack: bool, unreliable: bool) -> Result<(), RelayError> {
+        let event = self.message_to_client(client_id, event, ack, unreliable)?;
+        self.master.send(event).await
+    }
+
+    fn ack_message(client_id: String, sequence: u64) -> MasterExternalEvent {
+        MasterExternalEvent::AckMessage {
+            transaction_id: Uuid::new_v4().to_string(),
+            client_id,
+            sequence,
+        }
+    }
+
+    fn message_to_client(&self, client_id: String, event: TEvent, ack: bool, unreliable: bool) -> Result<MasterExternalEvent, RelayError> {
+        let (data, payload) = self.serialize(event)?;
+        Ok(MasterExternalEvent::MessageToClient {
             client_id,
             transaction_id: Uuid::new_v4().to_string(),
-            data: raw,
-        };
-        self.master.send(event).await
+            data,
+            payload,
+            ack,
+            unreliable,
+        })
     }
 
-    fn serialize(&self, event: TEvent) -> Result<String, RelayError> {
-        Ok(serde_json::to_string(&event)?)
+    /// Typed events go in data unless payload_only was asked for
+    fn serialize(&self, event: TEvent) -> Result<(String, Option<Box<RawValue>>), RelayError> {
+        if self.payload_only {
+            return Ok((String::new(), Some(serde_json::value::to_raw_value(&event)?)));
+        }
+        Ok((serde_json::to_string(&event)?, None))
     }
 
     fn event_loop(receiver: crossbeam::Receiver<RelayEvent>, sender: crossbeam::Sender<MasterEvent<TEvent>>) {
@@ -67,18 +142,26 @@ impl<TEvent: Send + Serialize + DeserializeOwned + 'static> MasterTyped<TEvent>
                     match relay_event {
                         RelayEvent::Master(event) => {
                             let should_deserialize = match &event {
-                                MasterExternalEvent::MessageFromClient { client_id: _, data: _ } => true,
+                                MasterExternalEvent::MessageFromClient { client_id: _, data: _, payload: _, sequence: _, ack: _ } => true,
                                 _ => false,
                             };
                             if should_deserialize {
                                 match event {
-                                    MasterExternalEvent::MessageFromClient { client_id, data } => {
-                                        match Self::deserialize(data) {
+                                    MasterExternalEvent::MessageFromClient { client_id, data, payload, sequence, ack } => {
+                                        match Self::deserialize(data, payload) {
                                             Ok(internal_event) => {
-                                                match sender.send(MasterEvent::Internal {
-                                                    client_id,
-                                                    event: internal_event,
-                                                }) {
+                                                let output = match ack {
+                                                    true => MasterEvent::Acknowledge {
+                                                        client_id,
+                                                        sequence,
+                                                        event: internal_event,
+                                                    },
+                                                    false => MasterEvent::Internal {
+                                                        client_id,
+                                                        event: internal_event,
+                                                    },
+                                                };
+                                                match sender.send(output) {
                                                     Ok(_) => {}
                                                     Err(_) => {
                                                         // TODO: Log it
@@ -108,16 +191,20 @@ impl<TEvent: Send + Serialize + DeserializeOwned + 'static> MasterTyped<TEvent>
         });
     }
 
-    fn deserialize(raw: String) -> Result<TEvent, RelayError> {
-        Ok(serde_json::from_reader(BufReader::new(raw.as_bytes()))?)
+    /// Clients that send a structured payload skip the string form entirely
+    fn deserialize(raw: String, payload: Option<Box<RawValue>>) -> Result<TEvent, RelayError> {
+        match payload {
+            Some(payload) => Ok(serde_json::from_str(payload.get())?),
+            None => Ok(serde_json::from_reader(BufReader::new(raw.as_bytes()))?),
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::infrastructure::testing::block_on_future;
-    use crate::{AuthOptions, BackendType};
-    use crate::{MasterOptions, MasterTyped};
+    use crate::{AuthOptions, BackendType, WireEncoding};
+    use crate::{MasterMetadata, MasterOptions, MasterTyped};
     use serde::{Deserialize, Serialize};
 
     #[derive(Serialize, Deserialize, Debug)]
@@ -130,10 +217,16 @@ mod tests {
     #[test]
     fn test_create_master() {
         let _ = block_on_future(MasterTyped::<TestEventType>::new(MasterOptions {
-            master_id: "Master".to_string(),
-            max_clients: 10,
+            metadata: MasterMetadata {
+                master_id: "Master".to_string(),
+                max_clients: 10,
+                ..Default::default()
+            },
             remote: "123".to_string(),
             backend: BackendType::Mock,
+            encoding: WireEncoding::Json,
+            compression_threshold: None,
+            payload_only: false,
             auth: AuthOptions {
                 key: "1234567890".to_string(),
                 secret: "1234567890".to_string(),
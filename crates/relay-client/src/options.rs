@@ -1,11 +1,20 @@
 use crate::infrastructure::backend::BackendType;
+use relay_core::model::master_metadata::MasterMetadata;
+use relay_core::model::wire_encoding::WireEncoding;
 
 #[derive(Clone)]
 pub struct MasterOptions {
     pub remote: String,
     pub backend: BackendType,
-    pub master_id: String,
-    pub max_clients: u32,
+    /// How events are written on the connection; JSON unless the relay is known to support something else
+    pub encoding: WireEncoding,
+    /// Ask the relay for permessage-deflate, and compress messages of at least this many bytes if it agrees
+    pub compression_threshold: Option<usize>,
+    /// Send typed events only as a structured payload, leaving data empty; only for peers that read payloads.
+    /// Otherwise typed events go out as a JSON string in data, which every peer understands.
+    pub payload_only: bool,
+    /// The session to create; the join code, if it asks for one, is available from the master once it is running
+    pub metadata: MasterMetadata,
     pub auth: AuthOptions,
 }
 
@@ -13,6 +22,13 @@ pub struct MasterOptions {
 pub struct ClientOptions {
     pub remote: String,
     pub backend: BackendType,
+    /// How events are written on the connection; JSON unless the relay is known to support something else
+    pub encoding: WireEncoding,
+    /// Ask the relay for permessage-deflate, and compress messages of at least this many bytes if it agrees
+    pub compression_threshold: Option<usize>,
+    /// Send typed events only as a structured payload, leaving data empty; only for peers that read payloads.
+    /// Otherwise typed events go out as a JSON string in data, which every peer understands.
+    pub payload_only: bool,
     pub client_id: String,
     pub session_id: String,
     pub auth: AuthOptions,
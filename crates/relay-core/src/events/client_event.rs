@@ -1,43 +1,318 @@
 use serde::{Serialize, Deserialize};
 use crate::model::client_metadata::ClientMetadata;
+use crate::model::client_role::ClientRole;
 use crate::model::external_error::ExternalError;
+use crate::model::patch_format::PatchFormat;
+use crate::model::raw_payload;
+use crate::model::session_filter::SessionFilter;
+use crate::model::wire_encoding::{json_object_type, WireEvent};
+use serde_json::value::RawValue;
+use serde_json::Value;
 
 #[derive(Debug)]
 pub enum ClientInternalEvent {
     /// The response from the master when a request is made join
     ClientJoinResponse { transaction_id: String, success: bool, error: Option<ExternalError> },
 
-    /// Send a message to the master
-    MessageFromMaster { data: String },
+    /// The token to take back this client's slot with if the connection drops
+    ResumeToken { token: String },
+
+    /// This client took back a slot; its messages carry on numbering from the last one it was sent
+    Resumed { sequence: u64 },
+
+    /// Send a message to the master; if ack is set, it's the master transaction waiting for the client to acknowledge it
+    MessageFromMaster { data: String, payload: Option<Box<RawValue>>, ack: Option<String> },
+
+    /// The master answered a request, or the request failed
+    ResponseToClient { request_id: String, data: String, error: Option<ExternalError> },
 
     /// Something went wrong with a request
     MessageFromClientResponse { transaction_id: String, success: bool, error: Option<ExternalError> },
 
+    /// The master moved this client to another session; join it, and report back to the source session
+    TransferToSession { transaction_id: String, session_id: String, source_session_id: String, role: ClientRole },
+
+    /// The response from the master when a request is made to leave
+    LeaveResponse { transaction_id: String, success: bool, error: Option<ExternalError> },
+
     /// The master disconnected for some reason; this is a session ender
     MasterDisconnected { reason: String },
+
+    /// The master removed this client from the session
+    Kicked { reason: String },
+
+    /// The set of groups this client belongs to changed
+    GroupMembership { groups: Vec<String> },
+
+    /// A message from another client in the session
+    MessageFromPeer { client_id: String, data: String },
+
+    /// Another client joined the peer session
+    PeerJoined { client_id: String, name: String },
+
+    /// Another client left the peer session
+    PeerLeft { client_id: String, reason: String },
+
+    /// The session is full and this client is waiting for a slot
+    WaitlistPosition { transaction_id: String, position: u32 },
+
+    /// The master changed the shared session state
+    StatePatch { format: PatchFormat, patch: Value, version: u64 },
+
+    /// The full shared session state, for a client that just joined or asked for it
+    StateSnapshot { state: Value, version: u64 },
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(tag = "object_type")]
 pub enum ClientExternalEvent {
     /// Sent by the client application to initialize a new session
     InitializeClient { transaction_id: String, metadata: ClientMetadata },
 
     /// Join a session by id
-    Join { transaction_id: String, session_id: String },
+    Join {
+        transaction_id: String,
+        session_id: String,
+        #[serde(default)]
+        role: ClientRole,
+        #[serde(default)]
+        waitlist: bool,
+    },
+
+    /// Join whichever active session best matches the filter
+    JoinAny {
+        transaction_id: String,
+        filter: SessionFilter,
+        #[serde(default)]
+        role: ClientRole,
+    },
 
-    /// Send a message to the master, this is a fire and forget action
-    MessageFromClient { transaction_id: String, data: String },
+    /// Join a masterless peer session by id, creating it if it doesn't exist
+    JoinPeerSession { transaction_id: String, session_id: String },
+
+    /// Rejoin a session after the connection dropped, taking back the slot and any messages queued while away.
+    /// Only works in sessions with an offline queue, with the same name as before and the token sent on joining.
+    /// Message sequence numbers carry on from where they were, so queued messages can be told apart from ones already seen.
+    Resume {
+        transaction_id: String,
+        session_id: String,
+        #[serde(default)]
+        resume_token: String,
+    },
+
+    /// The token to resume this session with if the connection drops; sent after joining a session with an offline queue.
+    /// This is a notification event, not an action by the client.
+    ResumeToken { token: String },
+
+    /// Leave the current session, without closing the connection
+    Leave { transaction_id: String },
+
+    /// List the sessions this client can join; the SessionList arrives before the TransactionResult
+    ListSessions { transaction_id: String },
+
+    /// The names of every session in this client's tenant, in answer to ListSessions.
+    /// This is a notification event, not an action by the client.
+    SessionList { transaction_id: String, sessions: Vec<String> },
+
+    /// The master moved this client to another session; if it failed, the client is not in any session.
+    /// This is a notification event, not an action by the client.
+    SessionTransferred {
+        session_id: String,
+        success: bool,
+        error: Option<ExternalError>,
+    },
+
+    /// Send a message to the master, this is a fire and forget action.
+    /// If ack is set, the transaction only resolves once the master application acknowledges the message.
+    /// If unreliable is set, no TransactionResult is sent for it at all, even if it fails.
+    /// The message is either a string in data, or any JSON value in payload, which is passed on as it is.
+    MessageFromClient {
+        transaction_id: String,
+        #[serde(default)]
+        data: String,
+        #[serde(default, skip_serializing_if = "Option::is_none", with = "raw_payload")]
+        payload: Option<Box<RawValue>>,
+        #[serde(default)]
+        ack: bool,
+        #[serde(default)]
+        unreliable: bool,
+    },
 
     /// Sent by the application to notify about transaction result
     TransactionResult { transaction_id: String, success: bool, error: Option<ExternalError> },
 
-    /// Recv a message from the master
-    MessageToClient { data: String },
+    /// Recv a message from the master; sequence counts up by one for each message this client receives.
+    /// If ack is set, the master is waiting for an AckMessage with this sequence number.
+    MessageToClient {
+        #[serde(default)]
+        data: String,
+        #[serde(default, skip_serializing_if = "Option::is_none", with = "raw_payload")]
+        payload: Option<Box<RawValue>>,
+        #[serde(default)]
+        sequence: u64,
+        #[serde(default)]
+        ack: bool,
+    },
+
+    /// Confirm that a message from the master that asked for an ack has been processed
+    AckMessage { transaction_id: String, sequence: u64 },
+
+    /// Send a request to the master; the transaction resolves when the master has it, and the answer
+    /// arrives later as a ResponseToClient with the same request_id. Uses the default timeout if none is given;
+    /// timeouts longer than ten minutes are rejected with TimeoutOutOfRange.
+    RequestToMaster {
+        transaction_id: String,
+        request_id: String,
+        data: String,
+        #[serde(default)]
+        timeout_ms: Option<u64>,
+    },
+
+    /// The answer to a request; if the request timed out or failed, error is set and data is empty.
+    /// This is a notification event, not an action by the client.
+    ResponseToClient {
+        request_id: String,
+        data: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        error: Option<ExternalError>,
+    },
 
     /// The internal master disconnected or booted this client
     /// This is a notification event, not an action by the client.
     MasterDisconnected { reason: String },
+
+    /// The master removed this client from the session; the client may join another session.
+    /// This is a notification event, not an action by the client.
+    Kicked { reason: String },
+
+    /// The full set of groups this client now belongs to; sent whenever it changes.
+    /// This is a notification event, not an action by the client.
+    GroupMembership { groups: Vec<String> },
+
+    /// Send a message to another client in the session, if the session allows it
+    MessageToPeer {
+        transaction_id: String,
+        client_id: String,
+        data: String,
+    },
+
+    /// Send a message to every other client in the session, if the session allows it
+    BroadcastToPeers { transaction_id: String, data: String },
+
+    /// Recv a message from another client in the session
+    MessageFromPeer { client_id: String, data: String },
+
+    /// Another client joined the peer session
+    /// This is a notification event, not an action by the client.
+    PeerJoined { client_id: String, name: String },
+
+    /// Another client left the peer session
+    /// This is a notification event, not an action by the client.
+    PeerLeft { client_id: String, reason: String },
+
+    /// The session this client asked to join is full; the join completes when a slot frees up.
+    /// This is a notification event, not an action by the client.
+    WaitlistPosition { transaction_id: String, position: u32 },
+
+    /// Ask for the full shared session state, eg. after noticing a gap in the patch versions;
+    /// the StateSnapshot arrives before the TransactionResult
+    RequestStateSnapshot { transaction_id: String },
+
+    /// The master changed the shared session state; version goes up by exactly one for each patch.
+    /// This is a notification event, not an action by the client.
+    StatePatch { format: PatchFormat, patch: Value, version: u64 },
+
+    /// The full shared session state; sent after joining, and in answer to RequestStateSnapshot.
+    /// This is a notification event, not an action by the client.
+    StateSnapshot { state: Value, version: u64 },
+}
+
+impl ClientExternalEvent {
+    /// The transaction id of a request from the client application; notifications don't have one
+    pub fn transaction_id(&self) -> Option<&str> {
+        match self {
+            ClientExternalEvent::InitializeClient { transaction_id, .. }
+            | ClientExternalEvent::Join { transaction_id, .. }
+            | ClientExternalEvent::JoinAny { transaction_id, .. }
+            | ClientExternalEvent::JoinPeerSession { transaction_id, .. }
+            | ClientExternalEvent::Resume { transaction_id, .. }
+            | ClientExternalEvent::Leave { transaction_id }
+            | ClientExternalEvent::ListSessions { transaction_id }
+            | ClientExternalEvent::MessageFromClient { transaction_id, .. }
+            | ClientExternalEvent::AckMessage { transaction_id, .. }
+            | ClientExternalEvent::RequestToMaster { transaction_id, .. }
+            | ClientExternalEvent::MessageToPeer { transaction_id, .. }
+            | ClientExternalEvent::BroadcastToPeers { transaction_id, .. }
+            | ClientExternalEvent::RequestStateSnapshot { transaction_id } => Some(transaction_id),
+            _ => None,
+        }
+    }
+
+    /// Does this request deliver a message to someone, so processing it twice would deliver it twice.
+    /// Unreliable messages are never retried, so they don't count.
+    pub fn delivers_message(&self) -> bool {
+        match self {
+            ClientExternalEvent::MessageFromClient { unreliable, .. } => !*unreliable,
+            ClientExternalEvent::RequestToMaster { .. }
+            | ClientExternalEvent::MessageToPeer { .. }
+            | ClientExternalEvent::BroadcastToPeers { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+/// The fields of MessageFromClient, read untagged so the payload arrives raw
+#[derive(Deserialize)]
+struct MessageFromClientFields {
+    transaction_id: String,
+    #[serde(default)]
+    data: String,
+    #[serde(default)]
+    payload: Option<Box<RawValue>>,
+    #[serde(default)]
+    ack: bool,
+    #[serde(default)]
+    unreliable: bool,
+}
+
+/// The fields of MessageToClient, read untagged so the payload arrives raw
+#[derive(Deserialize)]
+struct MessageToClientFields {
+    #[serde(default)]
+    data: String,
+    #[serde(default)]
+    payload: Option<Box<RawValue>>,
+    #[serde(default)]
+    sequence: u64,
+    #[serde(default)]
+    ack: bool,
+}
+
+impl WireEvent for ClientExternalEvent {
+    fn from_json(raw: &[u8]) -> Result<Self, serde_json::Error> {
+        match json_object_type(raw)?.as_str() {
+            "MessageFromClient" => {
+                let fields: MessageFromClientFields = serde_json::from_slice(raw)?;
+                Ok(ClientExternalEvent::MessageFromClient {
+                    transaction_id: fields.transaction_id,
+                    data: fields.data,
+                    payload: fields.payload,
+                    ack: fields.ack,
+                    unreliable: fields.unreliable,
+                })
+            }
+            "MessageToClient" => {
+                let fields: MessageToClientFields = serde_json::from_slice(raw)?;
+                Ok(ClientExternalEvent::MessageToClient {
+                    data: fields.data,
+                    payload: fields.payload,
+                    sequence: fields.sequence,
+                    ack: fields.ack,
+                })
+            }
+            _ => serde_json::from_slice(raw),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -47,6 +322,9 @@ pub enum ClientControlEvent {
 
     /// Sent by the websocket handler to notify that the client disconnected
     ClientDisconnected { reason: String },
+
+    /// Sent by the websocket handler after spawning, with the key the connection authorized with and its tenant
+    Authorized { auth_key: String, tenant: String },
 }
 
 #[derive(Debug)]
@@ -1,7 +1,14 @@
+use crate::model::client_metadata::ClientMetadata;
+use crate::model::client_role::ClientRole;
 use crate::model::external_error::ExternalError;
 use crate::model::master_metadata::MasterMetadata;
+use crate::model::patch_format::PatchFormat;
+use crate::model::raw_payload;
+use crate::model::wire_encoding::{json_object_type, WireEvent};
 use rust_isolate::IsolateIdentity;
 use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use serde_json::Value;
 
 #[derive(Debug)]
 pub enum MasterInternalEvent {
@@ -10,17 +17,87 @@ pub enum MasterInternalEvent {
         transaction_id: String,
         client_id: String,
         identity: IsolateIdentity,
+        metadata: ClientMetadata,
+        auth_key: Option<String>,
+        role: ClientRole,
+        waitlist: bool,
     },
 
-    /// A client disconnected
-    ClientDisconnected { identity: IsolateIdentity, reason: String },
+    /// A client that dropped its connection wants its slot back; it must have the token it was sent when it joined
+    ClientResumeRequest {
+        transaction_id: String,
+        identity: IsolateIdentity,
+        name: String,
+        auth_key: Option<String>,
+        resume_token: String,
+    },
+
+    /// A client disconnected; sequence is the number of the last message it was sent
+    ClientDisconnected { identity: IsolateIdentity, reason: String, sequence: u64 },
+
+    /// A client asked to leave this master
+    ClientLeave { transaction_id: String, identity: IsolateIdentity },
+
+    /// A message from the master of another session
+    MessageFromMaster {
+        transaction_id: String,
+        source_session_id: String,
+        data: String,
+    },
+
+    /// The outcome of a message this master sent to another session
+    MessageToMasterResponse {
+        transaction_id: String,
+        success: bool,
+        error: Option<ExternalError>,
+    },
+
+    /// The outcome of a client transfer this master started
+    TransferResult {
+        transaction_id: String,
+        success: bool,
+        error: Option<ExternalError>,
+    },
 
     /// Send a message to the master
     MessageFromClient {
         transaction_id: String,
         client_id: IsolateIdentity,
         data: String,
+        payload: Option<Box<RawValue>>,
+        ack: bool,
+        unreliable: bool,
+    },
+
+    /// A request from a client that expects an answer
+    RequestFromClient {
+        transaction_id: String,
+        identity: IsolateIdentity,
+        request_id: String,
+        data: String,
+        timeout_ms: Option<u64>,
     },
+
+    /// A client acknowledged a message the master sent with ack set
+    MessageAck { transaction_id: String, identity: IsolateIdentity },
+
+    /// Route a message from one client to another
+    MessageToPeer {
+        transaction_id: String,
+        sender: IsolateIdentity,
+        client_id: String,
+        data: String,
+    },
+
+    /// Route a message from one client to every other client
+    BroadcastToPeers {
+        transaction_id: String,
+        sender: IsolateIdentity,
+        data: String,
+    },
+
+    /// A client asked for the full shared session state
+    StateSnapshotRequest { transaction_id: String, identity: IsolateIdentity },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -29,11 +106,21 @@ pub enum MasterExternalEvent {
     /// Sent by the client application to initialize a new session
     InitializeMaster { transaction_id: String, metadata: MasterMetadata },
 
-    /// Recv a message from the external master to send to a client
+    /// Recv a message from the external master to send to a client.
+    /// If ack is set, the transaction only resolves once the client application acknowledges the message.
+    /// If unreliable is set, no TransactionResult is sent for it at all, even if it fails.
+    /// The message is either a string in data, or any JSON value in payload, which is passed on as it is.
     MessageToClient {
         transaction_id: String,
         client_id: String,
+        #[serde(default)]
         data: String,
+        #[serde(default, skip_serializing_if = "Option::is_none", with = "raw_payload")]
+        payload: Option<Box<RawValue>>,
+        #[serde(default)]
+        ack: bool,
+        #[serde(default)]
+        unreliable: bool,
     },
 
     /// Sent by the application to notify about transaction result
@@ -41,16 +128,286 @@ pub enum MasterExternalEvent {
         transaction_id: String,
         success: bool,
         error: Option<ExternalError>,
+        /// The join code for the session, set on the result of InitializeMaster if one was requested
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        join_code: Option<String>,
     },
 
     /// Notify the master that a client joined
-    ClientJoined { client_id: String, name: String },
+    ClientJoined {
+        client_id: String,
+        name: String,
+        #[serde(default)]
+        role: ClientRole,
+    },
 
     /// A client disconnected for some reason, a notification for the external master
     ClientDisconnected { client_id: String, reason: String },
 
-    /// Send a message to the external master
-    MessageFromClient { client_id: String, data: String },
+    /// A disconnected client came back; it has a new client id, and any queued messages have been delivered
+    ClientResumed { client_id: String, previous_client_id: String },
+
+    /// Send a message to the external master; sequence counts up by one for each message from this client.
+    /// If ack is set, the client is waiting for an AckMessage with this client id and sequence number.
+    MessageFromClient {
+        client_id: String,
+        #[serde(default)]
+        data: String,
+        #[serde(default, skip_serializing_if = "Option::is_none", with = "raw_payload")]
+        payload: Option<Box<RawValue>>,
+        #[serde(default)]
+        sequence: u64,
+        #[serde(default)]
+        ack: bool,
+    },
+
+    /// Confirm that a message from a client that asked for an ack has been processed
+    AckMessage {
+        transaction_id: String,
+        client_id: String,
+        sequence: u64,
+    },
+
+    /// A request from a client; answer it with ResponseToClient before it times out
+    RequestFromClient {
+        client_id: String,
+        request_id: String,
+        data: String,
+    },
+
+    /// Answer a request from a client
+    ResponseToClient {
+        transaction_id: String,
+        client_id: String,
+        request_id: String,
+        data: String,
+    },
+
+    /// A client asked to join a session that requires approval; answer with ApproveJoin or RejectJoin
+    ClientJoinRequested {
+        client_id: String,
+        name: String,
+        metadata: ClientMetadata,
+        #[serde(default)]
+        role: ClientRole,
+    },
+
+    /// A join request was not answered in time and failed; the client never joined the session
+    JoinRequestExpired { client_id: String },
+
+    /// Admit a client that is waiting for approval
+    ApproveJoin { transaction_id: String, client_id: String },
+
+    /// Refuse a client that is waiting for approval
+    RejectJoin {
+        transaction_id: String,
+        client_id: String,
+        reason: String,
+    },
+
+    /// Remove a connected client from the session, optionally banning it from joining again.
+    /// A ban covers the client's name and connection; if ban_key is set, it also covers the auth key the
+    /// client connected with, which keeps out every client that shares that key.
+    KickClient {
+        transaction_id: String,
+        client_id: String,
+        reason: String,
+        #[serde(default)]
+        ban: bool,
+        #[serde(default)]
+        ban_key: bool,
+    },
+
+    /// Send a message to every connected client, except those listed in exclude.
+    /// Retained messages are also replayed to clients that join later, if the session keeps a history.
+    BroadcastToClients {
+        transaction_id: String,
+        data: String,
+        #[serde(default)]
+        exclude: Vec<String>,
+        #[serde(default)]
+        retain: bool,
+    },
+
+    /// Send a message to a specific set of connected clients
+    MulticastToClients {
+        transaction_id: String,
+        client_ids: Vec<String>,
+        data: String,
+    },
+
+    /// Create a new, empty, named group of clients
+    CreateGroup { transaction_id: String, group: String },
+
+    /// Remove a group; its members are told they left it
+    DeleteGroup { transaction_id: String, group: String },
+
+    /// Add a connected client to a group
+    AddToGroup {
+        transaction_id: String,
+        group: String,
+        client_id: String,
+    },
+
+    /// Remove a client from a group
+    RemoveFromGroup {
+        transaction_id: String,
+        group: String,
+        client_id: String,
+    },
+
+    /// Send a message to every client in a group
+    MessageToGroup {
+        transaction_id: String,
+        group: String,
+        data: String,
+    },
+
+    /// Move a connected client into another session
+    TransferClient {
+        transaction_id: String,
+        client_id: String,
+        target_session_id: String,
+    },
+
+    /// Hold a player slot for a client name or auth key until it joins, or the reservation expires
+    ReserveSeat {
+        transaction_id: String,
+        reservation: String,
+        expires_ms: u64,
+    },
+
+    /// Release a reserved slot
+    CancelReservation { transaction_id: String, reservation: String },
+
+    /// Send a message to the master of another session
+    MessageToMaster {
+        transaction_id: String,
+        session_id: String,
+        data: String,
+    },
+
+    /// Recv a message from the master of another session
+    MessageFromMaster { session_id: String, data: String },
+
+    /// Change the shared session state; every connected client is sent the patch
+    PatchState {
+        transaction_id: String,
+        format: PatchFormat,
+        patch: Value,
+    },
+
+    /// Send a message to every connected spectator
+    MessageToSpectators { transaction_id: String, data: String },
+
+    /// A copy of a message sent between clients, for sessions that moderate peer messages
+    PeerMessage {
+        client_id: String,
+        recipients: Vec<String>,
+        data: String,
+    },
+}
+
+impl MasterExternalEvent {
+    /// The transaction id of a request from the master application; notifications don't have one
+    pub fn transaction_id(&self) -> Option<&str> {
+        match self {
+            MasterExternalEvent::InitializeMaster { transaction_id, .. }
+            | MasterExternalEvent::MessageToClient { transaction_id, .. }
+            | MasterExternalEvent::AckMessage { transaction_id, .. }
+            | MasterExternalEvent::ResponseToClient { transaction_id, .. }
+            | MasterExternalEvent::ApproveJoin { transaction_id, .. }
+            | MasterExternalEvent::RejectJoin { transaction_id, .. }
+            | MasterExternalEvent::KickClient { transaction_id, .. }
+            | MasterExternalEvent::BroadcastToClients { transaction_id, .. }
+            | MasterExternalEvent::MulticastToClients { transaction_id, .. }
+            | MasterExternalEvent::CreateGroup { transaction_id, .. }
+            | MasterExternalEvent::DeleteGroup { transaction_id, .. }
+            | MasterExternalEvent::AddToGroup { transaction_id, .. }
+            | MasterExternalEvent::RemoveFromGroup { transaction_id, .. }
+            | MasterExternalEvent::MessageToGroup { transaction_id, .. }
+            | MasterExternalEvent::TransferClient { transaction_id, .. }
+            | MasterExternalEvent::ReserveSeat { transaction_id, .. }
+            | MasterExternalEvent::CancelReservation { transaction_id, .. }
+            | MasterExternalEvent::MessageToMaster { transaction_id, .. }
+            | MasterExternalEvent::PatchState { transaction_id, .. }
+            | MasterExternalEvent::MessageToSpectators { transaction_id, .. } => Some(transaction_id),
+            _ => None,
+        }
+    }
+
+    /// Does this request deliver a message to someone, so processing it twice would deliver it twice.
+    /// Unreliable messages are never retried, so they don't count.
+    pub fn delivers_message(&self) -> bool {
+        match self {
+            MasterExternalEvent::MessageToClient { unreliable, .. } => !*unreliable,
+            MasterExternalEvent::ResponseToClient { .. }
+            | MasterExternalEvent::BroadcastToClients { .. }
+            | MasterExternalEvent::MulticastToClients { .. }
+            | MasterExternalEvent::MessageToGroup { .. }
+            | MasterExternalEvent::MessageToMaster { .. }
+            | MasterExternalEvent::MessageToSpectators { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+/// The fields of MessageToClient, read untagged so the payload arrives raw
+#[derive(Deserialize)]
+struct MessageToClientFields {
+    transaction_id: String,
+    client_id: String,
+    #[serde(default)]
+    data: String,
+    #[serde(default)]
+    payload: Option<Box<RawValue>>,
+    #[serde(default)]
+    ack: bool,
+    #[serde(default)]
+    unreliable: bool,
+}
+
+/// The fields of MessageFromClient, read untagged so the payload arrives raw
+#[derive(Deserialize)]
+struct MessageFromClientFields {
+    client_id: String,
+    #[serde(default)]
+    data: String,
+    #[serde(default)]
+    payload: Option<Box<RawValue>>,
+    #[serde(default)]
+    sequence: u64,
+    #[serde(default)]
+    ack: bool,
+}
+
+impl WireEvent for MasterExternalEvent {
+    fn from_json(raw: &[u8]) -> Result<Self, serde_json::Error> {
+        match json_object_type(raw)?.as_str() {
+            "MessageToClient" => {
+                let fields: MessageToClientFields = serde_json::from_slice(raw)?;
+                Ok(MasterExternalEvent::MessageToClient {
+                    transaction_id: fields.transaction_id,
+                    client_id: fields.client_id,
+                    data: fields.data,
+                    payload: fields.payload,
+                    ack: fields.ack,
+                    unreliable: fields.unreliable,
+                })
+            }
+            "MessageFromClient" => {
+                let fields: MessageFromClientFields = serde_json::from_slice(raw)?;
+                Ok(MasterExternalEvent::MessageFromClient {
+                    client_id: fields.client_id,
+                    data: fields.data,
+                    payload: fields.payload,
+                    sequence: fields.sequence,
+                    ack: fields.ack,
+                })
+            }
+            _ => serde_json::from_slice(raw),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -60,6 +417,12 @@ pub enum MasterControlEvent {
 
     /// Sent by the websocket to notify of a master disconnect
     MasterDisconnected { reason: String },
+
+    /// Sent by the websocket handler after spawning, with the tenant the connection belongs to
+    Authorized { tenant: String },
+
+    /// Sent by the session manager to turn a freshly spawned master into a masterless peer session
+    InitializePeerSession { name: String, tenant: String },
 }
 
 #[derive(Debug)]
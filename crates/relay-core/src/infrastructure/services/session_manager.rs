@@ -5,13 +5,20 @@ use crate::infrastructure::services::SessionManagerError;
 use rust_isolate::IsolateIdentity;
 use rust_isolate::IsolateChannel;
 use crate::events::master_event::MasterEvent;
+use crate::events::master_event::MasterInternalEvent;
 use std::sync::PoisonError;
 use std::sync::MutexGuard;
 use rust_isolate::IsolateRegistryRef;
 use crate::events::client_event::ClientEvent;
+use crate::model::master_metadata::MasterMetadata;
+use crate::model::session_filter::SessionFilter;
+use crossbeam::Receiver;
 
 pub mod session_manager_error;
+mod join_code;
 mod session_manager_inner;
+mod session_record;
+mod tenant_sessions;
 
 #[derive(Clone)]
 pub struct SessionManager {
@@ -25,26 +32,66 @@ impl SessionManager {
         }
     }
 
-    /// Register a new session, if there isn't a conflict in the requested name
-    pub fn register_session(&self, identity: &IsolateIdentity, name: &str) -> Result<(), SessionManagerError> {
+    /// Register a new session for a tenant, if there isn't a conflict in the requested name; returns the join code, if one was requested
+    pub fn register_session(&self, tenant: &str, identity: &IsolateIdentity, metadata: &MasterMetadata) -> Result<Option<String>, SessionManagerError> {
         let mut inner = self.inner.lock()?;
-        inner.register_session(identity, name)
+        inner.register_session(tenant, identity, metadata)
+    }
+
+    /// Update how many player slots in a session are taken
+    pub fn update_occupancy(&self, tenant: &str, name: &str, taken: u32) -> Result<(), SessionManagerError> {
+        let mut inner = self.inner.lock()?;
+        inner.update_occupancy(tenant, name, taken)
     }
 
     /// Remove an existing session
-    pub fn remove_session(&mut self, name: &str) -> Result<(), SessionManagerError> {
+    pub fn remove_session(&mut self, tenant: &str, name: &str) -> Result<(), SessionManagerError> {
         let mut inner = self.inner.lock()?;
-        inner.remove_session(name)
+        inner.remove_session(tenant, name)
     }
 
 
-    /// Find a registered master by name
-    pub fn find_master(&self, name: &str) -> Result<IsolateChannel<MasterEvent>, SessionManagerError> {
+    /// Find a registered master by name, in the given tenant
+    pub fn find_master(&self, tenant: &str, name: &str) -> Result<IsolateChannel<MasterEvent>, SessionManagerError> {
+        let inner = self.inner.lock()?;
+        let master_ref = inner.find_master(tenant, name)?;
+        Ok(master_ref)
+    }
+
+    /// Find the fullest session in the tenant that matches the filter and still has room
+    pub fn find_matching_session(&self, tenant: &str, filter: &SessionFilter) -> Result<IsolateChannel<MasterEvent>, SessionManagerError> {
         let inner = self.inner.lock()?;
-        let master_ref = inner.find_master(name)?;
+        let master_ref = inner.find_matching_session(tenant, filter)?;
         Ok(master_ref)
     }
 
+    /// Send a join request to a peer session, creating the session if it doesn't exist yet.
+    /// The request is sent while holding the lock, so it can't race with the session being removed.
+    pub fn join_peer_session(&self, tenant: &str, name: &str, join: MasterInternalEvent) -> Result<IsolateChannel<MasterEvent>, SessionManagerError> {
+        let mut inner = self.inner.lock()?;
+        let peer_ref = inner.join_peer_session(tenant, name, join)?;
+        Ok(peer_ref)
+    }
+
+    /// Record which isolate runs a peer session, so only that isolate can remove it
+    pub fn claim_peer_session(&self, tenant: &str, name: &str, identity: &IsolateIdentity) -> Result<(), SessionManagerError> {
+        let mut inner = self.inner.lock()?;
+        inner.claim_peer_session(tenant, name, identity);
+        Ok(())
+    }
+
+    /// Remove an empty peer session if its inbox is empty; returns false if a join arrived and it should keep running
+    pub fn remove_idle_peer_session(&self, tenant: &str, name: &str, identity: &IsolateIdentity, inbox: &Receiver<MasterEvent>) -> Result<bool, SessionManagerError> {
+        let mut inner = self.inner.lock()?;
+        inner.remove_idle_peer_session(tenant, name, identity, inbox)
+    }
+
+    /// List the names of every session in a tenant
+    pub fn list_sessions(&self, tenant: &str) -> Result<Vec<String>, SessionManagerError> {
+        let inner = self.inner.lock()?;
+        Ok(inner.list_sessions(tenant))
+    }
+
     /// Find a registered session by name
     pub fn find_client(&self, identity: &IsolateIdentity) -> Result<IsolateChannel<ClientEvent>, SessionManagerError> {
         let inner = self.inner.lock()?;
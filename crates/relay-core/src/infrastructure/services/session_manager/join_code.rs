@@ -0,0 +1,20 @@
+use uuid::Uuid;
+
+/// Letters and digits that can't be mistaken for each other; no I, O, 0 or 1
+const JOIN_CODE_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+
+/// How many characters in a join code
+const JOIN_CODE_LENGTH: usize = 6;
+
+/// Generate a new random join code; this does not check it is unique
+pub fn generate_join_code() -> String {
+    Uuid::new_v4().as_bytes().iter()
+        .take(JOIN_CODE_LENGTH)
+        .map(|b| JOIN_CODE_ALPHABET[*b as usize % JOIN_CODE_ALPHABET.len()] as char)
+        .collect()
+}
+
+/// Join codes are typed by people, so match them regardless of case
+pub fn normalize_join_code(code: &str) -> String {
+    code.trim().to_uppercase()
+}
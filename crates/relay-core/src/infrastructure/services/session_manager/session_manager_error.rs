@@ -5,9 +5,18 @@ pub enum SessionManagerError {
     /// Conflict with an existing session name
     NameAlreadyInUse,
 
+    /// The session name is the join code of another session, so clients couldn't tell them apart
+    NameMatchesJoinCode,
+
     /// No match for the master id that was requested
     NoMatchingMaster,
 
     /// No match for the client id that was requested
     NoMatchingClient,
+
+    /// Unable to spawn or start an isolate for a peer session
+    PeerSessionFailed,
+
+    /// No active session matches a matchmaking filter
+    NoMatchingSession,
 }
\ No newline at end of file
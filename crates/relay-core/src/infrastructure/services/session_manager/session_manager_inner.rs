@@ -2,60 +2,161 @@ use rust_isolate::IsolateIdentity;
 use crate::infrastructure::services::SessionManagerError;
 use rust_isolate::IsolateChannel;
 use crate::events::master_event::MasterEvent;
+use crate::events::master_event::MasterInternalEvent;
 use crate::MASTER;
 use std::collections::HashMap;
 use rust_isolate::IsolateRegistryError;
 use rust_isolate::IsolateRegistryRef;
 use crate::CLIENT;
 use crate::events::client_event::ClientEvent;
+use crate::events::master_event::MasterControlEvent;
+use crate::infrastructure::services::session_manager::session_record::PeerSessionRecord;
+use crate::infrastructure::services::session_manager::tenant_sessions::TenantSessions;
+use crate::model::master_metadata::MasterMetadata;
+use crate::model::session_filter::SessionFilter;
+use crossbeam::Receiver;
 
 pub struct SessionManagerInner {
     registry: IsolateRegistryRef,
-    sessions: HashMap<String, IsolateIdentity>,
+    tenants: HashMap<String, TenantSessions>,
 }
 
 impl SessionManagerInner {
     pub fn new(registry: IsolateRegistryRef) -> SessionManagerInner {
         SessionManagerInner {
             registry,
-            sessions: HashMap::new(),
+            tenants: HashMap::new(),
         }
     }
 
     /// Register a new session, if there isn't a conflict in the requested name
-    pub fn register_session(&mut self, identity: &IsolateIdentity, name: &str) -> Result<(), SessionManagerError> {
-        if self.sessions.contains_key(name) {
-            return Err(SessionManagerError::NameAlreadyInUse);
+    pub fn register_session(&mut self, tenant: &str, identity: &IsolateIdentity, metadata: &MasterMetadata) -> Result<Option<String>, SessionManagerError> {
+        self.tenant_mut(tenant).register_session(identity, metadata)
+    }
+
+    /// Update how many player slots in a session are taken
+    pub fn update_occupancy(&mut self, tenant: &str, name: &str, taken: u32) -> Result<(), SessionManagerError> {
+        match self.tenants.get_mut(tenant).and_then(|t| t.sessions.get_mut(name)) {
+            Some(record) => {
+                record.taken = taken;
+                Ok(())
+            }
+            None => Err(SessionManagerError::NoMatchingMaster)
         }
-        self.sessions.insert(name.to_string(), identity.clone());
-        Ok(())
     }
 
     /// Remove an existing session
-    pub fn remove_session(&mut self, name: &str) -> Result<(), SessionManagerError> {
-        if !self.sessions.contains_key(name) {
-            return Err(SessionManagerError::NoMatchingMaster);
+    pub fn remove_session(&mut self, tenant: &str, name: &str) -> Result<(), SessionManagerError> {
+        let result = match self.tenants.get_mut(tenant) {
+            Some(t) => t.remove_session(name),
+            None => Err(SessionManagerError::NoMatchingMaster)
+        };
+
+        // Forget tenants with nothing left in them
+        if self.tenants.get(tenant).map(|t| !t.contains_any()).unwrap_or(false) {
+            self.tenants.remove(tenant);
         }
-        self.sessions.remove(name);
-        Ok(())
+        result
     }
 
     /// Find a registered master by name
-    pub fn find_master(&self, name: &str) -> Result<IsolateChannel<MasterEvent>, SessionManagerError> {
-        // Find the session
-        let identity = self.sessions.get(name);
-        if identity.is_none() {
+    pub fn find_master(&self, tenant: &str, name: &str) -> Result<IsolateChannel<MasterEvent>, SessionManagerError> {
+        let sessions = match self.tenants.get(tenant) {
+            Some(s) => s,
+            None => return Err(SessionManagerError::NoMatchingMaster)
+        };
+        if let Some(peer) = sessions.peer_sessions.get(name) {
+            return Ok(peer.channel.clone());
+        }
+
+        // Find the session, by name or by join code
+        let record = sessions.find_session(name);
+        if record.is_none() {
             return Err(SessionManagerError::NoMatchingMaster);
         }
 
         // Find a reference in the registry
         let master_runtime = self.registry.find(MASTER)?;
-        match master_runtime.find(identity.unwrap()) {
+        match master_runtime.find(&record.unwrap().identity) {
             Some(master_ref) => Ok(master_ref),
             None => Err(SessionManagerError::NoMatchingMaster)
         }
     }
 
+    /// Find the fullest session that matches the filter and still has room; peer sessions are never matched
+    pub fn find_matching_session(&self, tenant: &str, filter: &SessionFilter) -> Result<IsolateChannel<MasterEvent>, SessionManagerError> {
+        match self.tenants.get(tenant).and_then(|t| t.find_matching_session(filter)) {
+            Some(name) => self.find_master(tenant, name),
+            None => Err(SessionManagerError::NoMatchingSession)
+        }
+    }
+
+    /// Send a join request to a peer session, spawning a new masterless session if there isn't one yet.
+    /// A session whose isolate stopped without unregistering is replaced.
+    pub fn join_peer_session(&mut self, tenant: &str, name: &str, join: MasterInternalEvent) -> Result<IsolateChannel<MasterEvent>, SessionManagerError> {
+        let peer_ref = self.find_or_create_peer_session(tenant, name)?;
+        match peer_ref.sender.send(MasterEvent::Internal(join)) {
+            Ok(_) => Ok(peer_ref),
+            Err(e) => {
+                self.tenant_mut(tenant).peer_sessions.remove(name);
+                let peer_ref = self.find_or_create_peer_session(tenant, name)?;
+                peer_ref.sender.send(e.into_inner()).map_err(|_| SessionManagerError::PeerSessionFailed)?;
+                Ok(peer_ref)
+            }
+        }
+    }
+
+    /// Record the identity of the isolate running a peer session, once it starts
+    pub fn claim_peer_session(&mut self, tenant: &str, name: &str, identity: &IsolateIdentity) {
+        if let Some(peer) = self.tenants.get_mut(tenant).and_then(|t| t.peer_sessions.get_mut(name)) {
+            if peer.identity.is_none() {
+                peer.identity = Some(identity.clone());
+            }
+        }
+    }
+
+    /// Remove an empty peer session, unless something is still waiting in its inbox; returns true if it should halt.
+    /// A session registered under the same name by another isolate is not touched; this one halts all the same.
+    pub fn remove_idle_peer_session(&mut self, tenant: &str, name: &str, identity: &IsolateIdentity, inbox: &Receiver<MasterEvent>) -> Result<bool, SessionManagerError> {
+        if !inbox.is_empty() {
+            return Ok(false);
+        }
+        let removed = self.tenants.get_mut(tenant).map(|t| t.remove_peer_session(name, identity)).unwrap_or(false);
+        if removed && self.tenants.get(tenant).map(|t| !t.contains_any()).unwrap_or(false) {
+            self.tenants.remove(tenant);
+        }
+        Ok(true)
+    }
+
+    /// Find a peer session by name, spawning a new masterless session if there isn't one yet
+    fn find_or_create_peer_session(&mut self, tenant: &str, name: &str) -> Result<IsolateChannel<MasterEvent>, SessionManagerError> {
+        if let Some(peer) = self.tenants.get(tenant).and_then(|t| t.peer_sessions.get(name)) {
+            return Ok(peer.channel.clone());
+        }
+        if self.tenants.get(tenant).map(|t| t.sessions.contains_key(name)).unwrap_or(false) {
+            return Err(SessionManagerError::NameAlreadyInUse);
+        }
+        if self.tenants.get(tenant).map(|t| t.is_join_code(name)).unwrap_or(false) {
+            return Err(SessionManagerError::NameMatchesJoinCode);
+        }
+
+        // Spawn a master isolate to run the session; nothing external is attached to it
+        let mut master_runtime = self.registry.find::<MasterEvent>(MASTER)?;
+        let peer_ref = master_runtime.spawn().map_err(|_| SessionManagerError::PeerSessionFailed)?;
+        peer_ref.sender.send(MasterEvent::Control(MasterControlEvent::InitializePeerSession {
+            name: name.to_string(),
+            tenant: tenant.to_string(),
+        })).map_err(|_| SessionManagerError::PeerSessionFailed)?;
+
+        self.tenant_mut(tenant).peer_sessions.insert(name.to_string(), PeerSessionRecord::new(peer_ref.clone()));
+        Ok(peer_ref)
+    }
+
+    /// The names of every session that belongs to a tenant
+    pub fn list_sessions(&self, tenant: &str) -> Vec<String> {
+        self.tenants.get(tenant).map(|t| t.list_sessions()).unwrap_or_default()
+    }
+
     /// Find a registered client by name
     pub fn find_client(&self, identity: &IsolateIdentity) -> Result<IsolateChannel<ClientEvent>, SessionManagerError> {
         let client_runtime = self.registry.find(CLIENT)?;
@@ -64,10 +165,15 @@ impl SessionManagerInner {
             None => Err(SessionManagerError::NoMatchingClient)
         }
     }
+
+    /// The sessions for a tenant, created the first time the tenant registers something
+    fn tenant_mut(&mut self, tenant: &str) -> &mut TenantSessions {
+        self.tenants.entry(tenant.to_string()).or_insert_with(TenantSessions::new)
+    }
 }
 
 impl From<IsolateRegistryError> for SessionManagerError {
     fn from(_: IsolateRegistryError) -> Self {
         SessionManagerError::NoMatchingMaster
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,43 @@
+use crate::events::master_event::MasterEvent;
+use crate::model::master_metadata::MasterMetadata;
+use rust_isolate::IsolateChannel;
+use rust_isolate::IsolateIdentity;
+
+/// A registered session, and how full it is, for matchmaking
+pub struct SessionRecord {
+    pub identity: IsolateIdentity,
+    pub metadata: MasterMetadata,
+    pub taken: u32,
+    pub join_code: Option<String>,
+}
+
+impl SessionRecord {
+    pub fn new(identity: IsolateIdentity, metadata: MasterMetadata) -> SessionRecord {
+        SessionRecord {
+            identity,
+            metadata,
+            taken: 0,
+            join_code: None,
+        }
+    }
+
+    /// How many more players could join this session
+    pub fn free_slots(&self) -> u32 {
+        self.metadata.max_clients.saturating_sub(self.taken)
+    }
+}
+
+/// A masterless peer session; the isolate running it records its identity once it starts
+pub struct PeerSessionRecord {
+    pub channel: IsolateChannel<MasterEvent>,
+    pub identity: Option<IsolateIdentity>,
+}
+
+impl PeerSessionRecord {
+    pub fn new(channel: IsolateChannel<MasterEvent>) -> PeerSessionRecord {
+        PeerSessionRecord {
+            channel,
+            identity: None,
+        }
+    }
+}
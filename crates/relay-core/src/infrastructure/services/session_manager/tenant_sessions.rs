@@ -0,0 +1,123 @@
+use crate::infrastructure::services::session_manager::join_code::generate_join_code;
+use crate::infrastructure::services::session_manager::join_code::normalize_join_code;
+use crate::infrastructure::services::session_manager::session_record::PeerSessionRecord;
+use crate::infrastructure::services::session_manager::session_record::SessionRecord;
+use crate::infrastructure::services::SessionManagerError;
+use crate::model::master_metadata::MasterMetadata;
+use crate::model::session_filter::SessionFilter;
+use rust_isolate::IsolateIdentity;
+use std::collections::HashMap;
+
+/// The sessions that belong to one tenant; names and join codes only need to be unique in here
+pub struct TenantSessions {
+    pub sessions: HashMap<String, SessionRecord>,
+    pub peer_sessions: HashMap<String, PeerSessionRecord>,
+    join_codes: HashMap<String, String>,
+}
+
+impl TenantSessions {
+    pub fn new() -> TenantSessions {
+        TenantSessions {
+            sessions: HashMap::new(),
+            peer_sessions: HashMap::new(),
+            join_codes: HashMap::new(),
+        }
+    }
+
+    /// Is this name taken by any session?
+    pub fn contains(&self, name: &str) -> bool {
+        self.sessions.contains_key(name) || self.peer_sessions.contains_key(name)
+    }
+
+    /// Are there any sessions left?
+    pub fn contains_any(&self) -> bool {
+        !self.sessions.is_empty() || !self.peer_sessions.is_empty()
+    }
+
+    /// Register a new session, if there isn't a conflict in the requested name
+    pub fn register_session(&mut self, identity: &IsolateIdentity, metadata: &MasterMetadata) -> Result<Option<String>, SessionManagerError> {
+        let name = &metadata.master_id;
+        if self.contains(name) {
+            return Err(SessionManagerError::NameAlreadyInUse);
+        }
+        if self.is_join_code(name) {
+            return Err(SessionManagerError::NameMatchesJoinCode);
+        }
+
+        let mut record = SessionRecord::new(identity.clone(), metadata.clone());
+        if metadata.generate_join_code {
+            let code = self.unused_join_code();
+            self.join_codes.insert(code.clone(), name.to_string());
+            record.join_code = Some(code);
+        }
+
+        let join_code = record.join_code.clone();
+        self.sessions.insert(name.to_string(), record);
+        Ok(join_code)
+    }
+
+    /// Is this name the join code of a live session? Codes are matched regardless of case, so names are too
+    pub fn is_join_code(&self, name: &str) -> bool {
+        self.join_codes.contains_key(&normalize_join_code(name))
+    }
+
+    /// Generate join codes until we find one that isn't taken by another session
+    fn unused_join_code(&self) -> String {
+        loop {
+            let code = generate_join_code();
+            if !self.join_codes.contains_key(&code) && !self.contains(&code) {
+                return code;
+            }
+        }
+    }
+
+    /// Remove an existing session
+    pub fn remove_session(&mut self, name: &str) -> Result<(), SessionManagerError> {
+        if self.peer_sessions.remove(name).is_some() {
+            return Ok(());
+        }
+        match self.sessions.remove(name) {
+            Some(record) => {
+                // Release the join code, so another session can use it
+                if let Some(code) = record.join_code {
+                    self.join_codes.remove(&code);
+                }
+                Ok(())
+            }
+            None => Err(SessionManagerError::NoMatchingMaster)
+        }
+    }
+
+    /// Remove a peer session, but only if it is still the one run by this isolate; a session that replaced it is left alone
+    pub fn remove_peer_session(&mut self, name: &str, identity: &IsolateIdentity) -> bool {
+        match self.peer_sessions.get(name) {
+            Some(record) if record.identity.as_ref() == Some(identity) => {
+                self.peer_sessions.remove(name);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Find a registered session, by name or by join code
+    pub fn find_session(&self, name: &str) -> Option<&SessionRecord> {
+        self.sessions.get(name).or_else(|| {
+            self.join_codes.get(&normalize_join_code(name)).and_then(|n| self.sessions.get(n))
+        })
+    }
+
+    /// Find the name of the fullest session that matches the filter and still has room; peer sessions are never matched
+    pub fn find_matching_session(&self, filter: &SessionFilter) -> Option<&String> {
+        self.sessions.iter()
+            .filter(|(_, record)| filter.matches(&record.metadata, record.free_slots()))
+            .min_by(|(a_name, a), (b_name, b)| a.free_slots().cmp(&b.free_slots()).then(a_name.cmp(b_name)))
+            .map(|(name, _)| name)
+    }
+
+    /// The names of every session, sorted
+    pub fn list_sessions(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.sessions.keys().chain(self.peer_sessions.keys()).cloned().collect();
+        names.sort();
+        names
+    }
+}
@@ -1,2 +1,3 @@
 pub mod master;
-pub mod client;
\ No newline at end of file
+pub mod client;
+pub(crate) mod transaction_window;
@@ -10,6 +10,8 @@ use crate::infrastructure::services::SessionManager;
 use crate::isolates::client::client_state::ClientState;
 use crate::isolates::client::ClientEventDispatch::DispatchExternal;
 use crate::isolates::client::ClientEventDispatch::DispatchInternal;
+use crate::isolates::client::ClientEventDispatch::DispatchToSession;
+use crate::isolates::transaction_window::{TransactionSeen, TransactionWindow};
 use crate::{CLIENT, NO_IDENTITY};
 use relay_logging::RelayEventLogger;
 use rust_isolate::Isolate;
@@ -17,17 +19,22 @@ use rust_isolate::IsolateChannel;
 use rust_isolate::IsolateIdentity;
 use std::error::Error;
 
+/// How many recent transactions a client remembers the results of, to answer retried messages
+const CLIENT_TRANSACTION_WINDOW: usize = 256;
+
 #[derive(Debug)]
 pub enum ClientEventDispatch {
     DispatchNone,
     DispatchExternal(ClientExternalEvent),
     DispatchInternal(MasterInternalEvent),
+    DispatchToSession(String, MasterInternalEvent),
 }
 
 pub struct ClientIsolate {
     logger: RelayEventLogger,
     external: Option<IsolateChannel<ClientEvent>>,
     state: ClientState,
+    transactions: TransactionWindow<ClientExternalEvent>,
 }
 
 impl ClientIsolate {
@@ -36,6 +43,7 @@ impl ClientIsolate {
             state: ClientState::new(IsolateIdentity::new(), manager),
             logger: RelayEventLogger::new(NO_IDENTITY, CLIENT),
             external: None,
+            transactions: TransactionWindow::new(CLIENT_TRANSACTION_WINDOW),
         }
     }
 
@@ -48,11 +56,17 @@ impl ClientIsolate {
             state: self.state.instance(identity),
             logger: RelayEventLogger::new(&identity.to_string(), CLIENT),
             external: Some(channel.clone()),
+            transactions: TransactionWindow::new(CLIENT_TRANSACTION_WINDOW),
         }
     }
 
     pub fn dispatch(&mut self, event: ClientEvent) -> Result<(), ()> {
         self.logger.incoming_event(&event);
+        if let ClientEvent::External(e) = &event {
+            if self.is_duplicate(e) {
+                return Ok(());
+            }
+        }
         match event {
             ClientEvent::External(e) => match e {
                 ClientExternalEvent::InitializeClient {
@@ -65,15 +79,92 @@ impl ClientIsolate {
                 ClientExternalEvent::Join {
                     transaction_id,
                     session_id,
+                    role,
+                    waitlist,
+                } => {
+                    let response = self.state.external_join(transaction_id, &session_id, role, waitlist);
+                    self.send(response);
+                }
+                ClientExternalEvent::JoinAny {
+                    transaction_id,
+                    filter,
+                    role,
+                } => {
+                    let response = self.state.external_join_any(transaction_id, &filter, role);
+                    self.send(response);
+                }
+                ClientExternalEvent::JoinPeerSession {
+                    transaction_id,
+                    session_id,
+                } => {
+                    let response = self
+                        .state
+                        .external_join_peer_session(transaction_id, &session_id);
+                    self.send(response);
+                }
+                ClientExternalEvent::Resume {
+                    transaction_id,
+                    session_id,
+                    resume_token,
                 } => {
-                    let response = self.state.external_join(transaction_id, &session_id);
+                    let response = self.state.external_resume(transaction_id, &session_id, resume_token);
+                    self.send(response);
+                }
+                ClientExternalEvent::Leave { transaction_id } => {
+                    let response = self.state.external_leave(transaction_id);
                     self.send(response);
                 }
                 ClientExternalEvent::MessageFromClient {
                     transaction_id,
                     data,
+                    payload,
+                    ack,
+                    unreliable,
                 } => {
-                    let response = self.state.external_message(transaction_id, data);
+                    let response = self.state.external_message(transaction_id, data, payload, ack, unreliable);
+                    self.send(response);
+                }
+                ClientExternalEvent::AckMessage {
+                    transaction_id,
+                    sequence,
+                } => {
+                    let response = self.state.external_ack_message(transaction_id, sequence);
+                    self.send_many(response);
+                }
+                ClientExternalEvent::MessageToPeer {
+                    transaction_id,
+                    client_id,
+                    data,
+                } => {
+                    let response =
+                        self.state
+                            .external_message_to_peer(transaction_id, client_id, data);
+                    self.send(response);
+                }
+                ClientExternalEvent::BroadcastToPeers {
+                    transaction_id,
+                    data,
+                } => {
+                    let response = self.state.external_broadcast_to_peers(transaction_id, data);
+                    self.send(response);
+                }
+                ClientExternalEvent::ListSessions { transaction_id } => {
+                    let response = self.state.external_list_sessions(transaction_id);
+                    self.send_many(response);
+                }
+                ClientExternalEvent::RequestStateSnapshot { transaction_id } => {
+                    let response = self.state.external_request_state_snapshot(transaction_id);
+                    self.send(response);
+                }
+                ClientExternalEvent::RequestToMaster {
+                    transaction_id,
+                    request_id,
+                    data,
+                    timeout_ms,
+                } => {
+                    let response =
+                        self.state
+                            .external_request_to_master(transaction_id, request_id, data, timeout_ms);
                     self.send(response);
                 }
 
@@ -93,7 +184,21 @@ impl ClientIsolate {
                     let response =
                         self.state
                             .internal_join_response(transaction_id, success, error);
-                    self.send(response);
+                    self.send_many(response);
+                }
+                ClientInternalEvent::TransferToSession {
+                    transaction_id,
+                    session_id,
+                    source_session_id,
+                    role,
+                } => {
+                    let response = self.state.internal_transfer(
+                        transaction_id,
+                        session_id,
+                        source_session_id,
+                        role,
+                    );
+                    self.send_many(response);
                 }
                 ClientInternalEvent::MessageFromClientResponse {
                     transaction_id,
@@ -105,8 +210,18 @@ impl ClientIsolate {
                             .internal_message_response(transaction_id, success, error);
                     self.send(response);
                 }
-                ClientInternalEvent::MessageFromMaster { data } => {
-                    let response = self.state.internal_message_from_master(data);
+                ClientInternalEvent::LeaveResponse {
+                    transaction_id,
+                    success,
+                    error,
+                } => {
+                    let response =
+                        self.state
+                            .internal_leave_response(transaction_id, success, error);
+                    self.send(response);
+                }
+                ClientInternalEvent::MessageFromMaster { data, payload, ack } => {
+                    let response = self.state.internal_message_from_master(data, payload, ack);
                     self.send(response);
                 }
                 ClientInternalEvent::MasterDisconnected { reason } => {
@@ -116,9 +231,57 @@ impl ClientIsolate {
                         .warn(format!("Disconnected: Master disconnected: {}", reason));
                     return Err(());
                 }
+                ClientInternalEvent::Kicked { reason } => {
+                    let response = self.state.internal_kicked(&reason);
+                    self.send(response);
+                    self.logger.info(format!("Kicked by master: {}", reason));
+                }
+                ClientInternalEvent::MessageFromPeer { client_id, data } => {
+                    let response = self.state.internal_message_from_peer(client_id, data);
+                    self.send(response);
+                }
+                ClientInternalEvent::PeerJoined { client_id, name } => {
+                    let response = self.state.internal_peer_joined(client_id, name);
+                    self.send(response);
+                }
+                ClientInternalEvent::PeerLeft { client_id, reason } => {
+                    let response = self.state.internal_peer_left(client_id, reason);
+                    self.send(response);
+                }
+                ClientInternalEvent::GroupMembership { groups } => {
+                    let response = self.state.internal_group_membership(groups);
+                    self.send(response);
+                }
+                ClientInternalEvent::WaitlistPosition { transaction_id, position } => {
+                    let response = self.state.internal_waitlist_position(transaction_id, position);
+                    self.send(response);
+                }
+                ClientInternalEvent::StatePatch { format, patch, version } => {
+                    let response = self.state.internal_state_patch(format, patch, version);
+                    self.send(response);
+                }
+                ClientInternalEvent::ResumeToken { token } => {
+                    let response = self.state.internal_resume_token(token);
+                    self.send(response);
+                }
+                ClientInternalEvent::Resumed { sequence } => {
+                    let response = self.state.internal_resumed(sequence);
+                    self.send(response);
+                }
+                ClientInternalEvent::StateSnapshot { state, version } => {
+                    let response = self.state.internal_state_snapshot(state, version);
+                    self.send(response);
+                }
+                ClientInternalEvent::ResponseToClient { request_id, data, error } => {
+                    let response = self.state.internal_response_to_client(request_id, data, error);
+                    self.send(response);
+                }
             },
             ClientEvent::Control(e) => match e {
                 ClientControlEvent::Halt => return Err(()),
+                ClientControlEvent::Authorized { auth_key, tenant } => {
+                    self.state.control_authorized(auth_key, tenant);
+                }
                 ClientControlEvent::ClientDisconnected { reason } => {
                     let response = self.state.external_disconnect(&reason);
                     self.send(response);
@@ -144,16 +307,45 @@ impl ClientIsolate {
         }
     }
 
+    /// A retried message is answered with the result it got the first time instead of being delivered again
+    fn is_duplicate(&mut self, event: &ClientExternalEvent) -> bool {
+        let transaction_id = match event.transaction_id() {
+            Some(transaction_id) if event.delivers_message() => transaction_id,
+            _ => return false,
+        };
+        match self.transactions.check(transaction_id) {
+            TransactionSeen::New => false,
+            TransactionSeen::Pending => {
+                self.logger.info(format!("Discarded duplicate of pending transaction {}", transaction_id));
+                true
+            }
+            TransactionSeen::Completed(result) => {
+                self.send_external(result);
+                true
+            }
+        }
+    }
+
+    /// Send some arbitrary set of events to the appropriate destination and log them
+    fn send_many(&mut self, dispatch: Vec<ClientEventDispatch>) {
+        dispatch.into_iter().for_each(|i| self.send(i));
+    }
+
     /// Send some arbitrary event to the appropriate destination and log it
-    fn send(&self, dispatch: ClientEventDispatch) {
+    fn send(&mut self, dispatch: ClientEventDispatch) {
         match dispatch {
             ClientEventDispatch::DispatchNone => {}
             DispatchExternal(ext) => self.send_external(ext),
             DispatchInternal(event) => self.send_internal(event),
+            DispatchToSession(session_id, event) => self.send_to_session(&session_id, event),
         }
     }
 
-    fn send_external(&self, event: ClientExternalEvent) {
+    fn send_external(&mut self, event: ClientExternalEvent) {
+        if let ClientExternalEvent::TransactionResult { transaction_id, .. } = &event {
+            self.transactions.complete(transaction_id, &event);
+        }
+
         match self.external.as_ref() {
             Some(channel) => {
                 let output = ClientEvent::External(event);
@@ -184,6 +376,29 @@ impl ClientIsolate {
             }
         };
     }
+
+    /// Send an event to a master we are not attached to, for example to report on a transfer
+    fn send_to_session(&self, session_id: &str, event: MasterInternalEvent) {
+        match self.state.find_session(session_id) {
+            Some(channel) => {
+                let output = MasterEvent::Internal(event);
+                self.logger.outgoing_event(&output);
+                if let Err(e) = channel.sender.send(output) {
+                    self.logger.warn(format!(
+                        "Failed to send event to session {}: {}",
+                        session_id,
+                        e.description()
+                    ));
+                }
+            }
+            None => {
+                self.logger.warn(format!(
+                    "Unable to send event to unknown session {}: {:?}",
+                    session_id, event
+                ));
+            }
+        }
+    }
 }
 
 impl Isolate<ClientEvent> for ClientIsolate {
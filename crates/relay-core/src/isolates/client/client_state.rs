@@ -1,4 +1,11 @@
 use crate::infrastructure::services::SessionManager;
+use crate::infrastructure::services::SessionManagerError;
+use crate::model::client_role::ClientRole;
+use crate::model::patch_format::PatchFormat;
+use crate::model::session_filter::SessionFilter;
+use crate::DEFAULT_TENANT;
+use serde_json::value::RawValue;
+use serde_json::Value;
 use rust_isolate::IsolateIdentity;
 use crate::model::external_error::ErrorCode;
 use crate::model::external_error::ExternalError;
@@ -8,15 +15,40 @@ use crate::isolates::client::ClientEventDispatch::DispatchExternal;
 use crate::events::client_event::ClientExternalEvent;
 use crate::events::master_event::MasterEvent;
 use rust_isolate::IsolateChannel;
+use std::collections::HashMap;
 use crate::events::master_event::MasterInternalEvent;
 use crate::isolates::client::ClientEventDispatch::DispatchInternal;
+use crate::isolates::client::ClientEventDispatch::DispatchToSession;
+use std::time::Duration;
+use std::time::Instant;
+
+/// How long a message from the master waits for the client to ack it; the master gives up after the same time
+const MESSAGE_ACK_TIMEOUT_MS: u64 = 30000;
+
+/// A transfer to another session that the source master is waiting to hear about
+struct PendingTransfer {
+    transaction_id: String,
+    session_id: String,
+    source_session_id: String,
+}
+
+/// A message from the master waiting for the client to acknowledge it
+struct PendingAck {
+    transaction_id: String,
+    received: Instant,
+}
 
 pub struct ClientState {
-    name: String,
+    metadata: ClientMetadata,
+    auth_key: Option<String>,
+    tenant: String,
     identity: IsolateIdentity,
     active: bool,
     connected: bool,
     master: Option<IsolateChannel<MasterEvent>>,
+    transfer: Option<PendingTransfer>,
+    sequence: u64,
+    acks: HashMap<u64, PendingAck>,
     manager: SessionManager,
 }
 
@@ -25,8 +57,13 @@ impl ClientState {
         ClientState {
             manager,
             identity,
-            name: String::new(),
+            metadata: ClientMetadata { name: String::new() },
+            auth_key: None,
+            tenant: DEFAULT_TENANT.to_string(),
             master: None,
+            transfer: None,
+            sequence: 0,
+            acks: HashMap::new(),
             active: false,
             connected: false,
         }
@@ -37,15 +74,26 @@ impl ClientState {
             manager: self.manager.clone(),
             identity,
             master: None,
-            name: String::new(),
+            transfer: None,
+            sequence: 0,
+            acks: HashMap::new(),
+            metadata: ClientMetadata { name: String::new() },
+            auth_key: None,
+            tenant: DEFAULT_TENANT.to_string(),
             active: false,
             connected: false,
         }
     }
 
+    /// Remember the key this connection authorized with, and the tenant it can see sessions in
+    pub fn control_authorized(&mut self, auth_key: String, tenant: String) {
+        self.auth_key = Some(auth_key);
+        self.tenant = tenant;
+    }
+
     /// External initialize
     pub fn external_initialize(&mut self, transaction_id: String, metadata: ClientMetadata) -> ClientEventDispatch {
-        self.name = metadata.name.clone();
+        self.metadata = metadata;
         self.active = true;
         DispatchExternal(ClientExternalEvent::TransactionResult {
             transaction_id,
@@ -55,16 +103,79 @@ impl ClientState {
     }
 
     /// External request to join a master
-    pub fn external_join(&mut self, transaction_id: String, master_id: &str) -> ClientEventDispatch {
+    pub fn external_join(&mut self, transaction_id: String, master_id: &str, role: ClientRole, waitlist: bool) -> ClientEventDispatch {
         // First, lets see if we can lookup the session
-        match self.manager.find_master(&master_id) {
+        let session = self.manager.find_master(&self.tenant, &master_id);
+        self.join_session(transaction_id, session, role, waitlist)
+    }
+
+    /// External request to join any session that matches the filter
+    pub fn external_join_any(&mut self, transaction_id: String, filter: &SessionFilter, role: ClientRole) -> ClientEventDispatch {
+        let session = self.manager.find_matching_session(&self.tenant, filter);
+        self.join_session(transaction_id, session, role, false)
+    }
+
+    /// External request to join a peer session, which is created if it doesn't exist.
+    /// The session manager sends the join itself, so it can't reach a session that is closing.
+    pub fn external_join_peer_session(&mut self, transaction_id: String, session_id: &str) -> ClientEventDispatch {
+        if self.master.is_some() {
+            return DispatchExternal(ClientExternalEvent::TransactionResult {
+                transaction_id,
+                success: false,
+                error: Some(ExternalError::from(ErrorCode::AlreadyInSession)),
+            });
+        }
+
+        let join = self.join_request(transaction_id.clone(), ClientRole::Player, false);
+        match self.manager.join_peer_session(&self.tenant, session_id, join) {
             Ok(session_ref) => {
-                // If we got the master, update to refer to it, and pass the request to join to the master
                 self.master = Some(session_ref);
-                DispatchInternal(MasterInternalEvent::ClientJoinRequest {
+                ClientEventDispatch::DispatchNone
+            }
+            Err(e) => {
+                DispatchExternal(ClientExternalEvent::TransactionResult {
+                    transaction_id,
+                    success: false,
+                    error: Some(ExternalError::from(e)),
+                })
+            }
+        }
+    }
+
+    /// External request for the names of the sessions in this client's tenant
+    pub fn external_list_sessions(&self, transaction_id: String) -> Vec<ClientEventDispatch> {
+        match self.manager.list_sessions(&self.tenant) {
+            Ok(sessions) => vec!(
+                DispatchExternal(ClientExternalEvent::SessionList { transaction_id: transaction_id.clone(), sessions }),
+                DispatchExternal(ClientExternalEvent::TransactionResult { transaction_id, success: true, error: None }),
+            ),
+            Err(e) => vec!(DispatchExternal(ClientExternalEvent::TransactionResult {
+                transaction_id,
+                success: false,
+                error: Some(ExternalError::from(e)),
+            })),
+        }
+    }
+
+    /// External request to take back a slot in a session after the connection dropped
+    pub fn external_resume(&mut self, transaction_id: String, session_id: &str, resume_token: String) -> ClientEventDispatch {
+        if self.master.is_some() {
+            return DispatchExternal(ClientExternalEvent::TransactionResult {
+                transaction_id,
+                success: false,
+                error: Some(ExternalError::from(ErrorCode::AlreadyInSession)),
+            });
+        }
+
+        match self.manager.find_master(&self.tenant, session_id) {
+            Ok(session_ref) => {
+                self.master = Some(session_ref);
+                DispatchInternal(MasterInternalEvent::ClientResumeRequest {
                     transaction_id,
-                    client_id: self.name.clone(),
                     identity: self.identity.clone(),
+                    name: self.metadata.name.clone(),
+                    auth_key: self.auth_key.clone(),
+                    resume_token,
                 })
             }
             Err(e) => {
@@ -77,9 +188,52 @@ impl ClientState {
         }
     }
 
+    /// Ask the master of a session we looked up to let us join
+    fn join_session(&mut self, transaction_id: String, session: Result<IsolateChannel<MasterEvent>, SessionManagerError>, role: ClientRole, waitlist: bool) -> ClientEventDispatch {
+        // Only one session at a time; joining another one means leaving this one first
+        if self.master.is_some() {
+            return DispatchExternal(ClientExternalEvent::TransactionResult {
+                transaction_id,
+                success: false,
+                error: Some(ExternalError::from(ErrorCode::AlreadyInSession)),
+            });
+        }
+
+        match session {
+            Ok(session_ref) => {
+                // If we got the master, update to refer to it, and pass the request to join to the master
+                self.master = Some(session_ref);
+                DispatchInternal(self.join_request(transaction_id, role, waitlist))
+            }
+            Err(e) => {
+                DispatchExternal(ClientExternalEvent::TransactionResult {
+                    transaction_id,
+                    success: false,
+                    error: Some(ExternalError::from(e)),
+                })
+            }
+        }
+    }
+
+    /// Build a request to join whatever master we currently refer to
+    fn join_request(&self, transaction_id: String, role: ClientRole, waitlist: bool) -> MasterInternalEvent {
+        MasterInternalEvent::ClientJoinRequest {
+            transaction_id,
+            client_id: self.metadata.name.clone(),
+            identity: self.identity.clone(),
+            metadata: self.metadata.clone(),
+            auth_key: self.auth_key.clone(),
+            role,
+            waitlist,
+        }
+    }
+
     /// External new message from the client
-    pub fn external_message(&self, transaction_id: String, data: String) -> ClientEventDispatch {
+    pub fn external_message(&self, transaction_id: String, data: String, payload: Option<Box<RawValue>>, ack: bool, unreliable: bool) -> ClientEventDispatch {
         if !self.connected {
+            if unreliable {
+                return ClientEventDispatch::DispatchNone;
+            }
             return DispatchExternal(ClientExternalEvent::TransactionResult {
                 transaction_id,
                 success: false,
@@ -91,6 +245,121 @@ impl ClientState {
             transaction_id,
             client_id: self.identity.clone(),
             data,
+            payload,
+            ack: ack && !unreliable,
+            unreliable,
+        })
+    }
+
+    /// External confirmation that a message from the master was processed
+    pub fn external_ack_message(&mut self, transaction_id: String, sequence: u64) -> Vec<ClientEventDispatch> {
+        self.expire_acks();
+        let master_transaction_id = match self.acks.remove(&sequence) {
+            Some(pending) if self.connected => pending.transaction_id,
+            _ => {
+                return vec!(DispatchExternal(ClientExternalEvent::TransactionResult {
+                    transaction_id,
+                    success: false,
+                    error: Some(ExternalError::from(ErrorCode::NoMatchingMessage)),
+                }));
+            }
+        };
+        vec!(
+            DispatchInternal(MasterInternalEvent::MessageAck {
+                transaction_id: master_transaction_id,
+                identity: self.identity.clone(),
+            }),
+            DispatchExternal(ClientExternalEvent::TransactionResult {
+                transaction_id,
+                success: true,
+                error: None,
+            })
+        )
+    }
+
+    /// External new message for another client
+    pub fn external_message_to_peer(&self, transaction_id: String, client_id: String, data: String) -> ClientEventDispatch {
+        if !self.connected {
+            return DispatchExternal(ClientExternalEvent::TransactionResult {
+                transaction_id,
+                success: false,
+                error: Some(ExternalError::from(ErrorCode::ClientNotConnected)),
+            });
+        }
+
+        DispatchInternal(MasterInternalEvent::MessageToPeer {
+            transaction_id,
+            sender: self.identity.clone(),
+            client_id,
+            data,
+        })
+    }
+
+    /// External new message for every other client
+    pub fn external_broadcast_to_peers(&self, transaction_id: String, data: String) -> ClientEventDispatch {
+        if !self.connected {
+            return DispatchExternal(ClientExternalEvent::TransactionResult {
+                transaction_id,
+                success: false,
+                error: Some(ExternalError::from(ErrorCode::ClientNotConnected)),
+            });
+        }
+
+        DispatchInternal(MasterInternalEvent::BroadcastToPeers {
+            transaction_id,
+            sender: self.identity.clone(),
+            data,
+        })
+    }
+
+    /// External request for the whole shared session state
+    pub fn external_request_state_snapshot(&self, transaction_id: String) -> ClientEventDispatch {
+        if !self.connected {
+            return DispatchExternal(ClientExternalEvent::TransactionResult {
+                transaction_id,
+                success: false,
+                error: Some(ExternalError::from(ErrorCode::ClientNotConnected)),
+            });
+        }
+
+        DispatchInternal(MasterInternalEvent::StateSnapshotRequest {
+            transaction_id,
+            identity: self.identity.clone(),
+        })
+    }
+
+    /// External request to the master that expects an answer back
+    pub fn external_request_to_master(&self, transaction_id: String, request_id: String, data: String, timeout_ms: Option<u64>) -> ClientEventDispatch {
+        if !self.connected {
+            return DispatchExternal(ClientExternalEvent::TransactionResult {
+                transaction_id,
+                success: false,
+                error: Some(ExternalError::from(ErrorCode::ClientNotConnected)),
+            });
+        }
+
+        DispatchInternal(MasterInternalEvent::RequestFromClient {
+            transaction_id,
+            identity: self.identity.clone(),
+            request_id,
+            data,
+            timeout_ms,
+        })
+    }
+
+    /// External request to leave the current session
+    pub fn external_leave(&self, transaction_id: String) -> ClientEventDispatch {
+        if self.master.is_none() {
+            return DispatchExternal(ClientExternalEvent::TransactionResult {
+                transaction_id,
+                success: false,
+                error: Some(ExternalError::from(ErrorCode::ClientNotConnected)),
+            });
+        }
+
+        DispatchInternal(MasterInternalEvent::ClientLeave {
+            transaction_id,
+            identity: self.identity.clone(),
         })
     }
 
@@ -99,25 +368,87 @@ impl ClientState {
         DispatchInternal(MasterInternalEvent::ClientDisconnected {
             identity: self.identity.clone(),
             reason: reason.to_string(),
+            sequence: self.sequence,
         })
     }
 
     /// Response internally from a join request
-    pub fn internal_join_response(&mut self, transaction_id: String, success: bool, error: Option<ExternalError>) -> ClientEventDispatch {
+    pub fn internal_join_response(&mut self, transaction_id: String, success: bool, error: Option<ExternalError>) -> Vec<ClientEventDispatch> {
+        if success {
+            self.connected = true;
+        } else {
+            self.master = None;
+        }
+
+        // If this join was part of a transfer, the source master is waiting for the result, not the client
+        if self.transfer.as_ref().map(|t| t.transaction_id == transaction_id).unwrap_or(false) {
+            let transfer = self.transfer.take().unwrap();
+            return self.transfer_complete(transfer, success, error);
+        }
+
         if !success {
-            return DispatchExternal(ClientExternalEvent::TransactionResult {
+            return vec!(DispatchExternal(ClientExternalEvent::TransactionResult {
                 transaction_id,
                 success: false,
                 error,
-            });
+            }));
         }
 
-        self.connected = true;
-        return DispatchExternal(ClientExternalEvent::TransactionResult {
+        vec!(DispatchExternal(ClientExternalEvent::TransactionResult {
             transaction_id,
             success: true,
             error: None,
-        });
+        }))
+    }
+
+    /// The master moved us to another session; detach from it and join the new one
+    pub fn internal_transfer(&mut self, transaction_id: String, session_id: String, source_session_id: String, role: ClientRole) -> Vec<ClientEventDispatch> {
+        self.master = None;
+        self.connected = false;
+        let transfer = PendingTransfer { transaction_id: transaction_id.clone(), session_id, source_session_id };
+        match self.manager.find_master(&self.tenant, &transfer.session_id) {
+            Ok(session_ref) => {
+                self.master = Some(session_ref);
+                self.transfer = Some(transfer);
+                vec!(DispatchInternal(self.join_request(transaction_id, role, false)))
+            }
+            Err(e) => self.transfer_complete(transfer, false, Some(ExternalError::from(e)))
+        }
+    }
+
+    /// Forget messages the master has stopped waiting for an ack on
+    fn expire_acks(&mut self) {
+        let timeout = Duration::from_millis(MESSAGE_ACK_TIMEOUT_MS);
+        self.acks.retain(|_, pending| pending.received.elapsed() <= timeout);
+    }
+
+    /// Tell both the client and the source master how a transfer went
+    fn transfer_complete(&self, transfer: PendingTransfer, success: bool, error: Option<ExternalError>) -> Vec<ClientEventDispatch> {
+        vec!(
+            DispatchExternal(ClientExternalEvent::SessionTransferred {
+                session_id: transfer.session_id,
+                success,
+                error: error.clone(),
+            }),
+            DispatchToSession(transfer.source_session_id, MasterInternalEvent::TransferResult {
+                transaction_id: transfer.transaction_id,
+                success,
+                error,
+            })
+        )
+    }
+
+    /// Response internally from a leave request; on success we are free to join another session
+    pub fn internal_leave_response(&mut self, transaction_id: String, success: bool, error: Option<ExternalError>) -> ClientEventDispatch {
+        if success {
+            self.connected = false;
+            self.master = None;
+        }
+        DispatchExternal(ClientExternalEvent::TransactionResult {
+            transaction_id,
+            success,
+            error,
+        })
     }
 
     /// Response internally from a message to master request
@@ -130,9 +461,18 @@ impl ClientState {
     }
 
     /// Forward a message from the master to the external connection
-    pub fn internal_message_from_master(&mut self, data: String) -> ClientEventDispatch {
+    pub fn internal_message_from_master(&mut self, data: String, payload: Option<Box<RawValue>>, ack: Option<String>) -> ClientEventDispatch {
+        self.expire_acks();
+        self.sequence += 1;
+        let wants_ack = ack.is_some();
+        if let Some(transaction_id) = ack {
+            self.acks.insert(self.sequence, PendingAck { transaction_id, received: Instant::now() });
+        }
         return DispatchExternal(ClientExternalEvent::MessageToClient {
             data,
+            payload,
+            sequence: self.sequence,
+            ack: wants_ack,
         });
     }
 
@@ -143,6 +483,89 @@ impl ClientState {
         })
     }
 
+    /// The master removed us from the session; detach from it, but stay alive to join another
+    pub fn internal_kicked(&mut self, reason: &str) -> ClientEventDispatch {
+        self.connected = false;
+        self.master = None;
+        DispatchExternal(ClientExternalEvent::Kicked {
+            reason: reason.to_string()
+        })
+    }
+
+    /// Forward a message from another client to the external connection
+    pub fn internal_message_from_peer(&mut self, client_id: String, data: String) -> ClientEventDispatch {
+        DispatchExternal(ClientExternalEvent::MessageFromPeer {
+            client_id,
+            data,
+        })
+    }
+
+    /// Another client joined our peer session
+    pub fn internal_peer_joined(&mut self, client_id: String, name: String) -> ClientEventDispatch {
+        DispatchExternal(ClientExternalEvent::PeerJoined {
+            client_id,
+            name,
+        })
+    }
+
+    /// Another client left our peer session
+    pub fn internal_peer_left(&mut self, client_id: String, reason: String) -> ClientEventDispatch {
+        DispatchExternal(ClientExternalEvent::PeerLeft {
+            client_id,
+            reason,
+        })
+    }
+
+    /// Forward a change in group membership to the external connection
+    pub fn internal_group_membership(&mut self, groups: Vec<String>) -> ClientEventDispatch {
+        DispatchExternal(ClientExternalEvent::GroupMembership {
+            groups
+        })
+    }
+
+    /// The session we asked to join is full; let the client know where it is in the queue
+    pub fn internal_waitlist_position(&mut self, transaction_id: String, position: u32) -> ClientEventDispatch {
+        DispatchExternal(ClientExternalEvent::WaitlistPosition {
+            transaction_id,
+            position,
+        })
+    }
+
+    /// The master changed the shared session state
+    pub fn internal_state_patch(&self, format: PatchFormat, patch: Value, version: u64) -> ClientEventDispatch {
+        DispatchExternal(ClientExternalEvent::StatePatch {
+            format,
+            patch,
+            version,
+        })
+    }
+
+    /// Pass on the token to resume the session with
+    pub fn internal_resume_token(&self, token: String) -> ClientEventDispatch {
+        DispatchExternal(ClientExternalEvent::ResumeToken { token })
+    }
+
+    /// Carry on numbering messages from where the connection that dropped left off
+    pub fn internal_resumed(&mut self, sequence: u64) -> ClientEventDispatch {
+        self.sequence = sequence;
+        ClientEventDispatch::DispatchNone
+    }
+
+    /// The whole shared session state
+    pub fn internal_state_snapshot(&self, state: Value, version: u64) -> ClientEventDispatch {
+        DispatchExternal(ClientExternalEvent::StateSnapshot { state, version })
+    }
+
+    /// The master answered one of our requests, or it timed out
+    pub fn internal_response_to_client(&self, request_id: String, data: String, error: Option<ExternalError>) -> ClientEventDispatch {
+        DispatchExternal(ClientExternalEvent::ResponseToClient { request_id, data, error })
+    }
+
+    /// Find the master for some other session by name
+    pub fn find_session(&self, session_id: &str) -> Option<IsolateChannel<MasterEvent>> {
+        self.manager.find_master(&self.tenant, session_id).ok()
+    }
+
     /// Return a reference to the master channel if we have one
     pub fn master_ref(&self) -> Option<&IsolateChannel<MasterEvent>> {
         self.master.as_ref()
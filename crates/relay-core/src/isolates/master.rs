@@ -1,3 +1,8 @@
+mod master_client;
+mod master_groups;
+mod master_history;
+mod master_offline;
+mod master_shared_state;
 mod master_state;
 
 use crate::events::client_event::ClientEvent;
@@ -10,24 +15,38 @@ use crate::infrastructure::services::SessionManager;
 use crate::isolates::master::master_state::MasterState;
 use crate::isolates::master::MasterEventDispatch::DispatchExternal;
 use crate::isolates::master::MasterEventDispatch::DispatchToClient;
+use crate::isolates::master::MasterEventDispatch::DispatchToSession;
+use crate::isolates::transaction_window::{TransactionSeen, TransactionWindow};
 use crate::{MASTER, NO_IDENTITY};
+use crossbeam::RecvTimeoutError;
 use relay_logging::RelayEventLogger;
 use rust_isolate::Isolate;
 use rust_isolate::IsolateChannel;
 use rust_isolate::IsolateIdentity;
 use std::error::Error;
+use std::time::Duration;
+use std::time::Instant;
+
+/// How often the master checks for expired requests, however busy it is
+const MASTER_POLL_INTERVAL_MS: u64 = 100;
+
+/// How many recent transactions the master remembers the results of, to answer retried messages
+const MASTER_TRANSACTION_WINDOW: usize = 1024;
 
 #[derive(Debug)]
 pub enum MasterEventDispatch {
     DispatchNone,
     DispatchExternal(MasterExternalEvent),
     DispatchToClient(IsolateIdentity, ClientInternalEvent),
+    DispatchToSession(String, MasterInternalEvent),
 }
 
 pub struct MasterIsolate {
     logger: RelayEventLogger,
     external: Option<IsolateChannel<MasterEvent>>,
     state: MasterState,
+    transactions: TransactionWindow<MasterExternalEvent>,
+    last_expire: Instant,
 }
 
 impl MasterIsolate {
@@ -37,6 +56,8 @@ impl MasterIsolate {
             state: MasterState::new(IsolateIdentity::new(), manager, logger.clone()),
             logger,
             external: None,
+            transactions: TransactionWindow::new(MASTER_TRANSACTION_WINDOW),
+            last_expire: Instant::now(),
         }
     }
 
@@ -50,11 +71,18 @@ impl MasterIsolate {
             state: self.state.instance(identity, logger.clone()),
             logger,
             external: Some(channel.clone()),
+            transactions: TransactionWindow::new(MASTER_TRANSACTION_WINDOW),
+            last_expire: Instant::now(),
         }
     }
 
     pub fn dispatch(&mut self, event: MasterEvent) -> Result<(), ()> {
         self.logger.incoming_event(&event);
+        if let MasterEvent::External(e) = &event {
+            if self.is_duplicate(e) {
+                return Ok(());
+            }
+        }
         match event {
             MasterEvent::External(e) => match e {
                 MasterExternalEvent::InitializeMaster {
@@ -62,16 +90,192 @@ impl MasterIsolate {
                     metadata,
                 } => {
                     let response = self.state.external_initialize(transaction_id, metadata);
+                    self.transactions.pending_at_least(self.state.longest_message_wait());
                     self.send(response);
                 }
                 MasterExternalEvent::MessageToClient {
                     client_id,
                     transaction_id,
                     data,
+                    payload,
+                    ack,
+                    unreliable,
+                } => {
+                    let response = match unreliable {
+                        true => self.state.external_unreliable_message_to_client(client_id, data, payload),
+                        false => self.state.external_message_to_client(client_id, transaction_id, data, payload, ack),
+                    };
+                    self.send_many(response);
+                }
+                MasterExternalEvent::AckMessage {
+                    transaction_id,
+                    client_id,
+                    sequence,
+                } => {
+                    let response =
+                        self.state
+                            .external_ack_message(transaction_id, client_id, sequence);
+                    self.send_many(response);
+                }
+                MasterExternalEvent::ResponseToClient {
+                    transaction_id,
+                    client_id,
+                    request_id,
+                    data,
+                } => {
+                    let response =
+                        self.state
+                            .external_response_to_client(transaction_id, client_id, request_id, data);
+                    self.send_many(response);
+                }
+                MasterExternalEvent::ApproveJoin {
+                    transaction_id,
+                    client_id,
+                } => {
+                    let response = self.state.external_approve_join(transaction_id, client_id);
+                    self.send_many(response);
+                }
+                MasterExternalEvent::RejectJoin {
+                    transaction_id,
+                    client_id,
+                    reason,
+                } => {
+                    let response = self
+                        .state
+                        .external_reject_join(transaction_id, client_id, reason);
+                    self.send_many(response);
+                }
+                MasterExternalEvent::KickClient {
+                    transaction_id,
+                    client_id,
+                    reason,
+                    ban,
+                    ban_key,
+                } => {
+                    let response = self
+                        .state
+                        .external_kick_client(transaction_id, client_id, reason, ban, ban_key);
+                    self.send_many(response);
+                }
+                MasterExternalEvent::BroadcastToClients {
+                    transaction_id,
+                    data,
+                    exclude,
+                    retain,
+                } => {
+                    let response =
+                        self.state
+                            .external_broadcast_to_clients(transaction_id, data, exclude, retain);
+                    self.send_many(response);
+                }
+                MasterExternalEvent::MulticastToClients {
+                    transaction_id,
+                    client_ids,
+                    data,
+                } => {
+                    let response =
+                        self.state
+                            .external_multicast_to_clients(transaction_id, client_ids, data);
+                    self.send_many(response);
+                }
+                MasterExternalEvent::TransferClient {
+                    transaction_id,
+                    client_id,
+                    target_session_id,
+                } => {
+                    let response = self.state.external_transfer_client(
+                        transaction_id,
+                        client_id,
+                        target_session_id,
+                    );
+                    self.send_many(response);
+                }
+                MasterExternalEvent::ReserveSeat {
+                    transaction_id,
+                    reservation,
+                    expires_ms,
+                } => {
+                    let response =
+                        self.state
+                            .external_reserve_seat(transaction_id, reservation, expires_ms);
+                    self.send(response);
+                }
+                MasterExternalEvent::CancelReservation {
+                    transaction_id,
+                    reservation,
+                } => {
+                    let response = self
+                        .state
+                        .external_cancel_reservation(transaction_id, reservation);
+                    self.send_many(response);
+                }
+                MasterExternalEvent::MessageToMaster {
+                    transaction_id,
+                    session_id,
+                    data,
+                } => {
+                    let response =
+                        self.state
+                            .external_message_to_master(transaction_id, session_id, data);
+                    self.send(response);
+                }
+                MasterExternalEvent::PatchState {
+                    transaction_id,
+                    format,
+                    patch,
+                } => {
+                    let response = self.state.external_patch_state(transaction_id, format, patch);
+                    self.send_many(response);
+                }
+                MasterExternalEvent::MessageToSpectators {
+                    transaction_id,
+                    data,
+                } => {
+                    let response = self.state.external_message_to_spectators(transaction_id, data);
+                    self.send_many(response);
+                }
+                MasterExternalEvent::CreateGroup {
+                    transaction_id,
+                    group,
+                } => {
+                    let response = self.state.external_create_group(transaction_id, group);
+                    self.send(response);
+                }
+                MasterExternalEvent::DeleteGroup {
+                    transaction_id,
+                    group,
+                } => {
+                    let response = self.state.external_delete_group(transaction_id, group);
+                    self.send_many(response);
+                }
+                MasterExternalEvent::AddToGroup {
+                    transaction_id,
+                    group,
+                    client_id,
+                } => {
+                    let response =
+                        self.state
+                            .external_add_to_group(transaction_id, group, client_id);
+                    self.send_many(response);
+                }
+                MasterExternalEvent::RemoveFromGroup {
+                    transaction_id,
+                    group,
+                    client_id,
                 } => {
                     let response =
                         self.state
-                            .external_message_to_client(client_id, transaction_id, data);
+                            .external_remove_from_group(transaction_id, group, client_id);
+                    self.send_many(response);
+                }
+                MasterExternalEvent::MessageToGroup {
+                    transaction_id,
+                    group,
+                    data,
+                } => {
+                    let response = self
+                        .state
+                        .external_message_to_group(transaction_id, group, data);
                     self.send_many(response);
                 }
 
@@ -87,28 +291,136 @@ impl MasterIsolate {
                     transaction_id,
                     client_id,
                     identity,
+                    metadata,
+                    auth_key,
+                    role,
+                    waitlist,
                 } => {
                     let response = self.state.internal_client_join_request(
                         &client_id,
                         transaction_id,
                         identity,
+                        metadata,
+                        auth_key,
+                        role,
+                        waitlist,
                     );
                     self.send_many(response);
                 }
+                MasterInternalEvent::ClientResumeRequest {
+                    transaction_id,
+                    identity,
+                    name,
+                    auth_key,
+                    resume_token,
+                } => {
+                    let response = self.state.internal_client_resume_request(transaction_id, identity, name, auth_key, &resume_token);
+                    self.send_many(response);
+                }
                 MasterInternalEvent::MessageFromClient {
                     client_id,
                     transaction_id,
                     data,
+                    payload,
+                    ack,
+                    unreliable,
+                } => {
+                    let response = match unreliable {
+                        true => self.state.internal_unreliable_client_message(client_id, data, payload),
+                        false => self.state.internal_client_message(client_id, transaction_id, data, payload, ack),
+                    };
+                    self.send_many(response);
+                }
+                MasterInternalEvent::MessageAck {
+                    transaction_id,
+                    identity,
+                } => {
+                    let response = self.state.internal_message_ack(transaction_id, identity);
+                    self.send(response);
+                }
+                MasterInternalEvent::MessageToPeer {
+                    transaction_id,
+                    sender,
+                    client_id,
+                    data,
+                } => {
+                    let response =
+                        self.state
+                            .internal_message_to_peer(sender, transaction_id, client_id, data);
+                    self.send_many(response);
+                }
+                MasterInternalEvent::BroadcastToPeers {
+                    transaction_id,
+                    sender,
+                    data,
+                } => {
+                    let response =
+                        self.state
+                            .internal_broadcast_to_peers(sender, transaction_id, data);
+                    self.send_many(response);
+                }
+                MasterInternalEvent::RequestFromClient {
+                    transaction_id,
+                    identity,
+                    request_id,
+                    data,
+                    timeout_ms,
                 } => {
                     let response =
                         self.state
-                            .internal_client_message(client_id, transaction_id, data);
+                            .internal_request_from_client(identity, transaction_id, request_id, data, timeout_ms);
+                    self.send_many(response);
+                }
+                MasterInternalEvent::StateSnapshotRequest {
+                    transaction_id,
+                    identity,
+                } => {
+                    let response = self.state.internal_state_snapshot_request(identity, transaction_id);
+                    self.send_many(response);
+                }
+                MasterInternalEvent::ClientDisconnected { identity, reason, sequence } => {
+                    let response = self.state.internal_client_disconnected(identity, &reason, sequence);
+                    self.send_many(response);
+                }
+                MasterInternalEvent::MessageFromMaster {
+                    transaction_id,
+                    source_session_id,
+                    data,
+                } => {
+                    let response = self.state.internal_message_from_master(
+                        transaction_id,
+                        source_session_id,
+                        data,
+                    );
                     self.send_many(response);
                 }
-                MasterInternalEvent::ClientDisconnected { identity, reason } => {
-                    let response = self.state.internal_client_disconnected(identity, &reason);
+                MasterInternalEvent::MessageToMasterResponse {
+                    transaction_id,
+                    success,
+                    error,
+                } => {
+                    let response =
+                        self.state
+                            .internal_message_to_master_response(transaction_id, success, error);
+                    self.send(response);
+                }
+                MasterInternalEvent::TransferResult {
+                    transaction_id,
+                    success,
+                    error,
+                } => {
+                    let response =
+                        self.state
+                            .internal_transfer_result(transaction_id, success, error);
                     self.send(response);
                 }
+                MasterInternalEvent::ClientLeave {
+                    transaction_id,
+                    identity,
+                } => {
+                    let response = self.state.internal_client_leave(identity, transaction_id);
+                    self.send_many(response);
+                }
             },
             MasterEvent::Control(e) => {
                 match e {
@@ -118,6 +430,12 @@ impl MasterIsolate {
                         self.send_many(response);
                         return Err(()); // Halt
                     }
+                    MasterControlEvent::Authorized { tenant } => {
+                        self.state.control_authorized(tenant);
+                    }
+                    MasterControlEvent::InitializePeerSession { name, tenant } => {
+                        self.state.control_initialize_peer_session(name, tenant);
+                    }
                 }
             }
         }
@@ -126,33 +444,75 @@ impl MasterIsolate {
 
     pub fn event_loop(&mut self, channel: &IsolateChannel<MasterEvent>) -> Result<(), ()> {
         loop {
-            match channel.receiver.recv() {
+            match channel
+                .receiver
+                .recv_timeout(Duration::from_millis(MASTER_POLL_INTERVAL_MS))
+            {
                 Ok(event) => {
                     self.dispatch(event)?;
                 }
-                Err(_err) => {
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
                     self.logger.warn("Channel closed, halting isolate");
                     return Err(());
                 }
             }
+            if self.last_expire.elapsed() >= Duration::from_millis(MASTER_POLL_INTERVAL_MS) {
+                self.last_expire = Instant::now();
+                let response = self.state.expire();
+                self.send_many(response);
+            }
+
+            // An empty peer session stops, once nothing is waiting to join it
+            if self.state.reap(&channel.receiver) {
+                return Err(()); // Halt
+            }
+        }
+    }
+
+    /// A retried message is answered with the result it got the first time instead of being delivered again
+    fn is_duplicate(&mut self, event: &MasterExternalEvent) -> bool {
+        let transaction_id = match event.transaction_id() {
+            Some(transaction_id) if event.delivers_message() => transaction_id,
+            _ => return false,
+        };
+        match self.transactions.check(transaction_id) {
+            TransactionSeen::New => false,
+            TransactionSeen::Pending => {
+                self.logger.info(format!("Discarded duplicate of pending transaction {}", transaction_id));
+                true
+            }
+            TransactionSeen::Completed(result) => {
+                self.send_external(result);
+                true
+            }
         }
     }
 
     /// Send some arbitrary set of events to the appropriate destination and log them
-    fn send_many(&self, dispatch: Vec<MasterEventDispatch>) {
+    fn send_many(&mut self, dispatch: Vec<MasterEventDispatch>) {
         dispatch.into_iter().for_each(|i| self.send(i));
     }
 
     /// Send some arbitrary event to the appropriate destination and log it
-    fn send(&self, dispatch: MasterEventDispatch) {
+    fn send(&mut self, dispatch: MasterEventDispatch) {
         match dispatch {
             MasterEventDispatch::DispatchNone => {}
             DispatchExternal(ext) => self.send_external(ext),
             DispatchToClient(identity, event) => self.send_to_client(identity, event),
+            DispatchToSession(session_id, event) => self.send_to_session(&session_id, event),
         }
     }
 
-    fn send_external(&self, event: MasterExternalEvent) {
+    fn send_external(&mut self, event: MasterExternalEvent) {
+        if let MasterExternalEvent::TransactionResult { transaction_id, .. } = &event {
+            self.transactions.complete(transaction_id, &event);
+        }
+
+        // A peer session has nobody on the other end of the external channel
+        if self.state.is_peer() {
+            return;
+        }
         match self.external.as_ref() {
             Some(channel) => {
                 let output = MasterEvent::External(event);
@@ -163,6 +523,28 @@ impl MasterIsolate {
         };
     }
 
+    fn send_to_session(&self, session_id: &str, event: MasterInternalEvent) {
+        match self.state.find_session(session_id) {
+            Some(channel) => {
+                let output = MasterEvent::Internal(event);
+                self.logger.outgoing_event(&output);
+                if let Err(e) = channel.sender.send(output) {
+                    self.logger.warn(format!(
+                        "Failed to send event to session {}: {}",
+                        session_id,
+                        e.description()
+                    ));
+                }
+            }
+            None => {
+                self.logger.warn(format!(
+                    "Unable to send event to unknown session {}: {:?}",
+                    session_id, event
+                ));
+            }
+        }
+    }
+
     fn send_to_client(&self, identity: IsolateIdentity, event: ClientInternalEvent) {
         let output = ClientEvent::Internal(event);
         match self.state.get_client(&identity) {
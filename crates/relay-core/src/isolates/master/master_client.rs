@@ -0,0 +1,13 @@
+use crate::events::client_event::ClientEvent;
+use crate::model::client_role::ClientRole;
+use rust_isolate::IsolateChannel;
+
+/// A client that has been admitted to a session
+pub struct MasterClient {
+    pub channel: IsolateChannel<ClientEvent>,
+    pub name: String,
+    pub auth_key: Option<String>,
+    pub role: ClientRole,
+    pub sequence: u64,
+    pub resume_token: Option<String>,
+}
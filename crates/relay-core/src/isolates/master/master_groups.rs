@@ -0,0 +1,85 @@
+use rust_isolate::IsolateIdentity;
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+/// Named groups of clients inside a session
+pub struct MasterGroups {
+    groups: HashMap<String, HashSet<IsolateIdentity>>,
+}
+
+impl MasterGroups {
+    pub fn new() -> MasterGroups {
+        MasterGroups {
+            groups: HashMap::new(),
+        }
+    }
+
+    /// Create a new empty group; returns false if it already exists
+    pub fn create(&mut self, group: &str) -> bool {
+        if self.groups.contains_key(group) {
+            return false;
+        }
+        self.groups.insert(group.to_string(), HashSet::new());
+        true
+    }
+
+    /// Remove a group, returning the members it had
+    pub fn delete(&mut self, group: &str) -> Option<Vec<IsolateIdentity>> {
+        self.groups.remove(group).map(|members| members.into_iter().collect())
+    }
+
+    /// Add a client to a group; returns false if there is no such group
+    pub fn add(&mut self, group: &str, identity: &IsolateIdentity) -> bool {
+        match self.groups.get_mut(group) {
+            Some(members) => {
+                members.insert(identity.clone());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Remove a client from a group; returns false if there is no such group
+    pub fn remove(&mut self, group: &str, identity: &IsolateIdentity) -> bool {
+        match self.groups.get_mut(group) {
+            Some(members) => {
+                members.remove(identity);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Remove a client from every group it is in
+    pub fn remove_all(&mut self, identity: &IsolateIdentity) {
+        self.groups.values_mut().for_each(|members| {
+            members.remove(identity);
+        });
+    }
+
+    /// Move every membership of one client to another identity, eg. when a client resumes on a new connection
+    pub fn rename(&mut self, previous: &IsolateIdentity, identity: &IsolateIdentity) {
+        self.groups.values_mut().for_each(|members| {
+            if members.remove(previous) {
+                members.insert(identity.clone());
+            }
+        });
+    }
+
+    /// Return the members of a group, if it exists
+    pub fn members(&self, group: &str) -> Option<Vec<IsolateIdentity>> {
+        self.groups.get(group).map(|members| members.iter().cloned().collect())
+    }
+
+    /// Return the sorted names of every group a client is in
+    pub fn memberships(&self, identity: &IsolateIdentity) -> Vec<String> {
+        let mut groups: Vec<String> = self
+            .groups
+            .iter()
+            .filter(|(_, members)| members.contains(identity))
+            .map(|(name, _)| name.clone())
+            .collect();
+        groups.sort();
+        groups
+    }
+}
@@ -0,0 +1,44 @@
+use std::collections::VecDeque;
+
+/// The most recent messages the master marked as retained, for clients that join later
+pub struct MasterHistory {
+    messages: VecDeque<String>,
+    bytes: usize,
+    max_messages: usize,
+    max_bytes: usize,
+}
+
+impl MasterHistory {
+    pub fn new(max_messages: u32, max_bytes: u32) -> MasterHistory {
+        MasterHistory {
+            messages: VecDeque::new(),
+            bytes: 0,
+            max_messages: max_messages as usize,
+            max_bytes: max_bytes as usize,
+        }
+    }
+
+    /// Is there any limit set; without one nothing is retained
+    pub fn enabled(&self) -> bool {
+        self.max_messages > 0 || self.max_bytes > 0
+    }
+
+    /// Retain a message, dropping the oldest ones to stay inside the limits
+    pub fn push(&mut self, data: &str) {
+        if !self.enabled() || (self.max_bytes > 0 && data.len() > self.max_bytes) {
+            return;
+        }
+        self.messages.push_back(data.to_string());
+        self.bytes += data.len();
+        while (self.max_messages > 0 && self.messages.len() > self.max_messages) || (self.max_bytes > 0 && self.bytes > self.max_bytes) {
+            if let Some(oldest) = self.messages.pop_front() {
+                self.bytes -= oldest.len();
+            }
+        }
+    }
+
+    /// The retained messages, oldest first
+    pub fn messages(&self) -> impl Iterator<Item = &String> {
+        self.messages.iter()
+    }
+}
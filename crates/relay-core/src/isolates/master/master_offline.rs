@@ -0,0 +1,71 @@
+use crate::events::client_event::ClientInternalEvent;
+use crate::isolates::master::master_client::MasterClient;
+use crate::model::client_role::ClientRole;
+use serde_json::value::RawValue;
+use std::collections::VecDeque;
+use std::time::Duration;
+use std::time::Instant;
+use uuid::Uuid;
+
+/// A message from the master that is waiting for a disconnected client to resume.
+/// Copies of a broadcast have no transaction of their own; the broadcast resolved when they were queued.
+pub struct QueuedMessage {
+    pub transaction_id: Option<String>,
+    pub data: String,
+    pub payload: Option<Box<RawValue>>,
+    pub ack: bool,
+    pub queued: Instant,
+}
+
+/// A client whose connection dropped; its slot is held and messages to it are queued until it resumes
+pub struct OfflineClient {
+    pub name: String,
+    pub role: ClientRole,
+    pub resume_token: Option<String>,
+
+    /// The sequence number of the last message from this client
+    pub sequence: u64,
+
+    /// The sequence number of the last message this client was sent
+    pub received: u64,
+
+    pub disconnected: Instant,
+    pub queue: VecDeque<QueuedMessage>,
+
+    /// Answers to requests the client made before it disconnected; these don't count against the queue limit
+    pub responses: Vec<ClientInternalEvent>,
+}
+
+impl OfflineClient {
+    pub fn new(client: MasterClient, received: u64) -> OfflineClient {
+        OfflineClient {
+            name: client.name,
+            role: client.role,
+            resume_token: client.resume_token,
+            sequence: client.sequence,
+            received,
+            disconnected: Instant::now(),
+            queue: VecDeque::new(),
+            responses: Vec::new(),
+        }
+    }
+
+    /// Is this the client that is trying to resume? Names are picked by clients, so the token has to match too
+    pub fn matches(&self, name: &str, resume_token: &str) -> bool {
+        self.name == name && self.resume_token.as_ref().map(|t| t == resume_token).unwrap_or(false)
+    }
+
+    /// A new token for a client to resume with; it can't be guessed from anything else the client knows
+    pub fn generate_resume_token() -> String {
+        Uuid::new_v4().to_simple().to_string()
+    }
+
+    /// Remove every queued message older than the ttl, and return the transaction ids of the ones that have one
+    pub fn expire(&mut self, ttl: Duration) -> Vec<String> {
+        let mut expired = Vec::new();
+        while self.queue.front().map(|m| m.queued.elapsed() > ttl).unwrap_or(false) {
+            expired.extend(self.queue.pop_front().unwrap().transaction_id);
+        }
+        expired
+    }
+}
@@ -0,0 +1,45 @@
+use crate::model::external_error::ErrorCode;
+use crate::model::external_error::ExternalError;
+use crate::model::patch_format::PatchFormat;
+use json_patch::Patch;
+use serde_json::Value;
+
+/// A JSON document the relay holds for a session; only the master changes it, by sending patches
+pub struct MasterSharedState {
+    document: Value,
+    version: u64,
+}
+
+impl MasterSharedState {
+    pub fn new() -> MasterSharedState {
+        MasterSharedState {
+            document: Value::Null,
+            version: 0,
+        }
+    }
+
+    /// Apply a patch and return the new version; if the patch fails the document is left as it was
+    pub fn apply(&mut self, format: PatchFormat, patch: &Value) -> Result<u64, ExternalError> {
+        let mut document = self.document.clone();
+        match format {
+            PatchFormat::MergePatch => json_patch::merge(&mut document, patch),
+            PatchFormat::JsonPatch => {
+                let operations: Patch = serde_json::from_value(patch.clone())
+                    .map_err(|_| ExternalError::from(ErrorCode::InvalidStatePatch))?;
+                json_patch::patch(&mut document, &operations)
+                    .map_err(|_| ExternalError::from(ErrorCode::InvalidStatePatch))?;
+            }
+        }
+        self.document = document;
+        self.version += 1;
+        Ok(self.version)
+    }
+
+    pub fn document(&self) -> &Value {
+        &self.document
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+}
@@ -3,24 +3,110 @@ use crate::infrastructure::services::SessionManager;
 use rust_isolate::IsolateIdentity;
 use crate::model::external_error::ErrorCode;
 use crate::model::external_error::ExternalError;
+use crate::events::master_event::MasterEvent;
 use crate::events::master_event::MasterExternalEvent;
+use crate::events::master_event::MasterInternalEvent;
 use crate::isolates::master::MasterEventDispatch;
 use crate::isolates::master::MasterEventDispatch::DispatchExternal;
 use std::collections::HashMap;
 use rust_isolate::IsolateChannel;
 use crate::events::client_event::ClientEvent;
 use crate::isolates::master::MasterEventDispatch::DispatchToClient;
+use crate::isolates::master::MasterEventDispatch::DispatchToSession;
 use crate::events::client_event::ClientInternalEvent::ClientJoinResponse;
 use crate::events::client_event::ClientInternalEvent;
 use relay_logging::RelayEventLogger;
+use crate::model::client_metadata::ClientMetadata;
+use std::time::Duration;
+use std::time::Instant;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use crossbeam::Receiver;
+use crate::isolates::master::master_client::MasterClient;
+use crate::isolates::master::master_groups::MasterGroups;
+use crate::isolates::master::master_history::MasterHistory;
+use crate::isolates::master::master_offline::OfflineClient;
+use crate::isolates::master::master_offline::QueuedMessage;
+use crate::isolates::master::master_shared_state::MasterSharedState;
+use crate::model::client_role::ClientRole;
+use crate::model::patch_format::PatchFormat;
+use serde_json::value::RawValue;
+use serde_json::Value;
+use crate::DEFAULT_TENANT;
+
+/// How long to wait for the master to approve a join if the metadata doesn't say
+const DEFAULT_JOIN_APPROVAL_TIMEOUT_MS: u64 = 30000;
+
+/// How long a message that asked for an ack waits for it
+const MESSAGE_ACK_TIMEOUT_MS: u64 = 30000;
+
+/// How long a request from a client waits for an answer if it doesn't say
+const DEFAULT_REQUEST_TIMEOUT_MS: u64 = 30000;
+
+/// The longest a request from a client can ask to wait for an answer
+const MAX_REQUEST_TIMEOUT_MS: u64 = 10 * 60 * 1000;
+
+/// The longest a seat can be held for a client that hasn't joined yet
+const MAX_RESERVATION_MS: u64 = 24 * 60 * 60 * 1000;
+
+/// How many clients can join a peer session, which has no master to configure it
+const PEER_SESSION_MAX_CLIENTS: u32 = 64;
+
+/// A join request waiting for the master to approve or reject it
+struct PendingJoin {
+    transaction_id: String,
+    name: String,
+    auth_key: Option<String>,
+    role: ClientRole,
+    requested: Instant,
+}
+
+/// A message waiting for the other side to acknowledge it
+struct PendingAck {
+    transaction_id: String,
+    identity: IsolateIdentity,
+    sent: Instant,
+}
+
+/// A request from a client waiting for the master to answer it. The master knows it by the client id it was
+/// sent with; the answer goes to identity, which moves to the new connection if the client resumes.
+struct PendingRequest {
+    identity: IsolateIdentity,
+    deadline: Instant,
+}
+
+/// A join request waiting for a slot to free up in a full session
+struct WaitingClient {
+    identity: IsolateIdentity,
+    transaction_id: String,
+    name: String,
+    metadata: ClientMetadata,
+    auth_key: Option<String>,
+    role: ClientRole,
+}
 
 pub struct MasterState {
     name: String,
+    tenant: String,
     logger: RelayEventLogger,
     identity: IsolateIdentity,
     active: bool,
+    peer: bool,
     metadata: Option<MasterMetadata>,
-    clients: HashMap<IsolateIdentity, IsolateChannel<ClientEvent>>,
+    clients: HashMap<IsolateIdentity, MasterClient>,
+    pending_joins: HashMap<IsolateIdentity, PendingJoin>,
+    banned_names: HashSet<String>,
+    banned_identities: HashSet<IsolateIdentity>,
+    banned_keys: HashSet<String>,
+    reservations: HashMap<String, Instant>,
+    waitlist: VecDeque<WaitingClient>,
+    groups: MasterGroups,
+    shared_state: Option<MasterSharedState>,
+    history: MasterHistory,
+    offline: HashMap<IsolateIdentity, OfflineClient>,
+    client_acks: HashMap<(IsolateIdentity, u64), PendingAck>,
+    master_acks: HashMap<String, PendingAck>,
+    requests: HashMap<(IsolateIdentity, String), PendingRequest>,
     manager: SessionManager,
 }
 
@@ -31,8 +117,23 @@ impl MasterState {
             manager,
             identity,
             name: String::new(),
+            tenant: DEFAULT_TENANT.to_string(),
             clients: HashMap::new(),
+            pending_joins: HashMap::new(),
+            banned_names: HashSet::new(),
+            banned_identities: HashSet::new(),
+            banned_keys: HashSet::new(),
+            reservations: HashMap::new(),
+            waitlist: VecDeque::new(),
+            groups: MasterGroups::new(),
+            shared_state: None,
+            history: MasterHistory::new(0, 0),
+            offline: HashMap::new(),
+            client_acks: HashMap::new(),
+            master_acks: HashMap::new(),
+            requests: HashMap::new(),
             active: false,
+            peer: false,
             metadata: None,
         }
     }
@@ -43,15 +144,35 @@ impl MasterState {
             manager: self.manager.clone(),
             identity,
             name: String::new(),
+            tenant: DEFAULT_TENANT.to_string(),
             clients: HashMap::new(),
+            pending_joins: HashMap::new(),
+            banned_names: HashSet::new(),
+            banned_identities: HashSet::new(),
+            banned_keys: HashSet::new(),
+            reservations: HashMap::new(),
+            waitlist: VecDeque::new(),
+            groups: MasterGroups::new(),
+            shared_state: None,
+            history: MasterHistory::new(0, 0),
+            offline: HashMap::new(),
+            client_acks: HashMap::new(),
+            master_acks: HashMap::new(),
+            requests: HashMap::new(),
             active: false,
+            peer: false,
             metadata: None,
         }
     }
 
     /// Return a reference to the given client, if it is connected
     pub fn get_client(&self, identity: &IsolateIdentity) -> Option<&IsolateChannel<ClientEvent>> {
-        self.clients.get(identity)
+        self.clients.get(identity).map(|c| &c.channel)
+    }
+
+    /// Return the master of some other session
+    pub fn find_session(&self, session_id: &str) -> Option<IsolateChannel<MasterEvent>> {
+        self.manager.find_master(&self.tenant, session_id).ok()
     }
 
     /// Return a client that isn't attached to this master
@@ -66,21 +187,66 @@ impl MasterState {
     }
 
     pub fn external_initialize(&mut self, transaction_id: String, metadata: MasterMetadata) -> MasterEventDispatch {
-        match self.manager.register_session(&self.identity, &metadata.master_id) {
-            Ok(_) => {
+        match self.manager.register_session(&self.tenant, &self.identity, &metadata) {
+            Ok(join_code) => {
                 self.name = metadata.master_id.clone();
+                self.banned_names = metadata.banned.iter().cloned().collect();
+                self.banned_keys = metadata.banned_keys.iter().cloned().collect();
+                self.history = MasterHistory::new(metadata.history_max_messages, metadata.history_max_bytes);
                 self.metadata = Some(metadata);
                 self.active = true;
-                DispatchExternal(MasterExternalEvent::TransactionResult { transaction_id, success: true, error: None })
+                DispatchExternal(MasterExternalEvent::TransactionResult { transaction_id, success: true, error: None, join_code })
             }
             Err(e) => {
-                DispatchExternal(MasterExternalEvent::TransactionResult { transaction_id, success: false, error: Some(ExternalError::from(e)) })
+                DispatchExternal(MasterExternalEvent::TransactionResult { transaction_id, success: false, error: Some(ExternalError::from(e)), join_code: None })
             }
         }
     }
 
-    pub fn internal_client_join_request(&mut self, name: &str, transaction_id: String, identity: IsolateIdentity) -> Vec<MasterEventDispatch> {
-        if self.clients.contains_key(&identity) {
+    /// Remember the tenant this master registers its session in
+    pub fn control_authorized(&mut self, tenant: String) {
+        self.tenant = tenant;
+    }
+
+    /// Run this master as a masterless peer session; there is no external master to talk to
+    pub fn control_initialize_peer_session(&mut self, name: String, tenant: String) {
+        if self.manager.claim_peer_session(&tenant, &name, &self.identity).is_err() {
+            self.logger.warn("Failed to claim peer session");
+        }
+        self.name = name.clone();
+        self.tenant = tenant;
+        self.metadata = Some(MasterMetadata {
+            master_id: name,
+            max_clients: PEER_SESSION_MAX_CLIENTS,
+            peer_messaging: true,
+            ..Default::default()
+        });
+        self.active = true;
+        self.peer = true;
+    }
+
+    /// Is this a peer session, without an external master?
+    pub fn is_peer(&self) -> bool {
+        self.peer
+    }
+
+    /// Once the last member leaves a peer session it is removed; returns true if the isolate should halt.
+    /// Joins are sent to peer sessions under the session manager lock, and the inbox is checked under the same lock,
+    /// so a join can't be left behind in an isolate that is about to halt.
+    pub fn reap(&mut self, inbox: &Receiver<MasterEvent>) -> bool {
+        if !self.peer || !self.active || !self.clients.is_empty() || !self.pending_joins.is_empty() || !self.waitlist.is_empty() {
+            return false;
+        }
+        if let Ok(false) = self.manager.remove_idle_peer_session(&self.tenant, &self.name, &self.identity, inbox) {
+            return false;
+        }
+        self.active = false;
+        self.logger.info("Peer session is empty, closing it");
+        true
+    }
+
+    pub fn internal_client_join_request(&mut self, name: &str, transaction_id: String, identity: IsolateIdentity, metadata: ClientMetadata, auth_key: Option<String>, role: ClientRole, waitlist: bool) -> Vec<MasterEventDispatch> {
+        if self.clients.contains_key(&identity) || self.pending_joins.contains_key(&identity) || self.is_waiting(&identity) {
             return vec!(
                 DispatchToClient(identity, ClientJoinResponse {
                     transaction_id,
@@ -98,74 +264,569 @@ impl MasterState {
                 })
             );
         }
-        match self.metadata.as_ref() {
-            Some(m) => {
-                if self.clients.len() >= m.max_clients as usize {
-                    return vec!(
-                        DispatchToClient(identity, ClientJoinResponse {
-                            transaction_id,
-                            success: false,
-                            error: Some(ExternalError::from(ErrorCode::ClientLimitExceeded)),
-                        })
-                    );
-                }
+        if self.is_banned(name, &identity, auth_key.as_ref()) {
+            return vec!(
+                DispatchToClient(identity, ClientJoinResponse {
+                    transaction_id,
+                    success: false,
+                    error: Some(ExternalError::from(ErrorCode::ClientBanned)),
+                })
+            );
+        }
+        if let Err(e) = self.check_capacity(role, name, auth_key.as_ref()) {
+            // If the client is happy to wait, queue it up until a slot frees
+            if waitlist {
+                self.waitlist.push_back(WaitingClient {
+                    identity: identity.clone(),
+                    transaction_id: transaction_id.clone(),
+                    name: name.to_string(),
+                    metadata,
+                    auth_key,
+                    role,
+                });
+                return vec!(DispatchToClient(identity, ClientInternalEvent::WaitlistPosition {
+                    transaction_id,
+                    position: self.waitlist.len() as u32,
+                }));
+            }
+            return vec!(
+                DispatchToClient(identity, ClientJoinResponse {
+                    transaction_id,
+                    success: false,
+                    error: Some(e),
+                })
+            );
+        }
+
+        self.accept_join(name, transaction_id, identity, metadata, auth_key, role)
+    }
+
+    /// A join request that passed every check; either admit the client, or ask the master about it
+    fn accept_join(&mut self, name: &str, transaction_id: String, identity: IsolateIdentity, metadata: ClientMetadata, auth_key: Option<String>, role: ClientRole) -> Vec<MasterEventDispatch> {
+        // If the master wants to vet clients, park the request until it answers
+        if self.metadata.as_ref().map(|m| m.approve_joins).unwrap_or(false) {
+            self.pending_joins.insert(identity.clone(), PendingJoin {
+                transaction_id,
+                name: name.to_string(),
+                auth_key,
+                role,
+                requested: Instant::now(),
+            });
+            return vec!(
+                DispatchExternal(MasterExternalEvent::ClientJoinRequested { client_id: identity.to_string(), name: name.to_string(), metadata, role })
+            );
+        }
+
+        match self.admit_client(name, auth_key, role, transaction_id.clone(), identity.clone()) {
+            Ok(response) => response,
+            Err(e) => {
+                vec!(DispatchToClient(identity, ClientJoinResponse {
+                    transaction_id,
+                    success: false,
+                    error: Some(e),
+                }))
             }
-            _ => {
-                self.logger.warn("Master has no metadata set!")
+        }
+    }
+
+    /// The external master approved a pending join request
+    pub fn external_approve_join(&mut self, transaction_id: String, client_id: String) -> Vec<MasterEventDispatch> {
+        let identity = match self.pending_identity(&client_id) {
+            Ok(identity) => identity,
+            Err(e) => {
+                return vec!(DispatchExternal(MasterExternalEvent::TransactionResult { transaction_id, success: false, error: Some(e), join_code: None }));
             }
+        };
+        let pending = self.pending_joins.remove(&identity).unwrap();
+
+        // The session may have filled up while the master was thinking about it
+        if let Err(e) = self.check_capacity(pending.role, &pending.name, pending.auth_key.as_ref()) {
+            return vec!(
+                DispatchToClient(identity, ClientJoinResponse {
+                    transaction_id: pending.transaction_id,
+                    success: false,
+                    error: Some(e.clone()),
+                }),
+                DispatchExternal(MasterExternalEvent::TransactionResult {
+                    transaction_id,
+                    success: false,
+                    error: Some(e),
+                    join_code: None,
+                })
+            );
         }
-        match self.manager.find_client(&identity) {
-            Ok(client_ref) => {
-                self.clients.insert(identity.clone(), client_ref);
+
+        match self.admit_client(&pending.name, pending.auth_key.clone(), pending.role, pending.transaction_id.clone(), identity.clone()) {
+            Ok(mut response) => {
+                response.push(DispatchExternal(MasterExternalEvent::TransactionResult { transaction_id, success: true, error: None, join_code: None }));
+                response
+            }
+            Err(e) => {
                 vec!(
-                    DispatchExternal(MasterExternalEvent::ClientJoined { name: name.to_string(), client_id: identity.to_string() }),
-                    DispatchToClient(identity, ClientJoinResponse { transaction_id, success: true, error: None })
+                    DispatchToClient(identity, ClientJoinResponse {
+                        transaction_id: pending.transaction_id,
+                        success: false,
+                        error: Some(e.clone()),
+                    }),
+                    DispatchExternal(MasterExternalEvent::TransactionResult { transaction_id, success: false, error: Some(e), join_code: None })
                 )
             }
+        }
+    }
+
+    /// The external master rejected a pending join request
+    pub fn external_reject_join(&mut self, transaction_id: String, client_id: String, reason: String) -> Vec<MasterEventDispatch> {
+        let identity = match self.pending_identity(&client_id) {
+            Ok(identity) => identity,
             Err(e) => {
-                vec!(DispatchToClient(identity, ClientJoinResponse {
+                return vec!(DispatchExternal(MasterExternalEvent::TransactionResult { transaction_id, success: false, error: Some(e), join_code: None }));
+            }
+        };
+        let pending = self.pending_joins.remove(&identity).unwrap();
+        let mut response = vec!(
+            DispatchToClient(identity, ClientJoinResponse {
+                transaction_id: pending.transaction_id,
+                success: false,
+                error: Some(ExternalError { error_code: ErrorCode::JoinRejected as i32, error_reason: reason, undelivered: Vec::new() }),
+            }),
+            DispatchExternal(MasterExternalEvent::TransactionResult { transaction_id, success: true, error: None, join_code: None })
+        );
+        response.extend(self.admit_waiting());
+        response
+    }
+
+    /// The external master wants a client gone
+    pub fn external_kick_client(&mut self, transaction_id: String, client_id: String, reason: String, ban: bool, ban_key: bool) -> Vec<MasterEventDispatch> {
+        let identity = match IsolateIdentity::try_from(&client_id) {
+            Ok(s) => s,
+            Err(_) => {
+                return vec!(DispatchExternal(MasterExternalEvent::TransactionResult {
                     transaction_id,
                     success: false,
-                    error: Some(ExternalError::from(e)),
-                }))
+                    error: Some(ExternalError::from(ErrorCode::InvalidClientIdentityToken)),
+                    join_code: None,
+                }));
+            }
+        };
+        let client = match self.remove_client(&identity) {
+            Some(c) => c,
+            None => {
+                return vec!(DispatchExternal(MasterExternalEvent::TransactionResult {
+                    transaction_id,
+                    success: false,
+                    error: Some(ExternalError::from(ErrorCode::NoMatchingClientId)),
+                    join_code: None,
+                }));
+            }
+        };
+
+        if ban {
+            self.banned_names.insert(client.name.clone());
+            self.banned_identities.insert(identity.clone());
+        }
+        if let (true, Some(auth_key)) = (ban_key, client.auth_key.as_ref()) {
+            self.banned_keys.insert(auth_key.clone());
+        }
+
+        self.logger.info(format!("Client kicked: {}", reason));
+        let mut response = vec!(
+            DispatchToClient(identity, ClientInternalEvent::Kicked { reason }),
+            DispatchExternal(MasterExternalEvent::TransactionResult { transaction_id, success: true, error: None, join_code: None })
+        );
+        response.extend(self.admit_waiting());
+        response
+    }
+
+    /// Hold a player slot for a client name or auth key; reserving the same seat again extends it
+    pub fn external_reserve_seat(&mut self, transaction_id: String, reservation: String, expires_ms: u64) -> MasterEventDispatch {
+        if expires_ms > MAX_RESERVATION_MS {
+            return DispatchExternal(MasterExternalEvent::TransactionResult {
+                transaction_id,
+                success: false,
+                error: Some(ExternalError::from(ErrorCode::TimeoutOutOfRange)),
+                join_code: None,
+            });
+        }
+
+        let expires = Instant::now() + Duration::from_millis(expires_ms);
+        if let Some(existing) = self.reservations.get_mut(&reservation) {
+            *existing = expires;
+            return DispatchExternal(MasterExternalEvent::TransactionResult { transaction_id, success: true, error: None, join_code: None });
+        }
+
+        let max_clients = self.metadata.as_ref().map(|m| m.max_clients as usize).unwrap_or(0);
+        if self.player_count() + self.reservations.len() >= max_clients {
+            return DispatchExternal(MasterExternalEvent::TransactionResult {
+                transaction_id,
+                success: false,
+                error: Some(ExternalError::from(ErrorCode::ClientLimitExceeded)),
+                join_code: None,
+            });
+        }
+
+        self.reservations.insert(reservation, expires);
+        self.occupancy_changed();
+        DispatchExternal(MasterExternalEvent::TransactionResult { transaction_id, success: true, error: None, join_code: None })
+    }
+
+    /// Release a reserved slot, which may let a waiting client in
+    pub fn external_cancel_reservation(&mut self, transaction_id: String, reservation: String) -> Vec<MasterEventDispatch> {
+        if self.reservations.remove(&reservation).is_none() {
+            return vec!(DispatchExternal(MasterExternalEvent::TransactionResult {
+                transaction_id,
+                success: false,
+                error: Some(ExternalError::from(ErrorCode::NoMatchingReservation)),
+                join_code: None,
+            }));
+        }
+
+        self.occupancy_changed();
+        let mut response = vec!(DispatchExternal(MasterExternalEvent::TransactionResult { transaction_id, success: true, error: None, join_code: None }));
+        response.extend(self.admit_waiting());
+        response
+    }
+
+    /// Fail any requests that have been waiting too long; this is called periodically by the isolate
+    pub fn expire(&mut self) -> Vec<MasterEventDispatch> {
+        let timeout = Duration::from_millis(self.metadata.as_ref()
+            .and_then(|m| m.join_approval_timeout_ms)
+            .unwrap_or(DEFAULT_JOIN_APPROVAL_TIMEOUT_MS));
+        let expired: Vec<IsolateIdentity> = self.pending_joins.iter()
+            .filter(|(_, v)| v.requested.elapsed() > timeout)
+            .map(|(k, _)| k.clone())
+            .collect();
+
+        let mut notifications = Vec::new();
+        let joins_expired = !expired.is_empty();
+        for identity in expired {
+            let pending = self.pending_joins.remove(&identity).unwrap();
+            notifications.push(DispatchExternal(MasterExternalEvent::JoinRequestExpired {
+                client_id: identity.to_string(),
+            }));
+            notifications.push(DispatchToClient(identity, ClientJoinResponse {
+                transaction_id: pending.transaction_id,
+                success: false,
+                error: Some(ExternalError::from(ErrorCode::JoinRequestExpired)),
+            }));
+        }
+        if joins_expired {
+            notifications.extend(self.admit_waiting());
+        }
+
+        // Messages that were never acknowledged fail
+        let ack_timeout = Duration::from_millis(MESSAGE_ACK_TIMEOUT_MS);
+        let expired: Vec<(IsolateIdentity, u64)> = self.client_acks.iter()
+            .filter(|(_, v)| v.sent.elapsed() > ack_timeout)
+            .map(|(k, _)| k.clone())
+            .collect();
+        for key in expired {
+            let pending = self.client_acks.remove(&key).unwrap();
+            notifications.push(DispatchToClient(pending.identity, ClientInternalEvent::MessageFromClientResponse {
+                transaction_id: pending.transaction_id,
+                success: false,
+                error: Some(ExternalError::from(ErrorCode::AckTimeout)),
+            }));
+        }
+        let expired: Vec<String> = self.master_acks.iter()
+            .filter(|(_, v)| v.sent.elapsed() > ack_timeout)
+            .map(|(k, _)| k.clone())
+            .collect();
+        for transaction_id in expired {
+            self.master_acks.remove(&transaction_id);
+            notifications.push(DispatchExternal(MasterExternalEvent::TransactionResult {
+                transaction_id,
+                success: false,
+                error: Some(ExternalError::from(ErrorCode::AckTimeout)),
+                join_code: None,
+            }));
+        }
+
+        // Requests the master didn't answer in time fail
+        let now = Instant::now();
+        let expired: Vec<(IsolateIdentity, String)> = self.requests.iter()
+            .filter(|(_, v)| v.deadline <= now)
+            .map(|(k, _)| k.clone())
+            .collect();
+        for key in expired {
+            let pending = self.requests.remove(&key).unwrap();
+            let (_, request_id) = key;
+            notifications.extend(self.respond_to_client(pending.identity, ClientInternalEvent::ResponseToClient {
+                request_id,
+                data: String::new(),
+                error: Some(ExternalError::from(ErrorCode::RequestTimeout)),
+            }));
+        }
+
+        // Queued messages expire, and so do disconnected clients that took too long to resume
+        let ttl = self.offline_queue_ttl();
+        let mut gone = Vec::new();
+        for (identity, offline) in self.offline.iter_mut() {
+            notifications.extend(offline.expire(ttl).into_iter().map(message_expired));
+            if offline.disconnected.elapsed() > ttl {
+                gone.push(identity.clone());
             }
         }
+        for identity in gone.iter() {
+            let offline = self.offline.remove(identity).unwrap();
+            notifications.extend(offline.queue.into_iter().filter_map(|m| m.transaction_id).map(message_expired));
+            self.groups.remove_all(identity);
+            self.requests.retain(|_, v| v.identity != *identity);
+        }
+        if !gone.is_empty() {
+            self.occupancy_changed();
+            notifications.extend(self.admit_waiting());
+        }
+
+        // An expired reservation frees a slot for someone on the waitlist
+        let reserved = self.reservations.len();
+        self.reservations.retain(|_, expires| *expires > now);
+        if self.reservations.len() != reserved {
+            self.occupancy_changed();
+            notifications.extend(self.admit_waiting());
+        }
+        notifications
     }
 
     /// New message from some connected client
-    pub fn internal_client_message(&self, client_id: IsolateIdentity, transaction_id: String, data: String) -> Vec<MasterEventDispatch> {
-        if !self.clients.contains_key(&client_id) {
+    pub fn internal_client_message(&mut self, client_id: IsolateIdentity, transaction_id: String, data: String, payload: Option<Box<RawValue>>, ack: bool) -> Vec<MasterEventDispatch> {
+        if let Err(e) = self.check_can_send(&client_id) {
             return vec!(DispatchToClient(client_id, ClientInternalEvent::MessageFromClientResponse {
                 transaction_id,
                 success: false,
-                error: Some(ExternalError::from(ErrorCode::NoMatchingClientId)),
+                error: Some(e),
             }));
         }
 
-        vec!(DispatchExternal(MasterExternalEvent::MessageFromClient {
+        // Without a master, messages go to every other member
+        if self.peer {
+            let recipients = self.clients.keys()
+                .filter(|k| **k != client_id)
+                .cloned()
+                .collect();
+            return self.route_to_peers(client_id, transaction_id, recipients, peer_data(data, payload));
+        }
+
+        let sequence = match self.clients.get_mut(&client_id) {
+            Some(client) => {
+                client.sequence += 1;
+                client.sequence
+            }
+            None => 0,
+        };
+        let forward = DispatchExternal(MasterExternalEvent::MessageFromClient {
             client_id: client_id.to_string(),
             data,
-        }), DispatchToClient(client_id, ClientInternalEvent::MessageFromClientResponse {
+            payload,
+            sequence,
+            ack,
+        });
+
+        // The client hears back once the master application acknowledges the message
+        if ack {
+            self.client_acks.insert((client_id.clone(), sequence), PendingAck { transaction_id, identity: client_id, sent: Instant::now() });
+            return vec!(forward);
+        }
+        vec!(forward, DispatchToClient(client_id, ClientInternalEvent::MessageFromClientResponse {
             transaction_id,
             success: true,
             error: None,
         }))
     }
 
-    /// A client disappeared
-    pub fn internal_client_disconnected(&mut self, identity: IsolateIdentity, reason: &str) -> MasterEventDispatch {
-        if self.clients.contains_key(&identity) {
-            self.clients.remove(&identity);
+    /// Fire-and-forget message from some connected client; there is no result either way
+    pub fn internal_unreliable_client_message(&mut self, client_id: IsolateIdentity, data: String, payload: Option<Box<RawValue>>) -> Vec<MasterEventDispatch> {
+        if self.check_can_send(&client_id).is_err() {
+            return Vec::new();
+        }
+        if self.peer {
+            let recipients = self.clients.keys()
+                .filter(|k| **k != client_id)
+                .cloned()
+                .collect();
+            return self.peer_deliveries(&client_id, recipients, peer_data(data, payload));
+        }
+        let sequence = match self.clients.get_mut(&client_id) {
+            Some(client) => {
+                client.sequence += 1;
+                client.sequence
+            }
+            None => 0,
+        };
+        vec!(DispatchExternal(MasterExternalEvent::MessageFromClient {
+            client_id: client_id.to_string(),
+            data,
+            payload,
+            sequence,
+            ack: false,
+        }))
+    }
+
+    /// New message from a connected client to another client
+    pub fn internal_message_to_peer(&self, sender: IsolateIdentity, transaction_id: String, client_id: String, data: String) -> Vec<MasterEventDispatch> {
+        if let Err(e) = self.check_peer_messaging(&sender) {
+            return vec!(DispatchToClient(sender, ClientInternalEvent::MessageFromClientResponse { transaction_id, success: false, error: Some(e) }));
+        }
+        let identity = match self.client_identity(&client_id) {
+            Ok(identity) => identity,
+            Err(e) => {
+                return vec!(DispatchToClient(sender, ClientInternalEvent::MessageFromClientResponse { transaction_id, success: false, error: Some(e) }));
+            }
+        };
+        self.route_to_peers(sender, transaction_id, vec!(identity), data)
+    }
+
+    /// New message from a connected client to every other client
+    pub fn internal_broadcast_to_peers(&self, sender: IsolateIdentity, transaction_id: String, data: String) -> Vec<MasterEventDispatch> {
+        if let Err(e) = self.check_peer_messaging(&sender) {
+            return vec!(DispatchToClient(sender, ClientInternalEvent::MessageFromClientResponse { transaction_id, success: false, error: Some(e) }));
+        }
+        let recipients = self.clients.keys()
+            .filter(|k| **k != sender)
+            .cloned()
+            .collect();
+        self.route_to_peers(sender, transaction_id, recipients, data)
+    }
+
+    /// A client's connection dropped; if the session has an offline queue, its slot is held until it resumes.
+    /// It stays in its groups, and its requests stay open, while it is away.
+    pub fn internal_client_disconnected(&mut self, identity: IsolateIdentity, reason: &str, sequence: u64) -> Vec<MasterEventDispatch> {
+        if self.offline_queue_limit() > 0 && !self.peer {
+            if let Some(client) = self.clients.remove(&identity) {
+                self.logger.info(format!("Client disconnected, holding its slot: {}", reason));
+                self.offline.insert(identity.clone(), OfflineClient::new(client, sequence));
+                self.occupancy_changed();
+                return vec!(DispatchExternal(MasterExternalEvent::ClientDisconnected {
+                    reason: reason.to_string(),
+                    client_id: identity.to_string(),
+                }));
+            }
+        }
+        self.client_gone(identity, reason)
+    }
+
+    /// A disconnected client wants its slot back; it gets everything queued for it, in order
+    pub fn internal_client_resume_request(&mut self, transaction_id: String, identity: IsolateIdentity, name: String, auth_key: Option<String>, resume_token: &str) -> Vec<MasterEventDispatch> {
+        let previous = match self.offline.iter().find(|(_, v)| v.matches(&name, resume_token)) {
+            Some((k, _)) => k.clone(),
+            None => {
+                return vec!(DispatchToClient(identity, ClientJoinResponse {
+                    transaction_id,
+                    success: false,
+                    error: Some(ExternalError::from(ErrorCode::NoMatchingClientId)),
+                }));
+            }
+        };
+        let channel = match self.manager.find_client(&identity) {
+            Ok(c) => c,
+            Err(e) => {
+                return vec!(DispatchToClient(identity, ClientJoinResponse { transaction_id, success: false, error: Some(ExternalError::from(e)) }));
+            }
+        };
+
+        let offline = self.offline.remove(&previous).unwrap();
+        self.rename_client(&previous, &identity);
+        self.logger.info(format!("Client resumed with {} queued messages", offline.queue.len()));
+        let mut notifications = vec!(
+            DispatchExternal(MasterExternalEvent::ClientResumed { client_id: identity.to_string(), previous_client_id: previous.to_string() }),
+            DispatchToClient(identity.clone(), ClientInternalEvent::Resumed { sequence: offline.received }),
+            DispatchToClient(identity.clone(), ClientJoinResponse { transaction_id, success: true, error: None })
+        );
+        if self.shared_state.is_some() {
+            notifications.push(self.state_snapshot(identity.clone()));
+        }
+        if !self.groups.memberships(&identity).is_empty() {
+            notifications.push(self.membership_update(identity.clone()));
         }
+        for message in offline.queue {
+            match message.transaction_id {
+                Some(transaction_id) => {
+                    notifications.extend(self.deliver_to_client(identity.clone(), transaction_id, message.data, message.payload, message.ack));
+                }
+                None => {
+                    notifications.push(DispatchToClient(identity.clone(), ClientInternalEvent::MessageFromMaster { data: message.data, payload: message.payload, ack: None }));
+                }
+            }
+        }
+        notifications.extend(offline.responses.into_iter().map(|response| DispatchToClient(identity.clone(), response)));
+
+        self.clients.insert(identity, MasterClient {
+            channel,
+            name,
+            auth_key,
+            role: offline.role,
+            sequence: offline.sequence,
+            resume_token: offline.resume_token,
+        });
+        notifications
+    }
+
+    /// A client is gone from the session for good
+    fn client_gone(&mut self, identity: IsolateIdentity, reason: &str) -> Vec<MasterEventDispatch> {
+        let removed = self.remove_client(&identity).is_some();
+        let waiting = self.remove_waiting(&identity).is_some();
+        let pending = self.pending_joins.remove(&identity).is_some();
         self.logger.info(format!("Client disconnected: {}", reason));
-        MasterEventDispatch::DispatchExternal(MasterExternalEvent::ClientDisconnected {
+
+        let mut notifications = vec!(MasterEventDispatch::DispatchExternal(MasterExternalEvent::ClientDisconnected {
             reason: reason.to_string(),
             client_id: identity.to_string(),
-        })
+        }));
+        if self.peer && removed {
+            self.clients.keys().for_each(|k| {
+                notifications.push(DispatchToClient(k.clone(), ClientInternalEvent::PeerLeft {
+                    client_id: identity.to_string(),
+                    reason: reason.to_string(),
+                }))
+            });
+        }
+
+        // Either a slot is free now, or the queue got shorter
+        if removed || pending {
+            notifications.extend(self.admit_waiting());
+        } else if waiting {
+            notifications.extend(self.waitlist_positions());
+        }
+        notifications
+    }
+
+    /// A client asked to leave the session, but keep its connection
+    pub fn internal_client_leave(&mut self, identity: IsolateIdentity, transaction_id: String) -> Vec<MasterEventDispatch> {
+        if !self.clients.contains_key(&identity) && !self.pending_joins.contains_key(&identity) && !self.is_waiting(&identity) {
+            return vec!(DispatchToClient(identity, ClientInternalEvent::LeaveResponse {
+                transaction_id,
+                success: false,
+                error: Some(ExternalError::from(ErrorCode::NoMatchingClientId)),
+            }));
+        }
+
+        // A client that leaves the waitlist, or leaves before the master answers, never gets in, so close off its join request too
+        let mut response = Vec::new();
+        if let Some(waiting) = self.waitlist.iter().find(|w| w.identity == identity) {
+            response.push(DispatchToClient(identity.clone(), ClientJoinResponse {
+                transaction_id: waiting.transaction_id.clone(),
+                success: false,
+                error: Some(ExternalError::from(ErrorCode::LeftWaitlist)),
+            }));
+        }
+        if let Some(pending) = self.pending_joins.get(&identity) {
+            response.push(DispatchToClient(identity.clone(), ClientJoinResponse {
+                transaction_id: pending.transaction_id.clone(),
+                success: false,
+                error: Some(ExternalError::from(ErrorCode::LeftBeforeApproval)),
+            }));
+        }
+
+        response.extend(self.client_gone(identity.clone(), "Client left the session"));
+        response.push(DispatchToClient(identity, ClientInternalEvent::LeaveResponse {
+            transaction_id,
+            success: true,
+            error: None,
+        }));
+        response
     }
 
     /// New message from master to some connected client
-    pub fn external_message_to_client(&self, client_id: String, transaction_id: String, data: String) -> Vec<MasterEventDispatch> {
+    pub fn external_message_to_client(&mut self, client_id: String, transaction_id: String, data: String, payload: Option<Box<RawValue>>, ack: bool) -> Vec<MasterEventDispatch> {
         // Attempt to resolve identity
         let identity = match IsolateIdentity::try_from(&client_id) {
             Ok(s) => s,
@@ -174,25 +835,71 @@ impl MasterState {
                     transaction_id,
                     success: false,
                     error: Some(ExternalError::from(ErrorCode::InvalidClientIdentityToken)),
+                    join_code: None,
                 }));
             }
         };
 
+        // A client whose connection dropped gets the message when it resumes; the transaction resolves then
+        let limit = self.offline_queue_limit();
+        if let Some(offline) = self.offline.get_mut(&identity) {
+            if offline.queue.len() >= limit {
+                return vec!(DispatchExternal(MasterExternalEvent::TransactionResult {
+                    transaction_id,
+                    success: false,
+                    error: Some(ExternalError::from(ErrorCode::OfflineQueueFull)),
+                    join_code: None,
+                }));
+            }
+            offline.queue.push_back(QueuedMessage { transaction_id: Some(transaction_id), data, payload, ack, queued: Instant::now() });
+            return Vec::new();
+        }
+
         // Check we know about that client
         if !self.clients.contains_key(&identity) {
             return vec!(DispatchExternal(MasterExternalEvent::TransactionResult {
                 transaction_id,
                 success: false,
                 error: Some(ExternalError::from(ErrorCode::NoMatchingClientId)),
+                join_code: None,
             }));
         }
 
         // If that all worked, send the message onwards and resolve the transaction
+        self.deliver_to_client(identity, transaction_id, data, payload, ack)
+    }
+
+    /// Fire-and-forget message from the external master; nothing is queued or retried, and there is no result either way
+    pub fn external_unreliable_message_to_client(&self, client_id: String, data: String, payload: Option<Box<RawValue>>) -> Vec<MasterEventDispatch> {
+        match IsolateIdentity::try_from(&client_id) {
+            Ok(identity) if self.clients.contains_key(&identity) => {
+                vec!(DispatchToClient(identity, ClientInternalEvent::MessageFromMaster { data, payload, ack: None }))
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// A request from a connected client; the answer has to come back before the deadline
+    pub fn internal_request_from_client(&mut self, identity: IsolateIdentity, transaction_id: String, request_id: String, data: String, timeout_ms: Option<u64>) -> Vec<MasterEventDispatch> {
+        let key = (identity.clone(), request_id.clone());
+        let timeout_ms = timeout_ms.unwrap_or(DEFAULT_REQUEST_TIMEOUT_MS);
+        let check = match self.check_can_send(&identity) {
+            Ok(_) if self.peer || self.requests.contains_key(&key) => Err(ExternalError::from(ErrorCode::InvalidRequest)),
+            Ok(_) if timeout_ms > MAX_REQUEST_TIMEOUT_MS => Err(ExternalError::from(ErrorCode::TimeoutOutOfRange)),
+            other => other,
+        };
+        if let Err(e) = check {
+            return vec!(DispatchToClient(identity, ClientInternalEvent::MessageFromClientResponse { transaction_id, success: false, error: Some(e) }));
+        }
+
+        self.requests.insert(key, PendingRequest { identity: identity.clone(), deadline: Instant::now() + Duration::from_millis(timeout_ms) });
         vec!(
-            DispatchToClient(identity, ClientInternalEvent::MessageFromMaster {
+            DispatchExternal(MasterExternalEvent::RequestFromClient {
+                client_id: identity.to_string(),
+                request_id,
                 data,
             }),
-            DispatchExternal(MasterExternalEvent::TransactionResult {
+            DispatchToClient(identity, ClientInternalEvent::MessageFromClientResponse {
                 transaction_id,
                 success: true,
                 error: None,
@@ -200,21 +907,726 @@ impl MasterState {
         )
     }
 
-    /// The master itself disconnected for some reason.
-    /// End the session session, notify all clients
-    pub fn external_master_disconnected(&mut self, reason: &str) -> Vec<MasterEventDispatch> {
-        self.active = false;
-
-        // Collect notifications
-        let mut notifications = Vec::new();
-        self.clients.iter().for_each(|(k, _)| {
+    /// The master answered a request from a client
+    pub fn external_response_to_client(&mut self, transaction_id: String, client_id: String, request_id: String, data: String) -> Vec<MasterEventDispatch> {
+        let identity = match IsolateIdentity::try_from(&client_id) {
+            Ok(s) => s,
+            Err(_) => {
+                return vec!(DispatchExternal(MasterExternalEvent::TransactionResult {
+                    transaction_id,
+                    success: false,
+                    error: Some(ExternalError::from(ErrorCode::InvalidClientIdentityToken)),
+                    join_code: None,
+                }));
+            }
+        };
+        let pending = match self.requests.remove(&(identity, request_id.clone())) {
+            Some(pending) => pending,
+            None => {
+                return vec!(DispatchExternal(MasterExternalEvent::TransactionResult {
+                    transaction_id,
+                    success: false,
+                    error: Some(ExternalError::from(ErrorCode::NoMatchingRequest)),
+                    join_code: None,
+                }));
+            }
+        };
+        let mut response: Vec<MasterEventDispatch> = self.respond_to_client(pending.identity, ClientInternalEvent::ResponseToClient { request_id, data, error: None })
+            .into_iter()
+            .collect();
+        response.push(DispatchExternal(MasterExternalEvent::TransactionResult { transaction_id, success: true, error: None, join_code: None }));
+        response
+    }
+
+    /// A client acknowledged a message; resolve the transaction that sent it
+    pub fn internal_message_ack(&mut self, transaction_id: String, identity: IsolateIdentity) -> MasterEventDispatch {
+        match self.master_acks.get(&transaction_id) {
+            Some(pending) if pending.identity == identity => {
+                self.master_acks.remove(&transaction_id);
+                DispatchExternal(MasterExternalEvent::TransactionResult { transaction_id, success: true, error: None, join_code: None })
+            }
+            _ => MasterEventDispatch::DispatchNone
+        }
+    }
+
+    /// The external master acknowledged a message from a client; resolve the client's transaction
+    pub fn external_ack_message(&mut self, transaction_id: String, client_id: String, sequence: u64) -> Vec<MasterEventDispatch> {
+        let identity = match IsolateIdentity::try_from(&client_id) {
+            Ok(s) => s,
+            Err(_) => {
+                return vec!(DispatchExternal(MasterExternalEvent::TransactionResult {
+                    transaction_id,
+                    success: false,
+                    error: Some(ExternalError::from(ErrorCode::InvalidClientIdentityToken)),
+                    join_code: None,
+                }));
+            }
+        };
+        match self.client_acks.remove(&(identity, sequence)) {
+            Some(pending) => vec!(
+                DispatchToClient(pending.identity, ClientInternalEvent::MessageFromClientResponse {
+                    transaction_id: pending.transaction_id,
+                    success: true,
+                    error: None,
+                }),
+                DispatchExternal(MasterExternalEvent::TransactionResult { transaction_id, success: true, error: None, join_code: None })
+            ),
+            None => vec!(DispatchExternal(MasterExternalEvent::TransactionResult {
+                transaction_id,
+                success: false,
+                error: Some(ExternalError::from(ErrorCode::NoMatchingMessage)),
+                join_code: None,
+            }))
+        }
+    }
+
+    /// New message from master to every connected client, except the excluded ones
+    pub fn external_broadcast_to_clients(&mut self, transaction_id: String, data: String, exclude: Vec<String>, retain: bool) -> Vec<MasterEventDispatch> {
+        if retain {
+            self.history.push(&data);
+        }
+        let targets = self.clients.keys()
+            .chain(self.offline.keys())
+            .map(|k| k.to_string())
+            .filter(|k| !exclude.contains(k))
+            .collect();
+        self.fan_out(transaction_id, targets, data)
+    }
+
+    /// New message from master to a set of connected clients
+    pub fn external_multicast_to_clients(&mut self, transaction_id: String, client_ids: Vec<String>, data: String) -> Vec<MasterEventDispatch> {
+        self.fan_out(transaction_id, client_ids, data)
+    }
+
+    /// Move a connected client into another session; the result comes back later as a TransferResult
+    pub fn external_transfer_client(&mut self, transaction_id: String, client_id: String, target_session_id: String) -> Vec<MasterEventDispatch> {
+        let identity = match self.client_identity(&client_id) {
+            Ok(identity) => identity,
+            Err(e) => {
+                return vec!(DispatchExternal(MasterExternalEvent::TransactionResult { transaction_id, success: false, error: Some(e), join_code: None }));
+            }
+        };
+        if target_session_id == self.name {
+            return vec!(DispatchExternal(MasterExternalEvent::TransactionResult {
+                transaction_id,
+                success: false,
+                error: Some(ExternalError::from(ErrorCode::TransferFailed)),
+                join_code: None,
+            }));
+        }
+
+        let client = self.remove_client(&identity).unwrap();
+        self.logger.info(format!("Client transferred to {}", target_session_id));
+        let mut response = vec!(DispatchToClient(identity, ClientInternalEvent::TransferToSession {
+            transaction_id,
+            session_id: target_session_id,
+            source_session_id: self.name.clone(),
+            role: client.role,
+        }));
+        response.extend(self.admit_waiting());
+        response
+    }
+
+    /// A client we transferred away reported how it went
+    pub fn internal_transfer_result(&self, transaction_id: String, success: bool, error: Option<ExternalError>) -> MasterEventDispatch {
+        DispatchExternal(MasterExternalEvent::TransactionResult { transaction_id, success, error, join_code: None })
+    }
+
+    /// New message from master to the master of another session
+    pub fn external_message_to_master(&self, transaction_id: String, session_id: String, data: String) -> MasterEventDispatch {
+        // Messages are sent from this session's name, so there has to be one
+        if self.metadata.is_none() {
+            return DispatchExternal(MasterExternalEvent::TransactionResult {
+                transaction_id,
+                success: false,
+                error: Some(ExternalError::from(ErrorCode::MasterNotInitialized)),
+                join_code: None,
+            });
+        }
+        if self.find_session(&session_id).is_none() {
+            return DispatchExternal(MasterExternalEvent::TransactionResult {
+                transaction_id,
+                success: false,
+                error: Some(ExternalError::from(ErrorCode::NoMatchingMasterId)),
+                join_code: None,
+            });
+        }
+        DispatchToSession(session_id, MasterInternalEvent::MessageFromMaster {
+            transaction_id,
+            source_session_id: self.name.clone(),
+            data,
+        })
+    }
+
+    /// Another master sent us a message; deliver it if that session is on the allowlist
+    pub fn internal_message_from_master(&self, transaction_id: String, source_session_id: String, data: String) -> Vec<MasterEventDispatch> {
+        let allowed = self.metadata.as_ref()
+            .map(|m| m.accept_master_messages_from.iter().any(|s| s == "*" || *s == source_session_id))
+            .unwrap_or(false);
+        if !allowed || self.peer {
+            return vec!(DispatchToSession(source_session_id, MasterInternalEvent::MessageToMasterResponse {
+                transaction_id,
+                success: false,
+                error: Some(ExternalError::from(ErrorCode::MasterMessageNotAllowed)),
+            }));
+        }
+        vec!(
+            DispatchExternal(MasterExternalEvent::MessageFromMaster {
+                session_id: source_session_id.clone(),
+                data,
+            }),
+            DispatchToSession(source_session_id, MasterInternalEvent::MessageToMasterResponse {
+                transaction_id,
+                success: true,
+                error: None,
+            })
+        )
+    }
+
+    /// The other master answered a message we sent it
+    pub fn internal_message_to_master_response(&self, transaction_id: String, success: bool, error: Option<ExternalError>) -> MasterEventDispatch {
+        DispatchExternal(MasterExternalEvent::TransactionResult { transaction_id, success, error, join_code: None })
+    }
+
+    /// New message from master to every connected spectator
+    pub fn external_message_to_spectators(&mut self, transaction_id: String, data: String) -> Vec<MasterEventDispatch> {
+        let targets = self.clients.iter()
+            .filter(|(_, v)| v.role == ClientRole::Spectator)
+            .map(|(k, _)| k.to_string())
+            .chain(self.offline.iter().filter(|(_, v)| v.role == ClientRole::Spectator).map(|(k, _)| k.to_string()))
+            .collect();
+        self.fan_out(transaction_id, targets, data)
+    }
+
+    /// Change the shared session state, and send the patch to every connected client
+    pub fn external_patch_state(&mut self, transaction_id: String, format: PatchFormat, patch: Value) -> Vec<MasterEventDispatch> {
+        let version = match self.shared_state.get_or_insert_with(MasterSharedState::new).apply(format, &patch) {
+            Ok(version) => version,
+            Err(e) => {
+                return vec!(DispatchExternal(MasterExternalEvent::TransactionResult { transaction_id, success: false, error: Some(e), join_code: None }));
+            }
+        };
+        let mut dispatch: Vec<MasterEventDispatch> = self.clients.keys().map(|k| {
+            DispatchToClient(k.clone(), ClientInternalEvent::StatePatch {
+                format,
+                patch: patch.clone(),
+                version,
+            })
+        }).collect();
+        dispatch.push(DispatchExternal(MasterExternalEvent::TransactionResult { transaction_id, success: true, error: None, join_code: None }));
+        dispatch
+    }
+
+    /// A connected client asked for the whole shared session state
+    pub fn internal_state_snapshot_request(&self, identity: IsolateIdentity, transaction_id: String) -> Vec<MasterEventDispatch> {
+        if !self.clients.contains_key(&identity) {
+            return vec!(DispatchToClient(identity, ClientInternalEvent::MessageFromClientResponse {
+                transaction_id,
+                success: false,
+                error: Some(ExternalError::from(ErrorCode::NoMatchingClientId)),
+            }));
+        }
+        vec!(
+            self.state_snapshot(identity.clone()),
+            DispatchToClient(identity, ClientInternalEvent::MessageFromClientResponse {
+                transaction_id,
+                success: true,
+                error: None,
+            })
+        )
+    }
+
+    /// Create a new empty group
+    pub fn external_create_group(&mut self, transaction_id: String, group: String) -> MasterEventDispatch {
+        if !self.groups.create(&group) {
+            return DispatchExternal(MasterExternalEvent::TransactionResult {
+                transaction_id,
+                success: false,
+                error: Some(ExternalError::from(ErrorCode::GroupIdConflict)),
+                join_code: None,
+            });
+        }
+        DispatchExternal(MasterExternalEvent::TransactionResult { transaction_id, success: true, error: None, join_code: None })
+    }
+
+    /// Delete a group and tell its members
+    pub fn external_delete_group(&mut self, transaction_id: String, group: String) -> Vec<MasterEventDispatch> {
+        let members = match self.groups.delete(&group) {
+            Some(m) => m,
+            None => {
+                return vec!(DispatchExternal(MasterExternalEvent::TransactionResult {
+                    transaction_id,
+                    success: false,
+                    error: Some(ExternalError::from(ErrorCode::NoMatchingGroup)),
+                    join_code: None,
+                }));
+            }
+        };
+        let mut dispatch: Vec<MasterEventDispatch> = members.into_iter()
+            .map(|identity| self.membership_update(identity))
+            .collect();
+        dispatch.push(DispatchExternal(MasterExternalEvent::TransactionResult { transaction_id, success: true, error: None, join_code: None }));
+        dispatch
+    }
+
+    /// Add a connected client to a group
+    pub fn external_add_to_group(&mut self, transaction_id: String, group: String, client_id: String) -> Vec<MasterEventDispatch> {
+        let identity = match self.client_identity(&client_id) {
+            Ok(identity) => identity,
+            Err(e) => {
+                return vec!(DispatchExternal(MasterExternalEvent::TransactionResult { transaction_id, success: false, error: Some(e), join_code: None }));
+            }
+        };
+        if !self.groups.add(&group, &identity) {
+            return vec!(DispatchExternal(MasterExternalEvent::TransactionResult {
+                transaction_id,
+                success: false,
+                error: Some(ExternalError::from(ErrorCode::NoMatchingGroup)),
+                join_code: None,
+            }));
+        }
+        vec!(
+            self.membership_update(identity),
+            DispatchExternal(MasterExternalEvent::TransactionResult { transaction_id, success: true, error: None, join_code: None })
+        )
+    }
+
+    /// Remove a connected client from a group
+    pub fn external_remove_from_group(&mut self, transaction_id: String, group: String, client_id: String) -> Vec<MasterEventDispatch> {
+        let identity = match self.client_identity(&client_id) {
+            Ok(identity) => identity,
+            Err(e) => {
+                return vec!(DispatchExternal(MasterExternalEvent::TransactionResult { transaction_id, success: false, error: Some(e), join_code: None }));
+            }
+        };
+        if !self.groups.remove(&group, &identity) {
+            return vec!(DispatchExternal(MasterExternalEvent::TransactionResult {
+                transaction_id,
+                success: false,
+                error: Some(ExternalError::from(ErrorCode::NoMatchingGroup)),
+                join_code: None,
+            }));
+        }
+        vec!(
+            self.membership_update(identity),
+            DispatchExternal(MasterExternalEvent::TransactionResult { transaction_id, success: true, error: None, join_code: None })
+        )
+    }
+
+    /// New message from master to every client in a group
+    pub fn external_message_to_group(&mut self, transaction_id: String, group: String, data: String) -> Vec<MasterEventDispatch> {
+        match self.groups.members(&group) {
+            Some(members) => {
+                let targets = members.iter().map(|k| k.to_string()).collect();
+                self.fan_out(transaction_id, targets, data)
+            }
+            None => vec!(DispatchExternal(MasterExternalEvent::TransactionResult {
+                transaction_id,
+                success: false,
+                error: Some(ExternalError::from(ErrorCode::NoMatchingGroup)),
+                join_code: None,
+            }))
+        }
+    }
+
+    /// The master itself disconnected for some reason.
+    /// End the session session, notify all clients
+    pub fn external_master_disconnected(&mut self, reason: &str) -> Vec<MasterEventDispatch> {
+        self.active = false;
+
+        // Collect notifications
+        let mut notifications = Vec::new();
+        self.clients.iter().for_each(|(k, _)| {
             notifications.push(MasterEventDispatch::DispatchToClient(k.clone(), ClientInternalEvent::MasterDisconnected {
                 reason: reason.to_string()
             }))
         });
+        self.pending_joins.drain().for_each(|(k, v)| {
+            notifications.push(MasterEventDispatch::DispatchToClient(k, ClientJoinResponse {
+                transaction_id: v.transaction_id,
+                success: false,
+                error: Some(ExternalError::from(ErrorCode::NotActive)),
+            }))
+        });
+        self.waitlist.drain(..).for_each(|w| {
+            notifications.push(MasterEventDispatch::DispatchToClient(w.identity, ClientJoinResponse {
+                transaction_id: w.transaction_id,
+                success: false,
+                error: Some(ExternalError::from(ErrorCode::NotActive)),
+            }))
+        });
 
         self.logger.info(format!("Master disconnected: {}", reason));
-        let _ = self.manager.remove_session(&self.name);
+        let _ = self.manager.remove_session(&self.tenant, &self.name);
         return notifications;
     }
-}
\ No newline at end of file
+
+    /// Send the same message to every target and resolve the transaction once.
+    /// Targets whose connection dropped get it queued for when they resume; any target that isn't
+    /// in the session, or whose queue is full, is reported back as undelivered.
+    fn fan_out(&mut self, transaction_id: String, targets: Vec<String>, data: String) -> Vec<MasterEventDispatch> {
+        let limit = self.offline_queue_limit();
+        let mut dispatch = Vec::new();
+        let mut undelivered = Vec::new();
+        for client_id in targets {
+            let identity = match IsolateIdentity::try_from(&client_id) {
+                Ok(identity) => identity,
+                Err(_) => {
+                    undelivered.push(client_id);
+                    continue;
+                }
+            };
+            if self.clients.contains_key(&identity) {
+                dispatch.push(DispatchToClient(identity, ClientInternalEvent::MessageFromMaster {
+                    data: data.clone(),
+                    payload: None,
+                    ack: None,
+                }));
+                continue;
+            }
+            match self.offline.get_mut(&identity) {
+                Some(offline) if offline.queue.len() < limit => {
+                    offline.queue.push_back(QueuedMessage { transaction_id: None, data: data.clone(), payload: None, ack: false, queued: Instant::now() });
+                }
+                _ => undelivered.push(client_id),
+            }
+        }
+
+        if undelivered.is_empty() {
+            dispatch.push(DispatchExternal(MasterExternalEvent::TransactionResult { transaction_id, success: true, error: None, join_code: None }));
+        } else {
+            let mut error = ExternalError::from(ErrorCode::PartialDelivery);
+            error.undelivered = undelivered;
+            dispatch.push(DispatchExternal(MasterExternalEvent::TransactionResult { transaction_id, success: false, error: Some(error), join_code: None }));
+        }
+        dispatch
+    }
+
+    /// Check the session allows peer messages, and the sender is part of it
+    fn check_peer_messaging(&self, sender: &IsolateIdentity) -> Result<(), ExternalError> {
+        self.check_can_send(sender)?;
+        if !self.metadata.as_ref().map(|m| m.peer_messaging).unwrap_or(false) {
+            return Err(ExternalError::from(ErrorCode::PeerMessagingDisabled));
+        }
+        Ok(())
+    }
+
+    /// Deliver a message from one client to others, copying the master if it moderates
+    fn route_to_peers(&self, sender: IsolateIdentity, transaction_id: String, recipients: Vec<IsolateIdentity>, data: String) -> Vec<MasterEventDispatch> {
+        let mut dispatch = self.peer_deliveries(&sender, recipients, data);
+        dispatch.push(DispatchToClient(sender, ClientInternalEvent::MessageFromClientResponse { transaction_id, success: true, error: None }));
+        dispatch
+    }
+
+    /// The messages that carry a peer message to its recipients, and to the master if it moderates them
+    fn peer_deliveries(&self, sender: &IsolateIdentity, recipients: Vec<IsolateIdentity>, data: String) -> Vec<MasterEventDispatch> {
+        let mut dispatch = Vec::new();
+        if self.metadata.as_ref().map(|m| m.moderate_peer_messages).unwrap_or(false) {
+            dispatch.push(DispatchExternal(MasterExternalEvent::PeerMessage {
+                client_id: sender.to_string(),
+                recipients: recipients.iter().map(|k| k.to_string()).collect(),
+                data: data.clone(),
+            }));
+        }
+        for identity in recipients {
+            dispatch.push(DispatchToClient(identity, ClientInternalEvent::MessageFromPeer {
+                client_id: sender.to_string(),
+                data: data.clone(),
+            }));
+        }
+        dispatch
+    }
+
+    /// Check if there is room for another client in the given role
+    fn check_capacity(&self, role: ClientRole, name: &str, auth_key: Option<&String>) -> Result<(), ExternalError> {
+        let metadata = match self.metadata.as_ref() {
+            Some(m) => m,
+            None => {
+                self.logger.warn("Master has no metadata set!");
+                return Ok(());
+            }
+        };
+        // Joins waiting for approval have a seat each, so approving them can't overfill the session
+        let count = self.clients.values().filter(|c| c.role == role).count()
+            + self.offline.values().filter(|c| c.role == role).count()
+            + self.pending_joins.values().filter(|p| p.role == role).count();
+
+        // Reserved seats are taken, unless one of them is reserved for this client
+        let mut reserved = self.reservations.len();
+        if self.find_reservation(name, auth_key).is_some() {
+            reserved -= 1;
+        }
+
+        match role {
+            ClientRole::Player if count + reserved >= metadata.max_clients as usize => Err(ExternalError::from(ErrorCode::ClientLimitExceeded)),
+            ClientRole::Spectator if count >= metadata.max_spectators as usize => Err(ExternalError::from(ErrorCode::SpectatorLimitExceeded)),
+            _ => Ok(())
+        }
+    }
+
+    /// The number of players, counting those that are disconnected but may resume
+    fn player_count(&self) -> usize {
+        self.clients.values().filter(|c| c.role == ClientRole::Player).count()
+            + self.offline.values().filter(|c| c.role == ClientRole::Player).count()
+    }
+
+    /// How many messages can be queued for each disconnected client
+    fn offline_queue_limit(&self) -> usize {
+        self.metadata.as_ref().map(|m| m.offline_queue_limit as usize).unwrap_or(0)
+    }
+
+    /// How long disconnected clients and their queued messages are kept
+    fn offline_queue_ttl(&self) -> Duration {
+        Duration::from_millis(self.metadata.as_ref().map(|m| m.offline_queue_ttl_ms).unwrap_or(0))
+    }
+
+    /// The longest a message from the master can wait for its result: queued until the client's slot
+    /// is given up, then delivered and waiting for an ack
+    pub fn longest_message_wait(&self) -> Duration {
+        self.offline_queue_ttl() + Duration::from_millis(MESSAGE_ACK_TIMEOUT_MS)
+    }
+
+    /// Find the seat reserved for a client, by name or by the key it authorized with
+    fn find_reservation(&self, name: &str, auth_key: Option<&String>) -> Option<String> {
+        self.reservations.keys()
+            .find(|k| *k == name || auth_key.map(|a| a == *k).unwrap_or(false))
+            .cloned()
+    }
+
+    /// Is this client on the waitlist?
+    fn is_waiting(&self, identity: &IsolateIdentity) -> bool {
+        self.waitlist.iter().any(|w| w.identity == *identity)
+    }
+
+    /// Take a client off the waitlist
+    fn remove_waiting(&mut self, identity: &IsolateIdentity) -> Option<WaitingClient> {
+        let index = self.waitlist.iter().position(|w| w.identity == *identity)?;
+        self.waitlist.remove(index)
+    }
+
+    /// Let in as many waiting clients as there are free slots, in the order they arrived
+    fn admit_waiting(&mut self) -> Vec<MasterEventDispatch> {
+        let mut notifications = Vec::new();
+        let mut still_waiting = VecDeque::new();
+        let mut admitted = false;
+        while let Some(waiting) = self.waitlist.pop_front() {
+            if self.check_capacity(waiting.role, &waiting.name, waiting.auth_key.as_ref()).is_err() {
+                still_waiting.push_back(waiting);
+                continue;
+            }
+            admitted = true;
+            notifications.extend(self.accept_join(&waiting.name, waiting.transaction_id, waiting.identity, waiting.metadata, waiting.auth_key, waiting.role));
+        }
+        self.waitlist = still_waiting;
+
+        if admitted {
+            notifications.extend(self.waitlist_positions());
+        }
+        notifications
+    }
+
+    /// Tell every waiting client where it is in the queue
+    fn waitlist_positions(&self) -> Vec<MasterEventDispatch> {
+        self.waitlist.iter().enumerate().map(|(i, w)| {
+            DispatchToClient(w.identity.clone(), ClientInternalEvent::WaitlistPosition {
+                transaction_id: w.transaction_id.clone(),
+                position: i as u32 + 1,
+            })
+        }).collect()
+    }
+
+    /// Check if a connected client is allowed to send messages
+    fn check_can_send(&self, identity: &IsolateIdentity) -> Result<(), ExternalError> {
+        match self.clients.get(identity) {
+            Some(c) if c.role == ClientRole::Spectator && !self.metadata.as_ref().map(|m| m.spectators_can_send).unwrap_or(false) => {
+                Err(ExternalError::from(ErrorCode::SpectatorCannotSend))
+            }
+            Some(_) => Ok(()),
+            None => Err(ExternalError::from(ErrorCode::NoMatchingClientId)),
+        }
+    }
+
+    /// Remove a client from the session and every group it was in
+    fn remove_client(&mut self, identity: &IsolateIdentity) -> Option<MasterClient> {
+        self.groups.remove_all(identity);
+        self.requests.retain(|_, v| v.identity != *identity);
+        let client = self.clients.remove(identity);
+        self.occupancy_changed();
+        client
+    }
+
+    /// Let the session manager know how many player slots are taken, for matchmaking
+    fn occupancy_changed(&self) {
+        if self.peer || !self.active {
+            return;
+        }
+        let taken = self.player_count() + self.reservations.len();
+        if self.manager.update_occupancy(&self.tenant, &self.name, taken as u32).is_err() {
+            self.logger.warn("Failed to update session occupancy");
+        }
+    }
+
+    /// The current shared session state, for a single client; sessions that never set any state are null at version 0
+    fn state_snapshot(&self, identity: IsolateIdentity) -> MasterEventDispatch {
+        let (state, version) = match &self.shared_state {
+            Some(s) => (s.document().clone(), s.version()),
+            None => (Value::Null, 0),
+        };
+        DispatchToClient(identity, ClientInternalEvent::StateSnapshot { state, version })
+    }
+
+    /// Send a message from the master to a connected client; the transaction resolves now, or once the client acknowledges it
+    fn deliver_to_client(&mut self, identity: IsolateIdentity, transaction_id: String, data: String, payload: Option<Box<RawValue>>, ack: bool) -> Vec<MasterEventDispatch> {
+        if ack {
+            self.master_acks.insert(transaction_id.clone(), PendingAck { transaction_id: transaction_id.clone(), identity: identity.clone(), sent: Instant::now() });
+            return vec!(DispatchToClient(identity, ClientInternalEvent::MessageFromMaster { data, payload, ack: Some(transaction_id) }));
+        }
+        vec!(
+            DispatchToClient(identity, ClientInternalEvent::MessageFromMaster { data, payload, ack: None }),
+            DispatchExternal(MasterExternalEvent::TransactionResult { transaction_id, success: true, error: None, join_code: None })
+        )
+    }
+
+    /// Send an answer to a client's request, or hold it until the client resumes if its connection dropped
+    fn respond_to_client(&mut self, identity: IsolateIdentity, response: ClientInternalEvent) -> Option<MasterEventDispatch> {
+        match self.offline.get_mut(&identity) {
+            Some(offline) => {
+                offline.responses.push(response);
+                None
+            }
+            None => Some(DispatchToClient(identity, response)),
+        }
+    }
+
+    /// A client resumed on a new connection; move its groups over, and send answers to anything still open to it.
+    /// The master keeps answering requests and acks with the client id they arrived with.
+    fn rename_client(&mut self, previous: &IsolateIdentity, identity: &IsolateIdentity) {
+        self.groups.rename(previous, identity);
+        self.requests.values_mut()
+            .filter(|pending| pending.identity == *previous)
+            .for_each(|pending| pending.identity = identity.clone());
+        self.client_acks.values_mut()
+            .filter(|pending| pending.identity == *previous)
+            .for_each(|pending| pending.identity = identity.clone());
+        self.master_acks.values_mut()
+            .filter(|pending| pending.identity == *previous)
+            .for_each(|pending| pending.identity = identity.clone());
+    }
+
+    /// Tell a client which groups it belongs to now
+    fn membership_update(&self, identity: IsolateIdentity) -> MasterEventDispatch {
+        let groups = self.groups.memberships(&identity);
+        DispatchToClient(identity, ClientInternalEvent::GroupMembership { groups })
+    }
+
+    /// Check if a client is on the ban list, by name, by the connection it was kicked from, or by its auth key.
+    /// Auth keys are often shared by every client of an app, so they are only banned when the master asks for it.
+    fn is_banned(&self, name: &str, identity: &IsolateIdentity, auth_key: Option<&String>) -> bool {
+        self.banned_names.contains(name)
+            || self.banned_identities.contains(identity)
+            || auth_key.map(|k| self.banned_keys.contains(k)).unwrap_or(false)
+    }
+
+    /// Add a client to the session and notify everyone about it
+    fn admit_client(&mut self, name: &str, auth_key: Option<String>, role: ClientRole, transaction_id: String, identity: IsolateIdentity) -> Result<Vec<MasterEventDispatch>, ExternalError> {
+        let channel = self.manager.find_client(&identity)?;
+        if role == ClientRole::Player {
+            if let Some(reservation) = self.find_reservation(name, auth_key.as_ref()) {
+                self.reservations.remove(&reservation);
+            }
+        }
+        let mut notifications = vec!(
+            DispatchExternal(MasterExternalEvent::ClientJoined { name: name.to_string(), client_id: identity.to_string(), role }),
+            DispatchToClient(identity.clone(), ClientJoinResponse { transaction_id, success: true, error: None })
+        );
+
+        // Clients that may resume after a dropped connection need something to prove who they are
+        let resume_token = match self.offline_queue_limit() > 0 && !self.peer {
+            true => Some(OfflineClient::generate_resume_token()),
+            false => None,
+        };
+        if let Some(token) = resume_token.as_ref() {
+            notifications.push(DispatchToClient(identity.clone(), ClientInternalEvent::ResumeToken { token: token.clone() }));
+        }
+
+        // In a peer session, the members and the new client need to know about each other
+        if self.peer {
+            self.clients.iter().for_each(|(k, v)| {
+                notifications.push(DispatchToClient(k.clone(), ClientInternalEvent::PeerJoined {
+                    client_id: identity.to_string(),
+                    name: name.to_string(),
+                }));
+                notifications.push(DispatchToClient(identity.clone(), ClientInternalEvent::PeerJoined {
+                    client_id: k.to_string(),
+                    name: v.name.clone(),
+                }));
+            });
+        }
+
+        // Late joiners need the shared state and the retained messages before any live traffic;
+        // everything is dispatched in order, so nothing sent later can overtake them
+        if self.shared_state.is_some() {
+            notifications.push(self.state_snapshot(identity.clone()));
+        }
+        self.history.messages().for_each(|data| {
+            notifications.push(DispatchToClient(identity.clone(), ClientInternalEvent::MessageFromMaster {
+                data: data.clone(),
+                payload: None,
+                ack: None,
+            }));
+        });
+
+        self.clients.insert(identity, MasterClient {
+            channel,
+            name: name.to_string(),
+            auth_key,
+            role,
+            sequence: 0,
+            resume_token,
+        });
+        self.occupancy_changed();
+        Ok(notifications)
+    }
+
+    /// Resolve the identity of a connected client
+    fn client_identity(&self, client_id: &str) -> Result<IsolateIdentity, ExternalError> {
+        let identity = match IsolateIdentity::try_from(&client_id.to_string()) {
+            Ok(s) => s,
+            Err(_) => return Err(ExternalError::from(ErrorCode::InvalidClientIdentityToken)),
+        };
+        if !self.clients.contains_key(&identity) {
+            return Err(ExternalError::from(ErrorCode::NoMatchingClientId));
+        }
+        Ok(identity)
+    }
+
+    /// Resolve the identity of a client that is waiting for approval
+    fn pending_identity(&self, client_id: &str) -> Result<IsolateIdentity, ExternalError> {
+        let identity = match IsolateIdentity::try_from(&client_id.to_string()) {
+            Ok(s) => s,
+            Err(_) => return Err(ExternalError::from(ErrorCode::InvalidClientIdentityToken)),
+        };
+        if !self.pending_joins.contains_key(&identity) {
+            return Err(ExternalError::from(ErrorCode::NoMatchingClientId));
+        }
+        Ok(identity)
+    }
+}
+
+/// The result for a queued message that was never delivered
+fn message_expired(transaction_id: String) -> MasterEventDispatch {
+    DispatchExternal(MasterExternalEvent::TransactionResult {
+        transaction_id,
+        success: false,
+        error: Some(ExternalError::from(ErrorCode::MessageExpired)),
+        join_code: None,
+    })
+}
+
+/// Peer messages only carry a string, so a structured payload goes to peers in its serialized form
+fn peer_data(data: String, payload: Option<Box<RawValue>>) -> String {
+    match payload {
+        Some(payload) => payload.get().to_string(),
+        None => data,
+    }
+}
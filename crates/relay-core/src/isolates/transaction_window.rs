@@ -0,0 +1,76 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// How long a transaction can go unanswered before a retry of it is processed again, unless the isolate
+/// knows something can take longer. This is longer than anything waits for an ack, so it only matters if the result was lost.
+const PENDING_TRANSACTION_TIMEOUT_MS: u64 = 60000;
+
+/// How a transaction id compares to the ones seen recently
+pub enum TransactionSeen<T> {
+    /// Never seen, or seen so long ago without an answer that it's treated as new; it is now being tracked
+    New,
+
+    /// Seen, but its result hasn't been sent yet
+    Pending,
+
+    /// Seen and answered with this result
+    Completed(T),
+}
+
+struct TrackedTransaction<T> {
+    seen: Instant,
+    result: Option<T>,
+}
+
+/// The most recent transaction ids an isolate processed and the results it answered them with,
+/// so a request that is retried after a timeout is answered again rather than processed twice.
+pub struct TransactionWindow<T> {
+    order: VecDeque<String>,
+    results: HashMap<String, TrackedTransaction<T>>,
+    capacity: usize,
+    pending_timeout: Duration,
+}
+
+impl<T: Clone> TransactionWindow<T> {
+    pub fn new(capacity: usize) -> TransactionWindow<T> {
+        TransactionWindow {
+            order: VecDeque::new(),
+            results: HashMap::new(),
+            capacity,
+            pending_timeout: Duration::from_millis(PENDING_TRANSACTION_TIMEOUT_MS),
+        }
+    }
+
+    /// Keep unanswered transactions pending for at least this long, eg. while a message waits in an offline queue
+    pub fn pending_at_least(&mut self, timeout: Duration) {
+        self.pending_timeout = self.pending_timeout.max(timeout);
+    }
+
+    /// Check a transaction id, tracking it if it is new and dropping the oldest one to stay inside the window
+    pub fn check(&mut self, transaction_id: &str) -> TransactionSeen<T> {
+        match self.results.get_mut(transaction_id) {
+            Some(TrackedTransaction { seen: _, result: Some(result) }) => return TransactionSeen::Completed(result.clone()),
+            Some(tracked) if tracked.seen.elapsed() < self.pending_timeout => return TransactionSeen::Pending,
+            Some(tracked) => {
+                tracked.seen = Instant::now();
+                return TransactionSeen::New;
+            }
+            None => {}
+        }
+        self.order.push_back(transaction_id.to_string());
+        self.results.insert(transaction_id.to_string(), TrackedTransaction { seen: Instant::now(), result: None });
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.results.remove(&oldest);
+            }
+        }
+        TransactionSeen::New
+    }
+
+    /// Record the result a tracked transaction was answered with
+    pub fn complete(&mut self, transaction_id: &str, result: &T) {
+        if let Some(tracked) = self.results.get_mut(transaction_id) {
+            tracked.result = Some(result.clone());
+        }
+    }
+}
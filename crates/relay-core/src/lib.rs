@@ -6,4 +6,7 @@ pub mod infrastructure;
 pub const MASTER: &'static str = "master";
 pub const CLIENT: &'static str = "client";
 
+/// The tenant for connections that were never told which tenant they belong to
+pub const DEFAULT_TENANT: &'static str = "default";
+
 pub(crate) const NO_IDENTITY: &'static str = "no identity";
@@ -1,3 +1,9 @@
 pub mod client_metadata;
+pub mod client_role;
 pub mod master_metadata;
-pub mod external_error;
\ No newline at end of file
+pub mod patch_format;
+pub mod raw_payload;
+pub mod session_filter;
+pub mod external_error;
+pub mod wire_encoding;
+pub mod wire_compression;
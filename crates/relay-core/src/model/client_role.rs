@@ -0,0 +1,17 @@
+use serde::{Deserialize, Serialize};
+
+/// What a client can do once it has joined a session
+#[derive(Serialize, Deserialize, Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ClientRole {
+    /// A normal client, counted against max_clients
+    Player,
+
+    /// A watcher, counted against max_spectators
+    Spectator,
+}
+
+impl Default for ClientRole {
+    fn default() -> Self {
+        ClientRole::Player
+    }
+}
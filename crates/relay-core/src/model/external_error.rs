@@ -19,6 +19,33 @@ pub enum ErrorCode {
     InvalidRequest,
     SyncError,
     Unknown,
+    JoinRejected,
+    JoinRequestExpired,
+    ClientBanned,
+    PartialDelivery,
+    NoMatchingGroup,
+    GroupIdConflict,
+    PeerMessagingDisabled,
+    PeerSessionFailed,
+    SpectatorLimitExceeded,
+    SpectatorCannotSend,
+    AlreadyInSession,
+    TransferFailed,
+    MasterMessageNotAllowed,
+    NoMatchingReservation,
+    LeftWaitlist,
+    NoMatchingSession,
+    InvalidStatePatch,
+    OfflineQueueFull,
+    MessageExpired,
+    NoMatchingMessage,
+    AckTimeout,
+    RequestTimeout,
+    NoMatchingRequest,
+    TimeoutOutOfRange,
+    InvalidTransactionId,
+    LeftBeforeApproval,
+    MasterNotInitialized,
 }
 
 /// For sending external errors
@@ -26,6 +53,10 @@ pub enum ErrorCode {
 pub struct ExternalError {
     pub error_code: i32,
     pub error_reason: String,
+
+    /// For fan out requests, the client ids the message could not be delivered to
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub undelivered: Vec<String>,
 }
 
 impl Error for ExternalError {}
@@ -54,8 +85,36 @@ impl From<ErrorCode> for ExternalError {
                 ErrorCode::ArcMutexFailure => "Mutex error",
                 ErrorCode::AuthFailed => "Internal error",
                 ErrorCode::Unknown => "Internal error",
+                ErrorCode::JoinRejected => "The master rejected the request to join",
+                ErrorCode::JoinRequestExpired => "The master did not answer the request to join in time",
+                ErrorCode::ClientBanned => "The client is banned from this session",
+                ErrorCode::PartialDelivery => "The message could not be delivered to some clients",
+                ErrorCode::NoMatchingGroup => "No group found matching the requested name",
+                ErrorCode::GroupIdConflict => "The requested group name is already in use",
+                ErrorCode::PeerMessagingDisabled => "This session does not allow messages between clients",
+                ErrorCode::PeerSessionFailed => "Unable to start a peer session",
+                ErrorCode::SpectatorLimitExceeded => "Too many connected spectators, no free slots",
+                ErrorCode::SpectatorCannotSend => "Spectators are not allowed to send messages in this session",
+                ErrorCode::AlreadyInSession => "The client must leave its current session first",
+                ErrorCode::TransferFailed => "The client could not be moved to the target session",
+                ErrorCode::MasterMessageNotAllowed => "The target session does not accept messages from this session",
+                ErrorCode::NoMatchingReservation => "No seat is reserved for that client",
+                ErrorCode::LeftWaitlist => "The client left the waitlist before a slot was free",
+                ErrorCode::NoMatchingSession => "No active session matches the requested filter",
+                ErrorCode::InvalidStatePatch => "The patch could not be applied to the session state",
+                ErrorCode::OfflineQueueFull => "Too many messages are already queued for the disconnected client",
+                ErrorCode::MessageExpired => "The disconnected client did not resume before the message expired",
+                ErrorCode::NoMatchingMessage => "No message is waiting for an ack with that sequence number",
+                ErrorCode::AckTimeout => "The message was not acknowledged in time",
+                ErrorCode::RequestTimeout => "The master did not answer the request in time",
+                ErrorCode::NoMatchingRequest => "No request from that client is waiting for an answer with that id",
+                ErrorCode::TimeoutOutOfRange => "The requested timeout is longer than the relay allows",
+                ErrorCode::InvalidTransactionId => "The transaction id is empty, too long or contains control characters",
+                ErrorCode::LeftBeforeApproval => "The client left before the master approved its join",
+                ErrorCode::MasterNotInitialized => "The master has not started a session yet",
             }
             .to_string(),
+            undelivered: Vec::new(),
         }
     }
 }
@@ -67,12 +126,21 @@ impl From<SessionManagerError> for ExternalError {
             SessionManagerError::NameAlreadyInUse => {
                 ExternalError::from(ErrorCode::MasterIdConflict)
             }
+            SessionManagerError::NameMatchesJoinCode => {
+                ExternalError::from(ErrorCode::MasterIdConflict)
+            }
             SessionManagerError::NoMatchingMaster => {
                 ExternalError::from(ErrorCode::NoMatchingMasterId)
             }
             SessionManagerError::NoMatchingClient => {
                 ExternalError::from(ErrorCode::NoMatchingClientId)
             }
+            SessionManagerError::PeerSessionFailed => {
+                ExternalError::from(ErrorCode::PeerSessionFailed)
+            }
+            SessionManagerError::NoMatchingSession => {
+                ExternalError::from(ErrorCode::NoMatchingSession)
+            }
         };
     }
 }
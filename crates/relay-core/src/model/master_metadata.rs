@@ -1,10 +1,76 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct MasterMetadata {
     /// The name of this master
     pub master_id: String,
 
     /// The maximum clients count to allow
     pub max_clients: u32,
+
+    /// If set, every join request must be approved by the master before the client is admitted
+    #[serde(default)]
+    pub approve_joins: bool,
+
+    /// How long a join request waits for approval before it fails; uses the default if not set
+    #[serde(default)]
+    pub join_approval_timeout_ms: Option<u64>,
+
+    /// Client names that may not join this session
+    #[serde(default)]
+    pub banned: Vec<String>,
+
+    /// Auth keys that may not join this session; every client that connects with one of these keys is kept out
+    #[serde(default)]
+    pub banned_keys: Vec<String>,
+
+    /// If set, clients may send messages directly to each other
+    #[serde(default)]
+    pub peer_messaging: bool,
+
+    /// If set, the master gets a copy of every message sent between clients
+    #[serde(default)]
+    pub moderate_peer_messages: bool,
+
+    /// The maximum spectators count to allow; spectators don't count against max_clients
+    #[serde(default)]
+    pub max_spectators: u32,
+
+    /// If set, spectators may send messages like any other client
+    #[serde(default)]
+    pub spectators_can_send: bool,
+
+    /// The sessions allowed to send messages to this master; use "*" to allow any session
+    #[serde(default)]
+    pub accept_master_messages_from: Vec<String>,
+
+    /// Labels that clients can match on when they join any session
+    #[serde(default)]
+    pub tags: Vec<String>,
+
+    /// Key value pairs that clients can match on when they join any session
+    #[serde(default)]
+    pub properties: HashMap<String, String>,
+
+    /// If set, the relay generates a short join code that clients can use in place of the session id
+    #[serde(default)]
+    pub generate_join_code: bool,
+
+    /// How many retained messages to keep for clients that join later; 0 means no limit by count
+    #[serde(default)]
+    pub history_max_messages: u32,
+
+    /// How many bytes of retained messages to keep for clients that join later; 0 means no limit by size.
+    /// If both limits are 0, messages are not retained.
+    #[serde(default)]
+    pub history_max_bytes: u32,
+
+    /// How many messages to queue for a client whose connection dropped; 0 means clients are removed as soon as they disconnect
+    #[serde(default)]
+    pub offline_queue_limit: u32,
+
+    /// How long a disconnected client can take to resume, and how long each queued message waits for it
+    #[serde(default)]
+    pub offline_queue_ttl_ms: u64,
 }
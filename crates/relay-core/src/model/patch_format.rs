@@ -0,0 +1,11 @@
+use serde::{Deserialize, Serialize};
+
+/// How a patch to the shared session state is written
+#[derive(Serialize, Deserialize, Copy, Clone, Eq, PartialEq, Debug)]
+pub enum PatchFormat {
+    /// RFC 7396; the patch is a partial document, and null removes a key
+    MergePatch,
+
+    /// RFC 6902; the patch is a list of add, remove, replace, move, copy and test operations
+    JsonPatch,
+}
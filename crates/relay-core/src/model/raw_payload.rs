@@ -0,0 +1,36 @@
+use serde::de::Error as DeError;
+use serde::ser::Error as SerError;
+use serde::{Deserialize, Deserializer, Serializer};
+use serde_json::value::RawValue;
+use serde_json::Value;
+
+/// Serde helpers for structured message payloads, which the relay holds as raw JSON and passes on without
+/// looking inside. On JSON connections the payload is written out exactly as it is; binary encodings get
+/// the structured value, so MessagePack and CBOR peers see a native map rather than a JSON string.
+///
+/// JSON connections don't come through here on the way in: WireEvent reads message events untagged, so the
+/// payload goes straight into a RawValue. The derived reader for the tagged events has already buffered the
+/// payload by the time it gets here, which is the case for MessagePack and CBOR; it is turned into raw JSON
+/// once, and turned back into a value only when it goes out on a binary connection.
+pub fn serialize<S: Serializer>(payload: &Option<Box<RawValue>>, serializer: S) -> Result<S::Ok, S::Error> {
+    match payload {
+        None => serializer.serialize_none(),
+        Some(raw) if serializer.is_human_readable() => serializer.serialize_some(raw),
+        Some(raw) => {
+            let value: Value = serde_json::from_str(raw.get()).map_err(S::Error::custom)?;
+            serializer.serialize_some(&value)
+        }
+    }
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Box<RawValue>>, D::Error> {
+    match Option::<Value>::deserialize(deserializer)? {
+        Some(value) => Ok(Some(to_raw(&value).map_err(D::Error::custom)?)),
+        None => Ok(None),
+    }
+}
+
+/// Write a value as a raw payload
+pub fn to_raw<T: serde::Serialize>(value: &T) -> Result<Box<RawValue>, serde_json::Error> {
+    serde_json::value::to_raw_value(value)
+}
@@ -0,0 +1,28 @@
+use crate::model::master_metadata::MasterMetadata;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Criteria a client uses to pick any suitable session to join
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct SessionFilter {
+    /// Every one of these tags must be on the session
+    #[serde(default)]
+    pub tags: Vec<String>,
+
+    /// Every one of these properties must be on the session, with the same value
+    #[serde(default)]
+    pub properties: HashMap<String, String>,
+
+    /// The session must have at least this many free player slots; a session with no free slots never matches
+    #[serde(default)]
+    pub min_free_slots: u32,
+}
+
+impl SessionFilter {
+    /// Check if a session with the given metadata and free slots matches this filter
+    pub fn matches(&self, metadata: &MasterMetadata, free_slots: u32) -> bool {
+        free_slots >= self.min_free_slots.max(1)
+            && self.tags.iter().all(|t| metadata.tags.contains(t))
+            && self.properties.iter().all(|(k, v)| metadata.properties.get(k) == Some(v))
+    }
+}
@@ -0,0 +1,182 @@
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress, Status};
+
+/// The deflate level connections use unless they're told otherwise
+pub const DEFAULT_COMPRESSION_LEVEL: u32 = 6;
+
+/// The largest message a connection accepts unless it's told otherwise, compressed or not
+pub const DEFAULT_MAX_MESSAGE_BYTES: usize = 16 * 1024 * 1024;
+
+/// The websocket extension name, as it appears in the handshake headers
+const EXTENSION: &'static str = "permessage-deflate";
+
+/// What the server agrees to; every message it sends is compressed on its own, so it keeps no window between them
+const EXTENSION_RESPONSE: &'static str = "permessage-deflate; server_no_context_takeover";
+
+/// The empty block a sync flush ends with; it's left off the wire, and put back before inflating
+const TRAILER: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+/// Why a compressed message couldn't be read
+#[derive(Debug)]
+pub enum WireCompressionError {
+    /// The message, compressed or inflated, is bigger than the connection accepts
+    MessageTooLarge,
+
+    /// The message isn't valid deflate data
+    Corrupt(String),
+}
+
+/// permessage-deflate (RFC 7692) state for a single connection.
+/// Messages under the threshold go out as they are; anything the peer compressed is inflated on the way in,
+/// up to the maximum message size, so a small message can't inflate into an unbounded one.
+///
+/// The ws crate has its own permessage-deflate handler, but it compresses every message at a fixed level,
+/// has no limit on what it inflates, and needs a native zlib; this keeps the threshold, level and limit.
+pub struct WireCompression {
+    threshold: usize,
+    max_message_bytes: usize,
+    negotiated: bool,
+    compressor: Compress,
+    decompressor: Decompress,
+    fragments: Option<(u8, Vec<u8>)>,
+    raw_bytes: u64,
+    sent_bytes: u64,
+}
+
+impl WireCompression {
+    pub fn new(threshold: usize, level: u32, max_message_bytes: usize) -> WireCompression {
+        WireCompression {
+            threshold,
+            max_message_bytes,
+            negotiated: false,
+            compressor: Compress::new(Compression::new(level), false),
+            decompressor: Decompress::new(false),
+            fragments: None,
+            raw_bytes: 0,
+            sent_bytes: 0,
+        }
+    }
+
+    /// Server side; pick an offer from the client, and return the extension to answer with if there was one we can use.
+    /// Offers that limit the server window are turned down, since the window size is fixed.
+    pub fn accept(&mut self, offers: &[&str]) -> Option<&'static str> {
+        let usable = offers.iter().any(|offer| {
+            let mut params = offer.split(';').map(|p| p.trim());
+            params.next() == Some(EXTENSION) && params.all(|p| !p.starts_with("server_max_window_bits"))
+        });
+        if usable {
+            self.negotiated = true;
+            return Some(EXTENSION_RESPONSE);
+        }
+        None
+    }
+
+    /// Client side; the extension to offer
+    pub fn offer(&self) -> &'static str {
+        EXTENSION
+    }
+
+    /// Client side; check if the server agreed to the offer
+    pub fn confirm(&mut self, extensions: &[&str]) {
+        self.negotiated = extensions.iter().any(|e| e.split(';').next().map(|p| p.trim()) == Some(EXTENSION));
+    }
+
+    pub fn is_negotiated(&self) -> bool {
+        self.negotiated
+    }
+
+    /// Is a compressed message that was split over several frames still being read?
+    pub fn is_reading(&self) -> bool {
+        self.fragments.is_some()
+    }
+
+    /// Total size of every message that was compressed, before compression
+    pub fn raw_bytes(&self) -> u64 {
+        self.raw_bytes
+    }
+
+    /// Total size of every message that was compressed, as sent
+    pub fn sent_bytes(&self) -> u64 {
+        self.sent_bytes
+    }
+
+    /// How big compressed messages were as sent, as a percentage of their size before compression
+    pub fn ratio_percent(&self) -> Option<u64> {
+        match self.raw_bytes {
+            0 => None,
+            raw => Some(self.sent_bytes * 100 / raw),
+        }
+    }
+
+    /// Compress an outgoing message; returns None if it should go out as it is
+    pub fn compress(&mut self, payload: &[u8]) -> Option<Vec<u8>> {
+        if !self.negotiated || payload.len() < self.threshold {
+            return None;
+        }
+        self.compressor.reset();
+        let start = self.compressor.total_in();
+        let mut output = Vec::with_capacity(payload.len() / 2 + 64);
+        loop {
+            let consumed = (self.compressor.total_in() - start) as usize;
+            if self.compressor.compress_vec(&payload[consumed..], &mut output, FlushCompress::Sync).is_err() {
+                return None;
+            }
+            if (self.compressor.total_in() - start) as usize == payload.len() && output.len() < output.capacity() {
+                break;
+            }
+            output.reserve(output.capacity());
+        }
+        if output.ends_with(&TRAILER) {
+            output.truncate(output.len() - TRAILER.len());
+        }
+
+        // Small or already compressed data can come out bigger
+        if output.len() >= payload.len() {
+            return None;
+        }
+        self.raw_bytes += payload.len() as u64;
+        self.sent_bytes += output.len() as u64;
+        Some(output)
+    }
+
+    /// Read a frame of a compressed message; the whole message and the opcode of its first frame come back once the last frame is in
+    pub fn read(&mut self, opcode: u8, payload: &[u8], last: bool) -> Option<Result<(u8, Vec<u8>), WireCompressionError>> {
+        let (opcode, mut buffer) = self.fragments.take().unwrap_or((opcode, Vec::new()));
+        if buffer.len() + payload.len() > self.max_message_bytes {
+            return Some(Err(WireCompressionError::MessageTooLarge));
+        }
+        buffer.extend_from_slice(payload);
+        if !last {
+            self.fragments = Some((opcode, buffer));
+            return None;
+        }
+        Some(self.inflate(buffer).map(|data| (opcode, data)))
+    }
+
+    /// Output never grows past one byte over the limit, which is enough to tell the message is too large
+    fn inflate(&mut self, mut input: Vec<u8>) -> Result<Vec<u8>, WireCompressionError> {
+        input.extend_from_slice(&TRAILER);
+        let limit = self.max_message_bytes.saturating_add(1);
+        let start = self.decompressor.total_in();
+        let mut output = Vec::with_capacity((input.len() * 2 + 64).min(limit));
+        loop {
+            let consumed = (self.decompressor.total_in() - start) as usize;
+            let produced = output.len();
+            let status = self
+                .decompressor
+                .decompress_vec(&input[consumed..], &mut output, FlushDecompress::Sync)
+                .map_err(|e| WireCompressionError::Corrupt(e.to_string()))?;
+            if output.len() > self.max_message_bytes {
+                return Err(WireCompressionError::MessageTooLarge);
+            }
+            let done = (self.decompressor.total_in() - start) as usize == input.len();
+            if status == Status::StreamEnd || (done && output.len() < output.capacity()) {
+                break;
+            }
+            if !done && consumed == (self.decompressor.total_in() - start) as usize && produced == output.len() && output.len() < output.capacity() {
+                return Err(WireCompressionError::Corrupt(format!("Compressed message is corrupt")));
+            }
+            output.reserve_exact(output.capacity().min(limit - output.len()));
+        }
+        Ok(output)
+    }
+}
@@ -0,0 +1,119 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+
+/// An event as it is read off a connection. On JSON connections the events that carry a payload are read
+/// straight into their fields, so the payload is kept as the raw JSON that arrived; the derived reader
+/// for a tagged enum buffers every field first, which would mean parsing the payload into a tree.
+pub trait WireEvent: Serialize + DeserializeOwned {
+    /// Read one event from JSON; by default this is the derived reader
+    fn from_json(raw: &[u8]) -> Result<Self, serde_json::Error> {
+        serde_json::from_slice(raw)
+    }
+}
+
+/// The tag of a JSON event; every other field is skipped without being read into anything
+#[derive(Deserialize)]
+struct ObjectType {
+    object_type: String,
+}
+
+/// Find the tag of a JSON event without reading the rest of it
+pub fn json_object_type(raw: &[u8]) -> Result<String, serde_json::Error> {
+    serde_json::from_slice::<ObjectType>(raw).map(|tagged| tagged.object_type)
+}
+
+/// How events are written on a connection; picked when the connection opens, and fixed after that.
+/// JSON goes out as text frames, the others as binary frames.
+#[derive(Serialize, Deserialize, Copy, Clone, Eq, PartialEq, Debug)]
+pub enum WireEncoding {
+    Json,
+    MessagePack,
+    Cbor,
+}
+
+impl Default for WireEncoding {
+    fn default() -> Self {
+        WireEncoding::Json
+    }
+}
+
+impl WireEncoding {
+    /// Find the encoding a connection asked for by its query parameter name
+    pub fn from_name(name: &str) -> Option<WireEncoding> {
+        match name {
+            "json" => Some(WireEncoding::Json),
+            "msgpack" => Some(WireEncoding::MessagePack),
+            "cbor" => Some(WireEncoding::Cbor),
+            _ => None,
+        }
+    }
+
+    /// The query parameter name for this encoding
+    pub fn name(&self) -> &'static str {
+        match self {
+            WireEncoding::Json => "json",
+            WireEncoding::MessagePack => "msgpack",
+            WireEncoding::Cbor => "cbor",
+        }
+    }
+
+    /// Does this encoding need binary frames?
+    pub fn is_binary(&self) -> bool {
+        *self != WireEncoding::Json
+    }
+
+    /// Does this frame hold an array, rather than a single value?
+    pub fn is_array(&self, raw: &[u8]) -> bool {
+        match self {
+            WireEncoding::Json => raw.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'['),
+            WireEncoding::MessagePack => raw.first().map(|b| (0x90..=0x9f).contains(b) || *b == 0xdc || *b == 0xdd).unwrap_or(false),
+            WireEncoding::Cbor => raw.first().map(|b| b >> 5 == 4).unwrap_or(false),
+        }
+    }
+
+    /// Something readable to log for a frame that couldn't be used
+    pub fn describe(&self, raw: &[u8]) -> String {
+        match self.is_binary() {
+            true => format!("{} byte {} frame", raw.len(), self.name()),
+            false => String::from_utf8_lossy(raw).to_string(),
+        }
+    }
+
+    /// Write a value; MessagePack keeps field names so tagged events can be read back
+    pub fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, String> {
+        match self {
+            WireEncoding::Json => serde_json::to_vec(value).map_err(|e| e.to_string()),
+            WireEncoding::MessagePack => rmp_serde::to_vec_named(value).map_err(|e| e.to_string()),
+            WireEncoding::Cbor => serde_cbor::to_vec(value).map_err(|e| e.to_string()),
+        }
+    }
+
+    /// Read a value
+    pub fn decode<T: DeserializeOwned>(&self, raw: &[u8]) -> Result<T, String> {
+        match self {
+            WireEncoding::Json => serde_json::from_slice(raw).map_err(|e| e.to_string()),
+            WireEncoding::MessagePack => rmp_serde::from_slice(raw).map_err(|e| e.to_string()),
+            WireEncoding::Cbor => serde_cbor::from_slice(raw).map_err(|e| e.to_string()),
+        }
+    }
+
+    /// Read one event
+    pub fn decode_event<T: WireEvent>(&self, raw: &[u8]) -> Result<T, String> {
+        match self {
+            WireEncoding::Json => T::from_json(raw).map_err(|e| e.to_string()),
+            _ => self.decode(raw),
+        }
+    }
+
+    /// Read an array of events
+    pub fn decode_events<T: WireEvent>(&self, raw: &[u8]) -> Result<Vec<T>, String> {
+        match self {
+            WireEncoding::Json => {
+                let events: Vec<&RawValue> = serde_json::from_slice(raw).map_err(|e| e.to_string())?;
+                events.iter().map(|event| T::from_json(event.get().as_bytes()).map_err(|e| e.to_string())).collect()
+            }
+            _ => self.decode(raw),
+        }
+    }
+}
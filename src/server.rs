@@ -3,13 +3,15 @@ use crate::server::server_config::ServerConfig;
 use std::sync::Arc;
 use std::sync::Mutex;
 use crate::server::server_connection_factory::ServerConnectionFactory;
-use ws::listen;
+use ws::Builder;
+use ws::Settings;
 
 pub mod server_config;
 pub mod server_error;
 pub mod server_connection;
 pub mod server_connection_factory;
 pub mod server_auth;
+pub mod server_frame;
 
 pub struct Server {}
 
@@ -18,11 +20,15 @@ impl Server {
         Server {}
     }
 
-    /// Run the server
+    /// Run the server; frames over the size limit close the connection before they are read
     pub fn listen(&mut self, config: ServerConfig) -> Result<(), ServerError> {
         let inner = ServerConnectionFactory::new(config.clone())?;
         let factory = Arc::new(Mutex::new(inner));
-        listen(&config.bind, move |out| {
+        let settings = Settings {
+            max_fragment_size: config.max_message_bytes,
+            ..Settings::default()
+        };
+        Builder::new().with_settings(settings).build(move |out| {
             match factory.lock() {
                 Ok(factory_ref) => {
                     match factory_ref.new_connection(Some(out)) {
@@ -32,7 +38,7 @@ impl Server {
                 }
                 Err(_) => panic!("Factory runtime is poisoned")
             }
-        })?;
+        })?.listen(&config.bind)?;
         Ok(())
     }
 }
\ No newline at end of file
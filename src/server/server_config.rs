@@ -4,6 +4,7 @@ use std::fs;
 use crate::server::server_error::ServerError;
 use std::error::Error;
 use std::collections::HashMap;
+use relay_core::model::wire_compression::{DEFAULT_COMPRESSION_LEVEL, DEFAULT_MAX_MESSAGE_BYTES};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ServerConfig {
@@ -12,6 +13,36 @@ pub struct ServerConfig {
 
     /// Set of key -> secret bindings
     pub secrets: HashMap<String, String>,
+
+    /// Set of key -> tenant bindings; keys that aren't listed are a tenant of their own, named by a hash of the key
+    #[serde(default)]
+    pub tenants: HashMap<String, String>,
+
+    /// Hold outbound events for up to this long so several can go out in one frame; 0 sends each one immediately
+    #[serde(default)]
+    pub coalesce_ms: u64,
+
+    /// Compress outbound frames of at least this many bytes for connections that negotiate permessage-deflate;
+    /// if this isn't set, compression is never offered
+    #[serde(default)]
+    pub compression_threshold: Option<usize>,
+
+    /// The deflate level to compress with, from 1 (fastest) to 9 (smallest)
+    #[serde(default = "default_compression_level")]
+    pub compression_level: u32,
+
+    /// The largest frame a connection accepts, and the largest a compressed message may inflate to;
+    /// connections that send anything bigger are closed
+    #[serde(default = "default_max_message_bytes")]
+    pub max_message_bytes: usize,
+}
+
+fn default_compression_level() -> u32 {
+    DEFAULT_COMPRESSION_LEVEL
+}
+
+fn default_max_message_bytes() -> usize {
+    DEFAULT_MAX_MESSAGE_BYTES
 }
 
 impl ServerConfig {
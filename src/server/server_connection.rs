@@ -1,31 +1,44 @@
 use crate::server::server_error::ServerError;
+use crate::server::server_frame;
 use chrono::Utc;
 use data_encoding::BASE64;
 use relay_analytics::analytics::Analytics;
+use relay_auth::AuthHasher;
 use relay_auth::AuthProvider;
+use relay_auth::AuthRequest;
 use relay_auth::AuthResponse;
+use relay_core::events::client_event::ClientControlEvent;
 use relay_core::events::client_event::ClientControlEvent::ClientDisconnected;
 use relay_core::events::client_event::ClientEvent;
 use relay_core::events::client_event::ClientExternalEvent;
+use relay_core::events::master_event::MasterControlEvent;
 use relay_core::events::master_event::MasterControlEvent::MasterDisconnected;
 use relay_core::events::master_event::MasterEvent;
 use relay_core::events::master_event::MasterExternalEvent;
 use relay_core::model::external_error::{ErrorCode, ExternalError};
+use relay_core::model::wire_compression::{WireCompression, WireCompressionError};
+use relay_core::model::wire_encoding::WireEncoding;
 use relay_logging::RelayLogger;
 use rust_isolate::IsolateChannel;
 use rust_isolate::IsolateRuntimeRef;
+use serde::Serialize;
+use std::collections::HashMap;
 use std::error::Error;
-use std::str::from_utf8;
+use std::time::Duration;
 use std::{mem, thread};
 use ws;
 use ws::CloseCode;
+use ws::Frame;
 use ws::Handler;
 use ws::Message;
+use ws::OpCode;
 use ws::Sender;
 
 #[derive(Clone)]
 pub struct ServerSession {
     expires: i64,
+    key: String,
+    tenant: String,
 }
 
 pub enum ServerEvent {
@@ -80,8 +93,13 @@ pub struct ServerConnection {
     auth: AuthProvider,
     state: ServerConnectionState,
     output: Option<Sender>,
+    replies: Option<Sender>,
     logger: RelayLogger,
     analytics: Analytics,
+    tenants: HashMap<String, String>,
+    coalesce: Duration,
+    encoding: WireEncoding,
+    compression: Option<WireCompression>,
     pub masters: IsolateRuntimeRef<MasterEvent>,
     pub clients: IsolateRuntimeRef<ClientEvent>,
 }
@@ -94,11 +112,19 @@ impl ServerConnection {
         analytics: Analytics,
         logger: RelayLogger,
         auth: AuthProvider,
+        tenants: HashMap<String, String>,
+        coalesce_ms: u64,
+        compression: Option<WireCompression>,
     ) -> ServerConnection {
         ServerConnection {
             state: ServerConnectionState::None,
             auth,
             analytics,
+            tenants,
+            coalesce: Duration::from_millis(coalesce_ms),
+            encoding: WireEncoding::default(),
+            compression,
+            replies: output.clone(),
             output,
             masters,
             clients,
@@ -107,27 +133,27 @@ impl ServerConnection {
     }
 
     /// Try to guess the state from the message type
-    fn pick_state_from(&mut self, message: &str) -> Result<(), ServerError> {
+    fn pick_state_from(&mut self, message: &[u8]) -> Result<(), ServerError> {
         let mut state = ServerConnectionState::None;
         mem::swap(&mut state, &mut self.state);
 
         match &state {
             ServerConnectionState::Authorized(session) => {
-                match serde_json::from_str::<MasterExternalEvent>(message) {
-                    Ok(_) => {
+                match server_frame::parse_frame::<MasterExternalEvent>(self.encoding, message) {
+                    Ok(ref events) if !events.is_empty() => {
                         self.become_master(session.clone())?;
                         return Ok(());
                     }
-                    Err(_) => {}
+                    _ => {}
                 }
-                match serde_json::from_str::<ClientExternalEvent>(message) {
-                    Ok(_) => {
+                match server_frame::parse_frame::<ClientExternalEvent>(self.encoding, message) {
+                    Ok(ref events) if !events.is_empty() => {
                         self.become_client(session.clone())?;
                         return Ok(());
                     }
-                    Err(_) => {}
+                    _ => {}
                 }
-                self.logger.warn(format!("Invalid message: {}", message));
+                self.logger.warn(format!("Invalid message: {}", self.encoding.describe(message)));
             }
             _ => {
                 self.logger.warn(format!(
@@ -141,22 +167,56 @@ impl ServerConnection {
         Ok(())
     }
 
-    /// Dispatch message based on type
-    fn dispatch_message(&self, message: &str) -> Result<(), ServerError> {
+    /// Dispatch message based on type; events with a bad transaction id are answered with an error,
+    /// and the rest of the batch is still processed
+    fn dispatch_message(&self, message: &[u8]) -> Result<(), ServerError> {
         match &self.state {
             ServerConnectionState::Master {
                 channel,
                 session: _,
             } => {
-                let message = serde_json::from_str::<MasterExternalEvent>(message)?;
-                channel.sender.send(MasterEvent::External(message))?;
+                let events = server_frame::parse_frame::<MasterExternalEvent>(self.encoding, message)?;
+                let (events, rejected) = server_frame::check_transaction_ids(
+                    events,
+                    |e| e.transaction_id(),
+                    |id| self.is_valid_transaction_id(id),
+                );
+                let results = rejected
+                    .into_iter()
+                    .map(|transaction_id| MasterExternalEvent::TransactionResult {
+                        transaction_id,
+                        success: false,
+                        error: Some(ExternalError::from(ErrorCode::InvalidTransactionId)),
+                        join_code: None,
+                    })
+                    .collect();
+                self.reject_transactions(results);
+                for event in events {
+                    channel.sender.send(MasterEvent::External(event))?;
+                }
             }
             ServerConnectionState::Client {
                 channel,
                 session: _,
             } => {
-                let message = serde_json::from_str::<ClientExternalEvent>(message)?;
-                channel.sender.send(ClientEvent::External(message))?;
+                let events = server_frame::parse_frame::<ClientExternalEvent>(self.encoding, message)?;
+                let (events, rejected) = server_frame::check_transaction_ids(
+                    events,
+                    |e| e.transaction_id(),
+                    |id| self.is_valid_transaction_id(id),
+                );
+                let results = rejected
+                    .into_iter()
+                    .map(|transaction_id| ClientExternalEvent::TransactionResult {
+                        transaction_id,
+                        success: false,
+                        error: Some(ExternalError::from(ErrorCode::InvalidTransactionId)),
+                    })
+                    .collect();
+                self.reject_transactions(results);
+                for event in events {
+                    channel.sender.send(ClientEvent::External(event))?;
+                }
             }
             ServerConnectionState::None => {}
             ServerConnectionState::Authorized(_) => {}
@@ -164,26 +224,91 @@ impl ServerConnection {
         Ok(())
     }
 
+    fn is_valid_transaction_id(&self, transaction_id: &str) -> bool {
+        match self.auth.validate_transaction_id(transaction_id) {
+            Ok(_) => true,
+            Err(e) => {
+                self.logger.warn(format!("Rejected transaction id: {:?}", e));
+                false
+            }
+        }
+    }
+
+    /// Answer events that were never processed straight from the connection
+    fn reject_transactions<T: Serialize>(&self, results: Vec<T>) {
+        if results.is_empty() {
+            return;
+        }
+        match &self.replies {
+            Some(output) => ServerConnection::send_frame(output, &self.logger, self.encoding, &results),
+            None => self
+                .logger
+                .warn("No output channel for connection, not delivering transaction results"),
+        }
+    }
+
     /// Become a master instance
     fn become_master(&mut self, session: ServerSession) -> Result<(), ServerError> {
         let channel = self.masters.spawn()?;
+        self.send(
+            &channel,
+            MasterEvent::Control(MasterControlEvent::Authorized {
+                tenant: session.tenant.clone(),
+            }),
+        );
         self.spawn_master_reader(channel.clone());
+        self.track_event("master", &session.tenant, 1);
+        self.track_event("master_total", &session.tenant, 1);
         self.state = ServerConnectionState::Master { channel, session };
-        self.analytics.track_event("master", 1);
-        self.analytics.track_event("master_total", 1);
         Ok(())
     }
 
     /// Become a client instance
     fn become_client(&mut self, session: ServerSession) -> Result<(), ServerError> {
         let channel = self.clients.spawn()?;
+        self.send(
+            &channel,
+            ClientEvent::Control(ClientControlEvent::Authorized {
+                auth_key: session.key.clone(),
+                tenant: session.tenant.clone(),
+            }),
+        );
         self.spawn_client_reader(channel.clone());
+        self.track_event("client", &session.tenant, 1);
+        self.track_event("client_total", &session.tenant, 1);
         self.state = ServerConnectionState::Client { channel, session };
-        self.analytics.track_event("client", 1);
-        self.analytics.track_event("client_total", 1);
         Ok(())
     }
 
+    /// Report how much compression saved on this connection. compression_ratio_percent adds up each connection's
+    /// compressed size as a percentage of its raw size, so dividing it by compression_ratio_samples gives the
+    /// average ratio; the byte totals give the ratio over all traffic.
+    fn track_compression(&self, tenant: &str) {
+        if let Some(compression) = &self.compression {
+            if let Some(ratio) = compression.ratio_percent() {
+                self.track_event("compression_raw_bytes", tenant, compression.raw_bytes().min(i32::MAX as u64) as i32);
+                self.track_event("compression_sent_bytes", tenant, compression.sent_bytes().min(i32::MAX as u64) as i32);
+                self.track_event("compression_ratio_percent", tenant, ratio.min(i32::MAX as u64) as i32);
+                self.track_event("compression_ratio_samples", tenant, 1);
+            }
+        }
+    }
+
+    /// Track an event both globally and for the tenant it happened in
+    fn track_event(&self, label: &str, tenant: &str, delta: i32) {
+        self.analytics.track_event(label, delta);
+        self.analytics.track_event(&format!("tenant:{}:{}", tenant, label), delta);
+    }
+
+    /// The tenant a key belongs to; keys without a configured tenant are their own tenant, named by a hash
+    /// of the key so the key itself never ends up in analytics labels
+    fn tenant_for(&self, key: &str) -> String {
+        match self.tenants.get(key) {
+            Some(tenant) => tenant.clone(),
+            None => format!("key-{}", AuthHasher::new().hash_key(key)),
+        }
+    }
+
     fn spawn_client_reader(&mut self, channel: IsolateChannel<ClientEvent>) {
         if self.output.is_none() {
             self.logger
@@ -193,28 +318,32 @@ impl ServerConnection {
 
         let read_channel = channel.clone();
         let read_logger = self.logger.clone();
+        let coalesce = self.coalesce;
+        let encoding = self.encoding;
         let output = self.output.take().unwrap();
         thread::spawn(move || {
             loop {
                 match read_channel.receiver.recv() {
                     Ok(message) => match message {
-                        ClientEvent::External(event) => match serde_json::to_string(&event) {
-                            Ok(serialized_event) => match output.send(serialized_event) {
-                                Ok(_) => {}
-                                Err(e) => {
-                                    read_logger.warn(format!(
-                                        "Failed to send message: {}",
-                                        e.description()
-                                    ));
-                                }
-                            },
-                            Err(e) => {
-                                read_logger.warn(format!(
-                                    "Failed to serialize message: {}",
-                                    e.description()
-                                ));
+                        ClientEvent::External(event) => {
+                            let mut batch = vec![event];
+                            let open = coalesce == Duration::from_millis(0)
+                                || server_frame::coalesce(
+                                    &read_channel.receiver,
+                                    coalesce,
+                                    &mut batch,
+                                    |m| match m {
+                                        ClientEvent::External(e) => Ok(e),
+                                        other => Err(other),
+                                    },
+                                    |m| read_logger.warn(format!("Discarded unknown message: {:?}", m)),
+                                );
+                            ServerConnection::send_frame(&output, &read_logger, encoding, &batch);
+                            if !open {
+                                // Channel went down, the connection is dead
+                                break;
                             }
-                        },
+                        }
                         _ => {
                             read_logger.warn(format!("Discarded unknown message: {:?}", message));
                         }
@@ -237,28 +366,32 @@ impl ServerConnection {
 
         let read_channel = channel.clone();
         let read_logger = self.logger.clone();
+        let coalesce = self.coalesce;
+        let encoding = self.encoding;
         let output = self.output.take().unwrap();
         thread::spawn(move || {
             loop {
                 match read_channel.receiver.recv() {
                     Ok(message) => match message {
-                        MasterEvent::External(event) => match serde_json::to_string(&event) {
-                            Ok(serialized_event) => match output.send(serialized_event) {
-                                Ok(_) => {}
-                                Err(e) => {
-                                    read_logger.warn(format!(
-                                        "Failed to send message: {}",
-                                        e.description()
-                                    ));
-                                }
-                            },
-                            Err(e) => {
-                                read_logger.warn(format!(
-                                    "Failed to serialize message: {}",
-                                    e.description()
-                                ));
+                        MasterEvent::External(event) => {
+                            let mut batch = vec![event];
+                            let open = coalesce == Duration::from_millis(0)
+                                || server_frame::coalesce(
+                                    &read_channel.receiver,
+                                    coalesce,
+                                    &mut batch,
+                                    |m| match m {
+                                        MasterEvent::External(e) => Ok(e),
+                                        other => Err(other),
+                                    },
+                                    |m| read_logger.warn(format!("Discarded unknown message: {:?}", m)),
+                                );
+                            ServerConnection::send_frame(&output, &read_logger, encoding, &batch);
+                            if !open {
+                                // Channel went down, the connection is dead
+                                break;
                             }
-                        },
+                        }
                         _ => {
                             read_logger.warn(format!("Discarded unknown message: {:?}", message));
                         }
@@ -272,6 +405,21 @@ impl ServerConnection {
         });
     }
 
+    /// Write a set of outbound events to the socket as a single frame
+    fn send_frame<T: Serialize>(output: &Sender, logger: &RelayLogger, encoding: WireEncoding, events: &[T]) {
+        match server_frame::serialize_frame(encoding, events) {
+            Ok(frame) => match output.send(frame) {
+                Ok(_) => {}
+                Err(e) => {
+                    logger.warn(format!("Failed to send message: {}", e.description()));
+                }
+            },
+            Err(e) => {
+                logger.warn(format!("Failed to serialize message: {}", e.description()));
+            }
+        }
+    }
+
     fn send<T: Send + 'static>(&self, channel: &IsolateChannel<T>, event: T) {
         match channel.sender.send(event) {
             Ok(_) => {}
@@ -282,8 +430,15 @@ impl ServerConnection {
         };
     }
 
-    fn try_authorize(&mut self, request: &str) -> AuthResponse {
-        self.auth.authorize(request)
+    /// The auth request is written in the same encoding as everything else on the connection
+    fn try_authorize(&mut self, request: &[u8]) -> AuthResponse {
+        match self.encoding.decode::<AuthRequest>(request) {
+            Ok(request) => self.auth.authorize_request(request),
+            Err(err) => {
+                self.logger.warn(format!("Failed to read auth request: {}", err));
+                AuthResponse::Failed
+            }
+        }
     }
 
     /// Halt this socket connection
@@ -303,19 +458,20 @@ impl ServerConnection {
     }
 
     /// Require authorization to continue
-    fn require_auth(&mut self, message: Option<&str>) -> Result<(), ExternalError> {
+    fn require_auth(&mut self, message: Option<&[u8]>) -> Result<(), ExternalError> {
         let (authorized, auth_expired) = self.state.is_authorized();
 
         // You must authorize before you can do anything.
         if !authorized && message.is_some() {
             match self.try_authorize(message.as_ref().unwrap()) {
-                AuthResponse::Passed { expires } => {
+                AuthResponse::Passed { expires, key } => {
                     self.logger.info(format!("Authorization success"));
-                    self.state = ServerConnectionState::Authorized(ServerSession { expires });
+                    let tenant = self.tenant_for(&key);
+                    self.state = ServerConnectionState::Authorized(ServerSession { expires, key, tenant });
                     return Ok(());
                 }
                 AuthResponse::Failed => {
-                    self.logger.warn(format!("Auth failed: {:?}", message));
+                    self.logger.warn(format!("Auth failed: {}", self.encoding.describe(message.unwrap())));
                     self.halt();
                     return Err(ExternalError::from(ErrorCode::InvalidRequest));
                 }
@@ -333,22 +489,38 @@ impl ServerConnection {
         return Ok(());
     }
 
-    /// Extract the auth token from the incoming request
-    fn message_from(&self, resource: &str) -> Option<String> {
-        let prefix = "/?token=";
+    /// Extract the auth token, and the wire encoding if one was asked for, from the incoming request
+    fn message_from(&self, resource: &str) -> Option<(Vec<u8>, WireEncoding)> {
+        let prefix = "/?";
         if !resource.starts_with(prefix) {
             self.logger.warn(format!("Invalid token: bad prefix"));
             return None;
         }
-        let encoded: String = resource.chars().skip(prefix.len()).collect();
-        return match BASE64.decode(&encoded.as_bytes()) {
-            Ok(values) => match from_utf8(&values) {
-                Ok(v) => Some(v.to_string()),
-                Err(err) => {
-                    self.logger.warn(format!("Invalid token: {}", err));
-                    None
-                }
-            },
+        let mut token = None;
+        let mut encoding = WireEncoding::default();
+        for param in resource[prefix.len()..].split('&') {
+            let mut parts = param.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some("token"), Some(value)) => token = Some(value),
+                (Some("encoding"), Some(value)) => match WireEncoding::from_name(value) {
+                    Some(e) => encoding = e,
+                    None => {
+                        self.logger.warn(format!("Invalid encoding: {}", value));
+                        return None;
+                    }
+                },
+                _ => {}
+            }
+        }
+        let encoded = match token {
+            Some(t) => t,
+            None => {
+                self.logger.warn(format!("Invalid token: no token"));
+                return None;
+            }
+        };
+        return match BASE64.decode(encoded.as_bytes()) {
+            Ok(values) => Some((values, encoding)),
             Err(err) => {
                 self.logger.warn(format!("Invalid token: {}", err));
                 None
@@ -358,14 +530,63 @@ impl ServerConnection {
 }
 
 impl Handler for ServerConnection {
+    fn on_request(&mut self, req: &ws::Request) -> ws::Result<ws::Response> {
+        let mut response = ws::Response::from_request(req)?;
+        if let Some(compression) = self.compression.as_mut() {
+            if let Some(extension) = compression.accept(&req.extensions()?) {
+                response.add_extension(extension);
+            }
+        }
+        Ok(response)
+    }
+
+    /// Inflate compressed messages; one split over several frames is held back until the last frame arrives
+    fn on_frame(&mut self, frame: Frame) -> ws::Result<Option<Frame>> {
+        let compression = match self.compression.as_mut() {
+            Some(c) if c.is_negotiated() => c,
+            _ => return Ok(Some(frame)),
+        };
+        match frame.opcode() {
+            OpCode::Text | OpCode::Binary if frame.has_rsv1() => {}
+            OpCode::Continue if compression.is_reading() => {}
+            _ => return Ok(Some(frame)),
+        }
+        match compression.read(frame.opcode().into(), frame.payload(), frame.is_final()) {
+            None => Ok(None),
+            Some(Ok((opcode, data))) => Ok(Some(Frame::message(data, OpCode::from(opcode), true))),
+            // ws closes the connection with CloseCode::Size for capacity errors
+            Some(Err(WireCompressionError::MessageTooLarge)) => Err(ws::Error::new(ws::ErrorKind::Capacity, "Compressed message is too large")),
+            Some(Err(WireCompressionError::Corrupt(e))) => Err(ws::Error::new(ws::ErrorKind::Protocol, e)),
+        }
+    }
+
+    /// Compress outbound messages that are big enough to be worth it
+    fn on_send_frame(&mut self, frame: Frame) -> ws::Result<Option<Frame>> {
+        let is_message = match frame.opcode() {
+            OpCode::Text | OpCode::Binary => frame.is_final(),
+            _ => false,
+        };
+        if is_message {
+            if let Some(compressed) = self.compression.as_mut().and_then(|c| c.compress(frame.payload())) {
+                let mut compressed_frame = Frame::message(compressed, frame.opcode(), true);
+                compressed_frame.set_rsv1(true);
+                return Ok(Some(compressed_frame));
+            }
+        }
+        Ok(Some(frame))
+    }
+
     fn on_open(&mut self, shake: ws::Handshake) -> ws::Result<()> {
         match self.message_from(&shake.request.resource()) {
-            Some(message) => match self.require_auth(Some(&message)) {
-                Ok(_) => {}
-                Err(err) => {
-                    self.logger.warn(format!("Auth failed: {}", err));
+            Some((message, encoding)) => {
+                self.encoding = encoding;
+                match self.require_auth(Some(&message)) {
+                    Ok(_) => {}
+                    Err(err) => {
+                        self.logger.warn(format!("Auth failed: {}", err));
+                    }
                 }
-            },
+            }
             None => {
                 self.logger.warn("Auth rejected: no token specified");
                 self.halt();
@@ -375,40 +596,53 @@ impl Handler for ServerConnection {
     }
 
     fn on_message(&mut self, msg: Message) -> ws::Result<()> {
-        match msg {
-            Message::Text(message) => {
-                match self.require_auth(None) {
-                    Ok(_) => {}
-                    Err(err) => {
-                        return Err(ws::Error::new(
-                            ws::ErrorKind::Custom(Box::new(err)),
-                            "Invalid request".to_string(),
-                        ));
-                    }
-                }
+        // Frames have to match the encoding the connection asked for
+        let message = match (msg, self.encoding.is_binary()) {
+            (Message::Text(message), false) => message.into_bytes(),
+            (Message::Binary(message), true) => message,
+            (Message::Text(_), true) => {
+                self.logger.warn("Discarded text chunk");
+                return Ok(());
+            }
+            (Message::Binary(_), false) => {
+                self.logger.warn("Discarded binary chunk");
+                return Ok(());
+            }
+        };
 
-                // Pick master / client mode
-                if self.state.is_unresolved() {
-                    match self.pick_state_from(&message) {
-                        Ok(_) => {}
-                        Err(e) => {
-                            self.logger
-                                .warn(format!("Failed to pick object state: {:?}: {}", e, message));
-                        }
-                    }
-                }
+        match self.require_auth(None) {
+            Ok(_) => {}
+            Err(err) => {
+                return Err(ws::Error::new(
+                    ws::ErrorKind::Custom(Box::new(err)),
+                    "Invalid request".to_string(),
+                ));
+            }
+        }
 
-                // Process real messages
-                match self.dispatch_message(&message) {
-                    Ok(_) => {}
-                    Err(e) => {
-                        self.logger
-                            .warn(format!("Failed to dispatch message: {:?}: {}", e, message));
-                    }
+        // Pick master / client mode
+        if self.state.is_unresolved() {
+            match self.pick_state_from(&message) {
+                Ok(_) => {}
+                Err(e) => {
+                    self.logger.warn(format!(
+                        "Failed to pick object state: {:?}: {}",
+                        e,
+                        self.encoding.describe(&message)
+                    ));
                 }
             }
-            _ => {
-                self.logger.warn("Discarded binary chunk");
+        }
+
+        // Process real messages
+        match self.dispatch_message(&message) {
+            Ok(_) => {}
+            Err(e) => {
+                self.logger.warn(format!(
+                    "Failed to dispatch message: {:?}: {}",
+                    e,
+                    self.encoding.describe(&message)
+                ));
             }
         }
         Ok(())
@@ -417,19 +651,15 @@ impl Handler for ServerConnection {
     fn on_close(&mut self, code: CloseCode, reason: &str) {
         let reason = format!("Connection closing due to ({:?}) {}", code, reason);
         match &self.state {
-            ServerConnectionState::Master {
-                channel,
-                session: _,
-            } => {
+            ServerConnectionState::Master { channel, session } => {
                 self.send(channel, MasterEvent::Control(MasterDisconnected { reason }));
-                self.analytics.track_event("master", -1);
+                self.track_event("master", &session.tenant, -1);
+                self.track_compression(&session.tenant);
             }
-            ServerConnectionState::Client {
-                channel,
-                session: _,
-            } => {
+            ServerConnectionState::Client { channel, session } => {
                 self.send(channel, ClientEvent::Control(ClientDisconnected { reason }));
-                self.analytics.track_event("client", -1);
+                self.track_event("client", &session.tenant, -1);
+                self.track_compression(&session.tenant);
             }
             ServerConnectionState::Authorized(_) => {}
             ServerConnectionState::None => {}
@@ -10,6 +10,7 @@ use relay_core::events::master_event::MasterEvent;
 use relay_core::infrastructure::services::SessionManager;
 use relay_core::isolates::client::ClientIsolate;
 use relay_core::isolates::master::MasterIsolate;
+use relay_core::model::wire_compression::WireCompression;
 use relay_core::CLIENT;
 use relay_core::MASTER;
 use relay_logging::RelayLogger;
@@ -57,6 +58,9 @@ impl ServerConnectionFactory {
             analytics,
             self.logger.clone(),
             auth,
+            self.config.tenants.clone(),
+            self.config.coalesce_ms,
+            self.config.compression_threshold.map(|t| WireCompression::new(t, self.config.compression_level, self.config.max_message_bytes)),
         ))
     }
 
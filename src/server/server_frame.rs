@@ -0,0 +1,77 @@
+use crate::server::server_error::ServerError;
+use crossbeam::{Receiver, RecvTimeoutError};
+use relay_core::model::wire_encoding::{WireEncoding, WireEvent};
+use serde::Serialize;
+use std::time::{Duration, Instant};
+use ws::Message;
+
+/// Read a frame, which is either a single event or an array of events to process in order
+pub fn parse_frame<T: WireEvent>(encoding: WireEncoding, raw: &[u8]) -> Result<Vec<T>, ServerError> {
+    if encoding.is_array(raw) {
+        return Ok(encoding.decode_events::<T>(raw).map_err(ServerError::Failed)?);
+    }
+    Ok(vec![encoding.decode_event::<T>(raw).map_err(ServerError::Failed)?])
+}
+
+/// Split a frame into the events that can be processed and the transaction ids of the ones that can't.
+/// One bad event doesn't hold up the rest of its batch; the sender hears back about each bad one.
+pub fn check_transaction_ids<T>(
+    events: Vec<T>,
+    transaction_id: impl Fn(&T) -> Option<&str>,
+    is_valid: impl Fn(&str) -> bool,
+) -> (Vec<T>, Vec<String>) {
+    let mut valid = Vec::new();
+    let mut rejected = Vec::new();
+    for event in events {
+        let invalid = transaction_id(&event).filter(|id| !is_valid(*id)).map(|id| id.to_string());
+        match invalid {
+            Some(id) => rejected.push(id),
+            None => valid.push(event),
+        }
+    }
+    (valid, rejected)
+}
+
+/// Write a set of events as one frame; a single event is sent on its own, so clients that
+/// never asked for batches see no difference.
+pub fn serialize_frame<T: Serialize>(encoding: WireEncoding, events: &[T]) -> Result<Message, ServerError> {
+    let encoded = match events.len() {
+        1 => encoding.encode(&events[0]),
+        _ => encoding.encode(&events),
+    }
+    .map_err(ServerError::Failed)?;
+    if encoding.is_binary() {
+        return Ok(Message::Binary(encoded));
+    }
+    match String::from_utf8(encoded) {
+        Ok(text) => Ok(Message::Text(text)),
+        Err(e) => Err(ServerError::Failed(e.to_string())),
+    }
+}
+
+/// Wait for more events to arrive within the window after the first one, so they can go out in one frame.
+/// Events that aren't meant for the connection are handed to discard.
+/// Returns false if the channel closed while waiting.
+pub fn coalesce<T, E>(
+    receiver: &Receiver<T>,
+    window: Duration,
+    batch: &mut Vec<E>,
+    external: impl Fn(T) -> Result<E, T>,
+    discard: impl Fn(T),
+) -> bool {
+    let deadline = Instant::now() + window;
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            return true;
+        }
+        match receiver.recv_timeout(deadline - now) {
+            Ok(message) => match external(message) {
+                Ok(event) => batch.push(event),
+                Err(message) => discard(message),
+            },
+            Err(RecvTimeoutError::Timeout) => return true,
+            Err(RecvTimeoutError::Disconnected) => return false,
+        }
+    }
+}
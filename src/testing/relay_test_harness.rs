@@ -1,9 +1,14 @@
+use relay_core::events::client_event::ClientControlEvent;
 use relay_core::events::client_event::ClientEvent;
+use relay_core::events::master_event::MasterControlEvent;
 use relay_core::events::master_event::MasterEvent;
 use relay_core::events::master_event::MasterExternalEvent;
 use relay_core::events::client_event::ClientExternalEvent;
 use relay_core::model::client_metadata::ClientMetadata;
+use relay_core::model::client_role::ClientRole;
 use relay_core::model::master_metadata::MasterMetadata;
+use relay_core::model::wire_compression::{DEFAULT_COMPRESSION_LEVEL, DEFAULT_MAX_MESSAGE_BYTES};
+use relay_core::DEFAULT_TENANT;
 use rust_isolate::IsolateChannel;
 use crate::server::server_connection_factory::ServerConnectionFactory;
 use crate::server::server_connection::ServerConnection;
@@ -23,23 +28,38 @@ impl RelayTestHarness {
             factory: ServerConnectionFactory::new(ServerConfig {
                 bind: "".to_string(),
                 secrets: HashMap::new(),
+                tenants: HashMap::new(),
+                coalesce_ms: 0,
+                compression_threshold: None,
+                compression_level: DEFAULT_COMPRESSION_LEVEL,
+                max_message_bytes: DEFAULT_MAX_MESSAGE_BYTES,
             }).unwrap(),
             instance: None,
         }
     }
 
-    /// Create a new session and join a set of peers to it
-    pub fn create_session(&mut self, session_name: &str, peers: usize, max_peers: usize) -> (IsolateChannel<MasterEvent>, Vec<IsolateChannel<ClientEvent>>) {
-        let mut service = self.factory.new_connection(None).unwrap();
+    /// Return the connection used to spawn isolates for this test run
+    pub fn service(&mut self) -> &mut ServerConnection {
+        if self.instance.is_none() {
+            self.instance = Some(self.factory.new_connection(None).unwrap());
+        }
+        self.instance.as_mut().unwrap()
+    }
 
-        // Create a master instance
-        let master = service.masters.spawn().unwrap();
+    /// Create a new master and initialize it with the given metadata
+    pub fn create_master(&mut self, metadata: MasterMetadata) -> IsolateChannel<MasterEvent> {
+        self.create_master_in(DEFAULT_TENANT, metadata)
+    }
+
+    /// Create a new master in the given tenant and initialize it with the given metadata
+    pub fn create_master_in(&mut self, tenant: &str, metadata: MasterMetadata) -> IsolateChannel<MasterEvent> {
+        let master = self.service().masters.spawn().unwrap();
+        master.sender.send(MasterEvent::Control(MasterControlEvent::Authorized {
+            tenant: tenant.to_string(),
+        })).unwrap();
         master.sender.send(MasterEvent::External(MasterExternalEvent::InitializeMaster {
             transaction_id: format!("Test"),
-            metadata: MasterMetadata {
-                max_clients: max_peers as u32,
-                master_id: session_name.to_string(),
-            },
+            metadata,
         })).unwrap();
 
         // Validate session started ok
@@ -48,7 +68,7 @@ impl RelayTestHarness {
                 match r {
                     MasterEvent::External(er) => {
                         match er {
-                            MasterExternalEvent::TransactionResult { transaction_id: _, success, error } => {
+                            MasterExternalEvent::TransactionResult { transaction_id: _, success, error, join_code: _ } => {
                                 assert!(success);
                                 assert!(error.is_none());
                             }
@@ -61,41 +81,80 @@ impl RelayTestHarness {
             Err(_) => unreachable!()
         };
 
-        // Create a set of clients
-        let mut clients = Vec::new();
-        for i in 0..peers {
+        master
+    }
 
-            // Initialize client
-            let client = service.clients.spawn().unwrap();
-            client.sender.send(ClientEvent::External(ClientExternalEvent::InitializeClient {
-                transaction_id: format!("Test-{}", i),
-                metadata: ClientMetadata {
-                    name: format!("Player {}", i)
-                },
-            })).unwrap();
+    /// Create a new client and initialize it, without joining it to any session
+    pub fn create_client(&mut self, name: &str) -> IsolateChannel<ClientEvent> {
+        let client = self.service().clients.spawn().unwrap();
+        client.sender.send(ClientEvent::External(ClientExternalEvent::InitializeClient {
+            transaction_id: format!("Test-{}", name),
+            metadata: ClientMetadata {
+                name: name.to_string()
+            },
+        })).unwrap();
 
-            // Check init passed
-            match client.receiver.recv() {
-                Ok(r) => {
-                    match r {
-                        ClientEvent::External(er) => {
-                            match er {
-                                ClientExternalEvent::TransactionResult { transaction_id: _, success, error: _ } => {
-                                    assert!(success);
-                                }
-                                _ => unreachable!()
+        // Check init passed
+        match client.receiver.recv() {
+            Ok(r) => {
+                match r {
+                    ClientEvent::External(er) => {
+                        match er {
+                            ClientExternalEvent::TransactionResult { transaction_id: _, success, error: _ } => {
+                                assert!(success);
                             }
+                            _ => unreachable!()
                         }
-                        _ => unreachable!()
                     }
+                    _ => unreachable!()
                 }
-                Err(_) => unreachable!()
             }
+            Err(_) => unreachable!()
+        }
+
+        client
+    }
+
+    /// Create a new client in the given tenant and initialize it, without joining it to any session
+    pub fn create_client_in(&mut self, tenant: &str, name: &str) -> IsolateChannel<ClientEvent> {
+        let client = self.create_client(name);
+        client.sender.send(ClientEvent::Control(ClientControlEvent::Authorized {
+            auth_key: tenant.to_string(),
+            tenant: tenant.to_string(),
+        })).unwrap();
+        client
+    }
+
+    /// Create a new session and join a set of peers to it
+    pub fn create_session(&mut self, session_name: &str, peers: usize, max_peers: usize) -> (IsolateChannel<MasterEvent>, Vec<IsolateChannel<ClientEvent>>) {
+        self.create_session_with(MasterMetadata {
+            max_clients: max_peers as u32,
+            master_id: session_name.to_string(),
+            ..Default::default()
+        }, peers)
+    }
+
+    /// Create a new session with the given metadata and join a set of peers to it
+    pub fn create_session_with(&mut self, metadata: MasterMetadata, peers: usize) -> (IsolateChannel<MasterEvent>, Vec<IsolateChannel<ClientEvent>>) {
+        self.instance = Some(self.factory.new_connection(None).unwrap());
+
+        // Create a master instance
+        let session_name = metadata.master_id.clone();
+        let master = self.create_master(metadata);
+
+        // Create a set of clients
+        let mut clients = Vec::new();
+        for i in 0..peers {
+
+            // Initialize client
+            let client = self.create_client(&format!("Player {}", i));
 
             // Now join the client to the given session id
             client.sender.send(ClientEvent::External(ClientExternalEvent::Join {
                 transaction_id: format!("Test"),
                 session_id: session_name.to_string(),
+                role: ClientRole::Player,
+                waitlist: false,
             })).unwrap();
 
             // Check join passed
@@ -120,7 +179,6 @@ impl RelayTestHarness {
             clients.push(client);
         }
 
-        self.instance = Some(service);
         return (master, clients);
     }
 
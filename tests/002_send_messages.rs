@@ -19,7 +19,7 @@ pub fn main() {
             match event {
                 MasterEvent::External(external) => {
                     match external {
-                        MasterExternalEvent::ClientJoined { client_id: _, name } => {
+                        MasterExternalEvent::ClientJoined { client_id: _, name, role: _ } => {
                             assert_eq!(name, "Player 0")
                         }
                         _ => unreachable!()
@@ -35,6 +35,9 @@ pub fn main() {
     clients[0].sender.send(ClientEvent::External(ClientExternalEvent::MessageFromClient {
         transaction_id: "1".to_string(),
         data: "Hello world".to_string(),
+        payload: None,
+        ack: false,
+        unreliable: false,
     })).unwrap();
 
     // Get a transaction result from sending the message
@@ -59,7 +62,7 @@ pub fn main() {
             match event {
                 MasterEvent::External(external) => {
                     match external {
-                        MasterExternalEvent::MessageFromClient { client_id, data: _ } => {
+                        MasterExternalEvent::MessageFromClient { client_id, data: _, payload: _, sequence: _, ack: _ } => {
                             client_id
                         }
                         _ => unreachable!()
@@ -79,6 +82,9 @@ pub fn main() {
         client_id: identity,
         transaction_id: "1".to_string(),
         data: "Hello world back!".to_string(),
+        payload: None,
+        ack: false,
+        unreliable: false,
     })).unwrap();
 
     // Read from the clients
@@ -87,7 +93,7 @@ pub fn main() {
             match event {
                 ClientEvent::External(external) => {
                     match external {
-                        ClientExternalEvent::MessageToClient { data } => {
+                        ClientExternalEvent::MessageToClient { data, payload: _, sequence: _, ack: _ } => {
                             assert_eq!(data, "Hello world back!");
                         }
                         _ => unreachable!()
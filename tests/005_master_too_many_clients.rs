@@ -6,6 +6,7 @@ use relay_core::events::client_event::ClientEvent;
 use std::thread;
 use std::time::Duration;
 use relay_core::model::client_metadata::ClientMetadata;
+use relay_core::model::client_role::ClientRole;
 
 #[test]
 pub fn main() {
@@ -47,6 +48,8 @@ pub fn main() {
     client.sender.send(ClientEvent::External(ClientExternalEvent::Join {
         transaction_id: format!("Test"),
         session_id: format!("Hello World"),
+        role: ClientRole::Player,
+        waitlist: false,
     })).unwrap();
 
     // Check we got a valid response
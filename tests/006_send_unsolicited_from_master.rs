@@ -19,7 +19,7 @@ pub fn main() {
             match event {
                 MasterEvent::External(external) => {
                     match external {
-                        MasterExternalEvent::ClientJoined { client_id, name } => {
+                        MasterExternalEvent::ClientJoined { client_id, name, role: _ } => {
                             assert_eq!(name, "Player 0");
                             client_id
                         }
@@ -37,6 +37,9 @@ pub fn main() {
         client_id,
         transaction_id: "1".to_string(),
         data: "Hello world".to_string(),
+        payload: None,
+        ack: false,
+        unreliable: false,
     })).unwrap();
 
     // Read from the clients
@@ -45,7 +48,7 @@ pub fn main() {
             match event {
                 ClientEvent::External(external) => {
                     match external {
-                        ClientExternalEvent::MessageToClient { data } => {
+                        ClientExternalEvent::MessageToClient { data, payload: _, sequence: _, ack: _ } => {
                             assert_eq!(data, "Hello world");
                         }
                         _ => unreachable!()
@@ -63,7 +66,7 @@ pub fn main() {
             match event {
                 MasterEvent::External(external) => {
                     match external {
-                        MasterExternalEvent::TransactionResult { transaction_id, success, error } => {
+                        MasterExternalEvent::TransactionResult { transaction_id, success, error, join_code: _ } => {
                             assert!(success);
                             assert!(error.is_none());
                             assert_eq!(transaction_id, "1");
@@ -0,0 +1,172 @@
+use relay::RelayTestHarness;
+use relay_core::events::client_event::ClientControlEvent;
+use relay_core::events::client_event::ClientEvent;
+use relay_core::events::client_event::ClientExternalEvent;
+use relay_core::events::master_event::MasterControlEvent;
+use relay_core::events::master_event::MasterEvent;
+use relay_core::events::master_event::MasterExternalEvent;
+use relay_core::model::external_error::ErrorCode;
+use relay_core::model::master_metadata::MasterMetadata;
+use relay_core::model::client_role::ClientRole;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+pub fn main() {
+    let mut harness = RelayTestHarness::new();
+    let master = harness.create_master(MasterMetadata {
+        master_id: "Hello World".to_string(),
+        max_clients: 2,
+        approve_joins: true,
+        join_approval_timeout_ms: Some(500),
+        ..Default::default()
+    });
+    let approved = harness.create_client("Player 0");
+    let rejected = harness.create_client("Player 1");
+    let ignored = harness.create_client("Player 2");
+    let impatient = harness.create_client("Player 3");
+
+    // Ask to join, and approve it
+    approved.sender.send(ClientEvent::External(ClientExternalEvent::Join {
+        transaction_id: "1".to_string(),
+        session_id: "Hello World".to_string(),
+        role: ClientRole::Player,
+        waitlist: false,
+    })).unwrap();
+    let client_id = match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::ClientJoinRequested { client_id, name, metadata, role: _ })) => {
+            assert_eq!(name, "Player 0");
+            assert_eq!(metadata.name, "Player 0");
+            client_id
+        }
+        _ => unreachable!()
+    };
+    master.sender.send(MasterEvent::External(MasterExternalEvent::ApproveJoin {
+        transaction_id: "2".to_string(),
+        client_id,
+    })).unwrap();
+    match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::ClientJoined { client_id: _, name, role: _ })) => {
+            assert_eq!(name, "Player 0");
+        }
+        _ => unreachable!()
+    };
+    match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::TransactionResult { transaction_id, success, error: _, join_code: _ })) => {
+            assert_eq!(transaction_id, "2");
+            assert!(success);
+        }
+        _ => unreachable!()
+    };
+    match approved.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::TransactionResult { transaction_id, success, error: _ })) => {
+            assert_eq!(transaction_id, "1");
+            assert!(success);
+        }
+        _ => unreachable!()
+    };
+
+    // Ask to join, and reject it
+    rejected.sender.send(ClientEvent::External(ClientExternalEvent::Join {
+        transaction_id: "3".to_string(),
+        session_id: "Hello World".to_string(),
+        role: ClientRole::Player,
+        waitlist: false,
+    })).unwrap();
+    let client_id = match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::ClientJoinRequested { client_id, name: _, metadata: _, role: _ })) => client_id,
+        _ => unreachable!()
+    };
+    master.sender.send(MasterEvent::External(MasterExternalEvent::RejectJoin {
+        transaction_id: "4".to_string(),
+        client_id,
+        reason: "Go away".to_string(),
+    })).unwrap();
+    match rejected.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::TransactionResult { transaction_id, success, error })) => {
+            assert_eq!(transaction_id, "3");
+            assert!(!success);
+            let error = error.unwrap();
+            assert_eq!(error.error_code, ErrorCode::JoinRejected as i32);
+            assert_eq!(error.error_reason, "Go away");
+        }
+        _ => unreachable!()
+    };
+    match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::TransactionResult { transaction_id, success, error: _, join_code: _ })) => {
+            assert_eq!(transaction_id, "4");
+            assert!(success);
+        }
+        _ => unreachable!()
+    };
+
+    // Ask to join, and never get an answer
+    ignored.sender.send(ClientEvent::External(ClientExternalEvent::Join {
+        transaction_id: "5".to_string(),
+        session_id: "Hello World".to_string(),
+        role: ClientRole::Player,
+        waitlist: false,
+    })).unwrap();
+    let ignored_id = match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::ClientJoinRequested { client_id, name: _, metadata: _, role: _ })) => client_id,
+        _ => unreachable!()
+    };
+    match ignored.receiver.recv_timeout(Duration::from_millis(2000)) {
+        Ok(ClientEvent::External(ClientExternalEvent::TransactionResult { transaction_id, success, error })) => {
+            assert_eq!(transaction_id, "5");
+            assert!(!success);
+            assert_eq!(error.unwrap().error_code, ErrorCode::JoinRequestExpired as i32);
+        }
+        _ => unreachable!()
+    };
+
+    // The master hears the request expired, not that a client it never admitted disconnected
+    match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::JoinRequestExpired { client_id })) => assert_eq!(client_id, ignored_id),
+        _ => unreachable!()
+    };
+
+    // Leaving before the master answers fails the join, and the master hears the client is gone
+    impatient.sender.send(ClientEvent::External(ClientExternalEvent::Join {
+        transaction_id: "6".to_string(),
+        session_id: "Hello World".to_string(),
+        role: ClientRole::Player,
+        waitlist: false,
+    })).unwrap();
+    let impatient_id = match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::ClientJoinRequested { client_id, name: _, metadata: _, role: _ })) => client_id,
+        _ => unreachable!()
+    };
+    impatient.sender.send(ClientEvent::External(ClientExternalEvent::Leave { transaction_id: "7".to_string() })).unwrap();
+    match impatient.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::TransactionResult { transaction_id, success, error })) => {
+            assert_eq!(transaction_id, "6");
+            assert!(!success);
+            assert_eq!(error.unwrap().error_code, ErrorCode::LeftBeforeApproval as i32);
+        }
+        _ => unreachable!()
+    };
+    match impatient.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::TransactionResult { transaction_id, success, error: _ })) => {
+            assert_eq!(transaction_id, "7");
+            assert!(success);
+        }
+        _ => unreachable!()
+    };
+    match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::ClientDisconnected { client_id, reason: _ })) => assert_eq!(client_id, impatient_id),
+        _ => unreachable!()
+    };
+
+    // Wait for processing to finish
+    thread::sleep(Duration::from_millis(100));
+
+    // Halt everyone
+    master.sender.send(MasterEvent::Control(MasterControlEvent::Halt)).unwrap();
+    approved.sender.send(ClientEvent::Control(ClientControlEvent::Halt)).unwrap();
+    rejected.sender.send(ClientEvent::Control(ClientControlEvent::Halt)).unwrap();
+    ignored.sender.send(ClientEvent::Control(ClientControlEvent::Halt)).unwrap();
+    impatient.sender.send(ClientEvent::Control(ClientControlEvent::Halt)).unwrap();
+
+    harness.complete();
+}
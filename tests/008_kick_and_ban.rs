@@ -0,0 +1,169 @@
+use relay::RelayTestHarness;
+use relay_core::events::client_event::ClientControlEvent;
+use relay_core::events::client_event::ClientEvent;
+use relay_core::events::client_event::ClientExternalEvent;
+use relay_core::events::master_event::MasterControlEvent;
+use relay_core::events::master_event::MasterEvent;
+use relay_core::events::master_event::MasterExternalEvent;
+use relay_core::model::external_error::ErrorCode;
+use relay_core::model::master_metadata::MasterMetadata;
+use relay_core::model::client_role::ClientRole;
+use relay_core::DEFAULT_TENANT;
+use rust_isolate::IsolateChannel;
+use std::thread;
+use std::time::Duration;
+
+fn authorize(client: &IsolateChannel<ClientEvent>, auth_key: &str) {
+    client.sender.send(ClientEvent::Control(ClientControlEvent::Authorized { auth_key: auth_key.to_string(), tenant: DEFAULT_TENANT.to_string() })).unwrap();
+}
+
+fn join_and_expect_ban(client: &IsolateChannel<ClientEvent>, transaction: &str) {
+    client.sender.send(ClientEvent::External(ClientExternalEvent::Join {
+        transaction_id: transaction.to_string(),
+        session_id: "Hello World".to_string(),
+        role: ClientRole::Player,
+        waitlist: false,
+    })).unwrap();
+    match client.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::TransactionResult { transaction_id, success, error })) => {
+            assert_eq!(transaction_id, transaction);
+            assert!(!success);
+            assert_eq!(error.unwrap().error_code, ErrorCode::ClientBanned as i32);
+        }
+        _ => unreachable!()
+    };
+}
+
+#[test]
+pub fn main() {
+    let mut harness = RelayTestHarness::new();
+    let master = harness.create_master(MasterMetadata {
+        master_id: "Hello World".to_string(),
+        max_clients: 4,
+        banned: vec!("Player 2".to_string()),
+        banned_keys: vec!("key-2".to_string()),
+        ..Default::default()
+    });
+    let kicked = harness.create_client("Player 0");
+    let same_key = harness.create_client("Player 1");
+    let banned = harness.create_client("Player 2");
+    let key_kicked = harness.create_client("Player 3");
+    let key_rejoin = harness.create_client("Player 4");
+    let key_banned = harness.create_client("Player 5");
+    authorize(&kicked, "key-0");
+    authorize(&same_key, "key-0");
+    authorize(&key_kicked, "key-1");
+    authorize(&key_rejoin, "key-1");
+    authorize(&key_banned, "key-2");
+
+    // Join, then get kicked and banned
+    kicked.sender.send(ClientEvent::External(ClientExternalEvent::Join {
+        transaction_id: "1".to_string(),
+        session_id: "Hello World".to_string(),
+        role: ClientRole::Player,
+        waitlist: false,
+    })).unwrap();
+    let client_id = match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::ClientJoined { client_id, name: _, role: _ })) => client_id,
+        _ => unreachable!()
+    };
+    match kicked.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::TransactionResult { transaction_id: _, success, error: _ })) => {
+            assert!(success);
+        }
+        _ => unreachable!()
+    };
+    master.sender.send(MasterEvent::External(MasterExternalEvent::KickClient {
+        transaction_id: "2".to_string(),
+        client_id,
+        reason: "Spamming".to_string(),
+        ban: true,
+        ban_key: false,
+    })).unwrap();
+    match kicked.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::Kicked { reason })) => {
+            assert_eq!(reason, "Spamming");
+        }
+        _ => unreachable!()
+    };
+    match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::TransactionResult { transaction_id, success, error: _, join_code: _ })) => {
+            assert_eq!(transaction_id, "2");
+            assert!(success);
+        }
+        _ => unreachable!()
+    };
+
+    // The kicked client can't come back, but other clients that share its auth key still can
+    join_and_expect_ban(&kicked, "3");
+    same_key.sender.send(ClientEvent::External(ClientExternalEvent::Join {
+        transaction_id: "4".to_string(),
+        session_id: "Hello World".to_string(),
+        role: ClientRole::Player,
+        waitlist: false,
+    })).unwrap();
+    match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::ClientJoined { client_id: _, name, role: _ })) => assert_eq!(name, "Player 1"),
+        _ => unreachable!()
+    };
+    match same_key.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::TransactionResult { transaction_id, success, error: _ })) => {
+            assert_eq!(transaction_id, "4");
+            assert!(success);
+        }
+        _ => unreachable!()
+    };
+
+    // Clients on the initial ban list can't join at all
+    join_and_expect_ban(&banned, "5");
+    join_and_expect_ban(&key_banned, "6");
+
+    // Banning by key keeps out a new connection under a new name, if it uses the same key
+    key_kicked.sender.send(ClientEvent::External(ClientExternalEvent::Join {
+        transaction_id: "7".to_string(),
+        session_id: "Hello World".to_string(),
+        role: ClientRole::Player,
+        waitlist: false,
+    })).unwrap();
+    let client_id = match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::ClientJoined { client_id, name: _, role: _ })) => client_id,
+        _ => unreachable!()
+    };
+    match key_kicked.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::TransactionResult { transaction_id: _, success, error: _ })) => assert!(success),
+        _ => unreachable!()
+    };
+    master.sender.send(MasterEvent::External(MasterExternalEvent::KickClient {
+        transaction_id: "8".to_string(),
+        client_id,
+        reason: "Cheating".to_string(),
+        ban: true,
+        ban_key: true,
+    })).unwrap();
+    match key_kicked.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::Kicked { reason })) => assert_eq!(reason, "Cheating"),
+        _ => unreachable!()
+    };
+    match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::TransactionResult { transaction_id, success, error: _, join_code: _ })) => {
+            assert_eq!(transaction_id, "8");
+            assert!(success);
+        }
+        _ => unreachable!()
+    };
+    join_and_expect_ban(&key_rejoin, "9");
+
+    // Wait for processing to finish
+    thread::sleep(Duration::from_millis(100));
+
+    // Halt everyone
+    master.sender.send(MasterEvent::Control(MasterControlEvent::Halt)).unwrap();
+    kicked.sender.send(ClientEvent::Control(ClientControlEvent::Halt)).unwrap();
+    same_key.sender.send(ClientEvent::Control(ClientControlEvent::Halt)).unwrap();
+    banned.sender.send(ClientEvent::Control(ClientControlEvent::Halt)).unwrap();
+    key_kicked.sender.send(ClientEvent::Control(ClientControlEvent::Halt)).unwrap();
+    key_rejoin.sender.send(ClientEvent::Control(ClientControlEvent::Halt)).unwrap();
+    key_banned.sender.send(ClientEvent::Control(ClientControlEvent::Halt)).unwrap();
+
+    harness.complete();
+}
@@ -0,0 +1,83 @@
+use relay::RelayTestHarness;
+use relay_core::events::client_event::ClientControlEvent;
+use relay_core::events::client_event::ClientEvent;
+use relay_core::events::client_event::ClientExternalEvent;
+use relay_core::events::master_event::MasterControlEvent;
+use relay_core::events::master_event::MasterEvent;
+use relay_core::events::master_event::MasterExternalEvent;
+use relay_core::model::external_error::ErrorCode;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+pub fn main() {
+    let mut harness = RelayTestHarness::new();
+    let (master, clients) = harness.create_session("Hello World", 3, 3);
+
+    // Read the join events
+    let mut client_ids = Vec::new();
+    for _ in 0..3 {
+        match master.receiver.recv() {
+            Ok(MasterEvent::External(MasterExternalEvent::ClientJoined { client_id, name: _, role: _ })) => client_ids.push(client_id),
+            _ => unreachable!()
+        };
+    }
+
+    // Broadcast to everyone except the first client
+    master.sender.send(MasterEvent::External(MasterExternalEvent::BroadcastToClients {
+        transaction_id: "1".to_string(),
+        data: "Hello everyone".to_string(),
+        exclude: vec!(client_ids[0].clone()),
+        retain: false,
+    })).unwrap();
+    match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::TransactionResult { transaction_id, success, error, join_code: _ })) => {
+            assert_eq!(transaction_id, "1");
+            assert!(success);
+            assert!(error.is_none());
+        }
+        _ => unreachable!()
+    };
+    for client in clients.iter().skip(1) {
+        match client.receiver.recv() {
+            Ok(ClientEvent::External(ClientExternalEvent::MessageToClient { data, payload: _, sequence: _, ack: _ })) => {
+                assert_eq!(data, "Hello everyone");
+            }
+            _ => unreachable!()
+        };
+    }
+
+    // Multicast to the first client and someone who isn't here
+    master.sender.send(MasterEvent::External(MasterExternalEvent::MulticastToClients {
+        transaction_id: "2".to_string(),
+        client_ids: vec!(client_ids[0].clone(), "nobody".to_string()),
+        data: "Hello you".to_string(),
+    })).unwrap();
+    match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::TransactionResult { transaction_id, success, error, join_code: _ })) => {
+            assert_eq!(transaction_id, "2");
+            assert!(!success);
+            let error = error.unwrap();
+            assert_eq!(error.error_code, ErrorCode::PartialDelivery as i32);
+            assert_eq!(error.undelivered, vec!("nobody".to_string()));
+        }
+        _ => unreachable!()
+    };
+    match clients[0].receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::MessageToClient { data, payload: _, sequence: _, ack: _ })) => {
+            assert_eq!(data, "Hello you");
+        }
+        _ => unreachable!()
+    };
+
+    // Wait for processing to finish
+    thread::sleep(Duration::from_millis(100));
+
+    // Halt everyone
+    master.sender.send(MasterEvent::Control(MasterControlEvent::Halt)).unwrap();
+    for client in clients.iter() {
+        client.sender.send(ClientEvent::Control(ClientControlEvent::Halt)).unwrap();
+    }
+
+    harness.complete();
+}
@@ -0,0 +1,118 @@
+use relay::RelayTestHarness;
+use relay_core::events::client_event::ClientControlEvent;
+use relay_core::events::client_event::ClientEvent;
+use relay_core::events::client_event::ClientExternalEvent;
+use relay_core::events::master_event::MasterControlEvent;
+use relay_core::events::master_event::MasterEvent;
+use relay_core::events::master_event::MasterExternalEvent;
+use relay_core::model::external_error::ErrorCode;
+use rust_isolate::IsolateChannel;
+use std::thread;
+use std::time::Duration;
+
+fn expect_result(master: &IsolateChannel<MasterEvent>, expected_id: &str, expected_error: Option<ErrorCode>) {
+    match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::TransactionResult { transaction_id, success, error, join_code: _ })) => {
+            assert_eq!(transaction_id, expected_id);
+            match expected_error {
+                Some(code) => {
+                    assert!(!success);
+                    assert_eq!(error.unwrap().error_code, code as i32);
+                }
+                None => assert!(success)
+            }
+        }
+        _ => unreachable!()
+    };
+}
+
+#[test]
+pub fn main() {
+    let mut harness = RelayTestHarness::new();
+    let (master, clients) = harness.create_session("Hello World", 2, 2);
+    let mut client_ids = Vec::new();
+    for _ in 0..2 {
+        match master.receiver.recv() {
+            Ok(MasterEvent::External(MasterExternalEvent::ClientJoined { client_id, name: _, role: _ })) => client_ids.push(client_id),
+            _ => unreachable!()
+        };
+    }
+
+    // Create a group and put the first client in it
+    master.sender.send(MasterEvent::External(MasterExternalEvent::CreateGroup {
+        transaction_id: "1".to_string(),
+        group: "red".to_string(),
+    })).unwrap();
+    expect_result(&master, "1", None);
+    master.sender.send(MasterEvent::External(MasterExternalEvent::CreateGroup {
+        transaction_id: "2".to_string(),
+        group: "red".to_string(),
+    })).unwrap();
+    expect_result(&master, "2", Some(ErrorCode::GroupIdConflict));
+    master.sender.send(MasterEvent::External(MasterExternalEvent::AddToGroup {
+        transaction_id: "3".to_string(),
+        group: "red".to_string(),
+        client_id: client_ids[0].clone(),
+    })).unwrap();
+    match clients[0].receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::GroupMembership { groups })) => {
+            assert_eq!(groups, vec!("red".to_string()));
+        }
+        _ => unreachable!()
+    };
+    expect_result(&master, "3", None);
+
+    // Message the group; only the member should get it
+    master.sender.send(MasterEvent::External(MasterExternalEvent::MessageToGroup {
+        transaction_id: "4".to_string(),
+        group: "red".to_string(),
+        data: "Hello red".to_string(),
+    })).unwrap();
+    match clients[0].receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::MessageToClient { data, payload: _, sequence: _, ack: _ })) => {
+            assert_eq!(data, "Hello red");
+        }
+        _ => unreachable!()
+    };
+    expect_result(&master, "4", None);
+    assert!(clients[1].receiver.try_recv().is_err());
+
+    // Disconnecting removes the client from its groups
+    clients[0].sender.send(ClientEvent::Control(ClientControlEvent::ClientDisconnected {
+        reason: "Went away".to_string(),
+    })).unwrap();
+    match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::ClientDisconnected { client_id, reason: _ })) => {
+            assert_eq!(client_id, client_ids[0]);
+        }
+        _ => unreachable!()
+    };
+    master.sender.send(MasterEvent::External(MasterExternalEvent::AddToGroup {
+        transaction_id: "5".to_string(),
+        group: "red".to_string(),
+        client_id: client_ids[0].clone(),
+    })).unwrap();
+    expect_result(&master, "5", Some(ErrorCode::NoMatchingClientId));
+
+    // Deleted groups can't be messaged
+    master.sender.send(MasterEvent::External(MasterExternalEvent::DeleteGroup {
+        transaction_id: "6".to_string(),
+        group: "red".to_string(),
+    })).unwrap();
+    expect_result(&master, "6", None);
+    master.sender.send(MasterEvent::External(MasterExternalEvent::MessageToGroup {
+        transaction_id: "7".to_string(),
+        group: "red".to_string(),
+        data: "Hello red".to_string(),
+    })).unwrap();
+    expect_result(&master, "7", Some(ErrorCode::NoMatchingGroup));
+
+    // Wait for processing to finish
+    thread::sleep(Duration::from_millis(100));
+
+    // Halt everyone
+    master.sender.send(MasterEvent::Control(MasterControlEvent::Halt)).unwrap();
+    clients[1].sender.send(ClientEvent::Control(ClientControlEvent::Halt)).unwrap();
+
+    harness.complete();
+}
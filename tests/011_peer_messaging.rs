@@ -0,0 +1,101 @@
+use relay::RelayTestHarness;
+use relay_core::events::client_event::ClientControlEvent;
+use relay_core::events::client_event::ClientEvent;
+use relay_core::events::client_event::ClientExternalEvent;
+use relay_core::events::master_event::MasterControlEvent;
+use relay_core::events::master_event::MasterEvent;
+use relay_core::events::master_event::MasterExternalEvent;
+use relay_core::model::master_metadata::MasterMetadata;
+use rust_isolate::IsolateChannel;
+use std::thread;
+use std::time::Duration;
+
+fn expect_peer_message(client: &IsolateChannel<ClientEvent>, expected_sender: &str, expected_data: &str) {
+    match client.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::MessageFromPeer { client_id, data })) => {
+            assert_eq!(client_id, expected_sender);
+            assert_eq!(data, expected_data);
+        }
+        _ => unreachable!()
+    };
+}
+
+fn expect_result(client: &IsolateChannel<ClientEvent>, expected_id: &str) -> bool {
+    match client.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::TransactionResult { transaction_id, success, error: _ })) => {
+            assert_eq!(transaction_id, expected_id);
+            success
+        }
+        _ => unreachable!()
+    }
+}
+
+#[test]
+pub fn main() {
+    let mut harness = RelayTestHarness::new();
+    let (master, clients) = harness.create_session_with(MasterMetadata {
+        master_id: "Hello World".to_string(),
+        max_clients: 3,
+        peer_messaging: true,
+        moderate_peer_messages: true,
+        ..Default::default()
+    }, 3);
+    let mut client_ids = Vec::new();
+    for _ in 0..3 {
+        match master.receiver.recv() {
+            Ok(MasterEvent::External(MasterExternalEvent::ClientJoined { client_id, name: _, role: _ })) => client_ids.push(client_id),
+            _ => unreachable!()
+        };
+    }
+
+    // Send directly to one peer; the master gets a copy
+    clients[0].sender.send(ClientEvent::External(ClientExternalEvent::MessageToPeer {
+        transaction_id: "1".to_string(),
+        client_id: client_ids[1].clone(),
+        data: "Hello you".to_string(),
+    })).unwrap();
+    match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::PeerMessage { client_id, recipients, data })) => {
+            assert_eq!(client_id, client_ids[0]);
+            assert_eq!(recipients, vec!(client_ids[1].clone()));
+            assert_eq!(data, "Hello you");
+        }
+        _ => unreachable!()
+    };
+    expect_peer_message(&clients[1], &client_ids[0], "Hello you");
+    assert!(expect_result(&clients[0], "1"));
+
+    // Broadcast to everyone else
+    clients[0].sender.send(ClientEvent::External(ClientExternalEvent::BroadcastToPeers {
+        transaction_id: "2".to_string(),
+        data: "Hello all".to_string(),
+    })).unwrap();
+    match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::PeerMessage { client_id: _, recipients, data: _ })) => {
+            assert_eq!(recipients.len(), 2);
+        }
+        _ => unreachable!()
+    };
+    expect_peer_message(&clients[1], &client_ids[0], "Hello all");
+    expect_peer_message(&clients[2], &client_ids[0], "Hello all");
+    assert!(expect_result(&clients[0], "2"));
+
+    // Unknown peers are rejected
+    clients[0].sender.send(ClientEvent::External(ClientExternalEvent::MessageToPeer {
+        transaction_id: "3".to_string(),
+        client_id: "nobody".to_string(),
+        data: "Hello?".to_string(),
+    })).unwrap();
+    assert!(!expect_result(&clients[0], "3"));
+
+    // Wait for processing to finish
+    thread::sleep(Duration::from_millis(100));
+
+    // Halt everyone
+    master.sender.send(MasterEvent::Control(MasterControlEvent::Halt)).unwrap();
+    for client in clients.iter() {
+        client.sender.send(ClientEvent::Control(ClientControlEvent::Halt)).unwrap();
+    }
+
+    harness.complete();
+}
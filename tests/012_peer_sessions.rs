@@ -0,0 +1,94 @@
+use relay::RelayTestHarness;
+use relay_core::events::client_event::ClientControlEvent;
+use relay_core::events::client_event::ClientEvent;
+use relay_core::events::client_event::ClientExternalEvent;
+use rust_isolate::IsolateChannel;
+use std::time::Duration;
+
+fn join_peer_session(client: &IsolateChannel<ClientEvent>, session: &str) {
+    client.sender.send(ClientEvent::External(ClientExternalEvent::JoinPeerSession {
+        transaction_id: "join".to_string(),
+        session_id: session.to_string(),
+    })).unwrap();
+    match client.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::TransactionResult { transaction_id, success, error: _ })) => {
+            assert_eq!(transaction_id, "join");
+            assert!(success);
+        }
+        _ => unreachable!()
+    };
+}
+
+fn expect_peer_joined(client: &IsolateChannel<ClientEvent>, expected_name: &str) -> String {
+    match client.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::PeerJoined { client_id, name })) => {
+            assert_eq!(name, expected_name);
+            client_id
+        }
+        _ => unreachable!()
+    }
+}
+
+#[test]
+pub fn main() {
+    let mut harness = RelayTestHarness::new();
+    let first = harness.create_client("Player 0");
+    let second = harness.create_client("Player 1");
+    let late = harness.create_client("Player 2");
+
+    // The first client creates the session, the second joins it
+    join_peer_session(&first, "Peers");
+    join_peer_session(&second, "Peers");
+    let second_id = expect_peer_joined(&first, "Player 1");
+    let first_id = expect_peer_joined(&second, "Player 0");
+
+    // Messages go to everyone else
+    first.sender.send(ClientEvent::External(ClientExternalEvent::MessageFromClient {
+        transaction_id: "1".to_string(),
+        data: "Hello peers".to_string(),
+        payload: None,
+        ack: false,
+        unreliable: false,
+    })).unwrap();
+    match second.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::MessageFromPeer { client_id, data })) => {
+            assert_eq!(client_id, first_id);
+            assert_eq!(data, "Hello peers");
+        }
+        _ => unreachable!()
+    };
+    match first.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::TransactionResult { transaction_id, success, error: _ })) => {
+            assert_eq!(transaction_id, "1");
+            assert!(success);
+        }
+        _ => unreachable!()
+    };
+
+    // Leaving notifies the others
+    second.sender.send(ClientEvent::Control(ClientControlEvent::ClientDisconnected {
+        reason: "Went away".to_string(),
+    })).unwrap();
+    match first.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::PeerLeft { client_id, reason })) => {
+            assert_eq!(client_id, second_id);
+            assert_eq!(reason, "Went away");
+        }
+        _ => unreachable!()
+    };
+
+    // When the last member leaves the session is reaped, so joining again starts a new, empty session.
+    // Joining right away races with the reap, and must still get an answer either way.
+    first.sender.send(ClientEvent::Control(ClientControlEvent::ClientDisconnected {
+        reason: "Went away".to_string(),
+    })).unwrap();
+    join_peer_session(&late, "Peers");
+    assert!(late.receiver.recv_timeout(Duration::from_millis(100)).is_err());
+
+    // Leave, so the new session is reaped too
+    late.sender.send(ClientEvent::Control(ClientControlEvent::ClientDisconnected {
+        reason: "Went away".to_string(),
+    })).unwrap();
+
+    harness.complete();
+}
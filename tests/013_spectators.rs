@@ -0,0 +1,107 @@
+use relay::RelayTestHarness;
+use relay_core::events::client_event::ClientControlEvent;
+use relay_core::events::client_event::ClientEvent;
+use relay_core::events::client_event::ClientExternalEvent;
+use relay_core::events::master_event::MasterControlEvent;
+use relay_core::events::master_event::MasterEvent;
+use relay_core::events::master_event::MasterExternalEvent;
+use relay_core::model::client_role::ClientRole;
+use relay_core::model::external_error::ErrorCode;
+use relay_core::model::external_error::ExternalError;
+use relay_core::model::master_metadata::MasterMetadata;
+use rust_isolate::IsolateChannel;
+use std::thread;
+use std::time::Duration;
+
+fn join(client: &IsolateChannel<ClientEvent>, role: ClientRole) -> Option<ExternalError> {
+    client.sender.send(ClientEvent::External(ClientExternalEvent::Join {
+        transaction_id: "join".to_string(),
+        session_id: "Hello World".to_string(),
+        role,
+        waitlist: false,
+    })).unwrap();
+    match client.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::TransactionResult { transaction_id: _, success: _, error })) => error,
+        _ => unreachable!()
+    }
+}
+
+#[test]
+pub fn main() {
+    let mut harness = RelayTestHarness::new();
+    let master = harness.create_master(MasterMetadata {
+        master_id: "Hello World".to_string(),
+        max_clients: 1,
+        max_spectators: 1,
+        ..Default::default()
+    });
+    let player = harness.create_client("Player 0");
+    let spectator = harness.create_client("Spectator 0");
+    let extra = harness.create_client("Spectator 1");
+
+    // A full player slot doesn't stop a spectator joining
+    assert!(join(&player, ClientRole::Player).is_none());
+    match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::ClientJoined { client_id: _, name: _, role })) => {
+            assert_eq!(role, ClientRole::Player);
+        }
+        _ => unreachable!()
+    };
+    assert!(join(&spectator, ClientRole::Spectator).is_none());
+    match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::ClientJoined { client_id: _, name, role })) => {
+            assert_eq!(name, "Spectator 0");
+            assert_eq!(role, ClientRole::Spectator);
+        }
+        _ => unreachable!()
+    };
+    assert_eq!(join(&extra, ClientRole::Spectator).unwrap().error_code, ErrorCode::SpectatorLimitExceeded as i32);
+
+    // Spectators can't talk in this session
+    spectator.sender.send(ClientEvent::External(ClientExternalEvent::MessageFromClient {
+        transaction_id: "1".to_string(),
+        data: "Hello?".to_string(),
+        payload: None,
+        ack: false,
+        unreliable: false,
+    })).unwrap();
+    match spectator.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::TransactionResult { transaction_id, success, error })) => {
+            assert_eq!(transaction_id, "1");
+            assert!(!success);
+            assert_eq!(error.unwrap().error_code, ErrorCode::SpectatorCannotSend as i32);
+        }
+        _ => unreachable!()
+    };
+
+    // Only spectators get spectator updates
+    master.sender.send(MasterEvent::External(MasterExternalEvent::MessageToSpectators {
+        transaction_id: "2".to_string(),
+        data: "Score is 1-0".to_string(),
+    })).unwrap();
+    match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::TransactionResult { transaction_id, success, error: _, join_code: _ })) => {
+            assert_eq!(transaction_id, "2");
+            assert!(success);
+        }
+        _ => unreachable!()
+    };
+    match spectator.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::MessageToClient { data, payload: _, sequence: _, ack: _ })) => {
+            assert_eq!(data, "Score is 1-0");
+        }
+        _ => unreachable!()
+    };
+    assert!(player.receiver.try_recv().is_err());
+
+    // Wait for processing to finish
+    thread::sleep(Duration::from_millis(100));
+
+    // Halt everyone
+    master.sender.send(MasterEvent::Control(MasterControlEvent::Halt)).unwrap();
+    player.sender.send(ClientEvent::Control(ClientControlEvent::Halt)).unwrap();
+    spectator.sender.send(ClientEvent::Control(ClientControlEvent::Halt)).unwrap();
+    extra.sender.send(ClientEvent::Control(ClientControlEvent::Halt)).unwrap();
+
+    harness.complete();
+}
@@ -0,0 +1,87 @@
+use relay::RelayTestHarness;
+use relay_core::events::client_event::ClientControlEvent;
+use relay_core::events::client_event::ClientEvent;
+use relay_core::events::client_event::ClientExternalEvent;
+use relay_core::events::master_event::MasterControlEvent;
+use relay_core::events::master_event::MasterEvent;
+use relay_core::events::master_event::MasterExternalEvent;
+use relay_core::model::client_role::ClientRole;
+use relay_core::model::external_error::ErrorCode;
+use relay_core::model::external_error::ExternalError;
+use relay_core::model::master_metadata::MasterMetadata;
+use rust_isolate::IsolateChannel;
+use std::thread;
+use std::time::Duration;
+
+fn metadata(name: &str) -> MasterMetadata {
+    MasterMetadata {
+        master_id: name.to_string(),
+        max_clients: 1,
+        ..Default::default()
+    }
+}
+
+fn request(client: &IsolateChannel<ClientEvent>, event: ClientExternalEvent) -> Option<ExternalError> {
+    client.sender.send(ClientEvent::External(event)).unwrap();
+    match client.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::TransactionResult { transaction_id: _, success: _, error })) => error,
+        _ => unreachable!()
+    }
+}
+
+fn join(session_id: &str) -> ClientExternalEvent {
+    ClientExternalEvent::Join {
+        transaction_id: "join".to_string(),
+        session_id: session_id.to_string(),
+        role: ClientRole::Player,
+        waitlist: false,
+    }
+}
+
+#[test]
+pub fn main() {
+    let mut harness = RelayTestHarness::new();
+    let first = harness.create_master(metadata("First"));
+    let second = harness.create_master(metadata("Second"));
+    let client = harness.create_client("Player 0");
+
+    // Join the first session; joining another one now is an error
+    assert!(request(&client, join("First")).is_none());
+    match first.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::ClientJoined { client_id: _, name: _, role: _ })) => {}
+        _ => unreachable!()
+    };
+    assert_eq!(request(&client, join("Second")).unwrap().error_code, ErrorCode::AlreadyInSession as i32);
+
+    // Leave, and the master is told about it
+    assert!(request(&client, ClientExternalEvent::Leave { transaction_id: "leave".to_string() }).is_none());
+    match first.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::ClientDisconnected { client_id: _, reason })) => {
+            assert_eq!(reason, "Client left the session");
+        }
+        _ => unreachable!()
+    };
+    assert_eq!(
+        request(&client, ClientExternalEvent::Leave { transaction_id: "leave".to_string() }).unwrap().error_code,
+        ErrorCode::ClientNotConnected as i32
+    );
+
+    // The same connection can now join somewhere else
+    assert!(request(&client, join("Second")).is_none());
+    match second.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::ClientJoined { client_id: _, name, role: _ })) => {
+            assert_eq!(name, "Player 0");
+        }
+        _ => unreachable!()
+    };
+
+    // Wait for processing to finish
+    thread::sleep(Duration::from_millis(100));
+
+    // Halt everyone
+    first.sender.send(MasterEvent::Control(MasterControlEvent::Halt)).unwrap();
+    second.sender.send(MasterEvent::Control(MasterControlEvent::Halt)).unwrap();
+    client.sender.send(ClientEvent::Control(ClientControlEvent::Halt)).unwrap();
+
+    harness.complete();
+}
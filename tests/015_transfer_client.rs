@@ -0,0 +1,109 @@
+use relay::RelayTestHarness;
+use relay_core::events::client_event::ClientControlEvent;
+use relay_core::events::client_event::ClientEvent;
+use relay_core::events::client_event::ClientExternalEvent;
+use relay_core::events::master_event::MasterControlEvent;
+use relay_core::events::master_event::MasterEvent;
+use relay_core::events::master_event::MasterExternalEvent;
+use relay_core::model::client_role::ClientRole;
+use relay_core::model::external_error::ErrorCode;
+use relay_core::model::master_metadata::MasterMetadata;
+use rust_isolate::IsolateChannel;
+use std::thread;
+use std::time::Duration;
+
+fn metadata(name: &str) -> MasterMetadata {
+    MasterMetadata {
+        master_id: name.to_string(),
+        max_clients: 2,
+        ..Default::default()
+    }
+}
+
+fn join_lobby(lobby: &IsolateChannel<MasterEvent>, client: &IsolateChannel<ClientEvent>) -> String {
+    client.sender.send(ClientEvent::External(ClientExternalEvent::Join {
+        transaction_id: "join".to_string(),
+        session_id: "Lobby".to_string(),
+        role: ClientRole::Player,
+        waitlist: false,
+    })).unwrap();
+    match client.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::TransactionResult { transaction_id: _, success, error: _ })) => assert!(success),
+        _ => unreachable!()
+    };
+    match lobby.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::ClientJoined { client_id, name: _, role: _ })) => client_id,
+        _ => unreachable!()
+    }
+}
+
+#[test]
+pub fn main() {
+    let mut harness = RelayTestHarness::new();
+    let lobby = harness.create_master(metadata("Lobby"));
+    let game = harness.create_master(metadata("Game"));
+    let moved = harness.create_client("Player 0");
+    let stuck = harness.create_client("Player 1");
+    let moved_id = join_lobby(&lobby, &moved);
+    let stuck_id = join_lobby(&lobby, &stuck);
+
+    // Move a client into the game
+    lobby.sender.send(MasterEvent::External(MasterExternalEvent::TransferClient {
+        transaction_id: "1".to_string(),
+        client_id: moved_id,
+        target_session_id: "Game".to_string(),
+    })).unwrap();
+    match game.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::ClientJoined { client_id: _, name, role: _ })) => {
+            assert_eq!(name, "Player 0");
+        }
+        _ => unreachable!()
+    };
+    match moved.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::SessionTransferred { session_id, success, error: _ })) => {
+            assert_eq!(session_id, "Game");
+            assert!(success);
+        }
+        _ => unreachable!()
+    };
+    match lobby.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::TransactionResult { transaction_id, success, error: _, join_code: _ })) => {
+            assert_eq!(transaction_id, "1");
+            assert!(success);
+        }
+        _ => unreachable!()
+    };
+
+    // Moving a client to a session that doesn't exist fails, and both sides hear about it
+    lobby.sender.send(MasterEvent::External(MasterExternalEvent::TransferClient {
+        transaction_id: "2".to_string(),
+        client_id: stuck_id,
+        target_session_id: "Nowhere".to_string(),
+    })).unwrap();
+    match stuck.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::SessionTransferred { session_id, success, error })) => {
+            assert_eq!(session_id, "Nowhere");
+            assert!(!success);
+            assert_eq!(error.unwrap().error_code, ErrorCode::NoMatchingMasterId as i32);
+        }
+        _ => unreachable!()
+    };
+    match lobby.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::TransactionResult { transaction_id, success, error: _, join_code: _ })) => {
+            assert_eq!(transaction_id, "2");
+            assert!(!success);
+        }
+        _ => unreachable!()
+    };
+
+    // Wait for processing to finish
+    thread::sleep(Duration::from_millis(100));
+
+    // Halt everyone
+    lobby.sender.send(MasterEvent::Control(MasterControlEvent::Halt)).unwrap();
+    game.sender.send(MasterEvent::Control(MasterControlEvent::Halt)).unwrap();
+    moved.sender.send(ClientEvent::Control(ClientControlEvent::Halt)).unwrap();
+    stuck.sender.send(ClientEvent::Control(ClientControlEvent::Halt)).unwrap();
+
+    harness.complete();
+}
@@ -0,0 +1,74 @@
+use relay::RelayTestHarness;
+use relay_core::events::master_event::MasterControlEvent;
+use relay_core::events::master_event::MasterEvent;
+use relay_core::events::master_event::MasterExternalEvent;
+use relay_core::model::external_error::ErrorCode;
+use relay_core::model::external_error::ExternalError;
+use relay_core::model::master_metadata::MasterMetadata;
+use rust_isolate::IsolateChannel;
+use std::thread;
+use std::time::Duration;
+
+fn metadata(name: &str, accept_from: Vec<String>) -> MasterMetadata {
+    MasterMetadata {
+        master_id: name.to_string(),
+        max_clients: 1,
+        accept_master_messages_from: accept_from,
+        ..Default::default()
+    }
+}
+
+fn message_to_master(master: &IsolateChannel<MasterEvent>, session_id: &str, data: &str) -> Option<ExternalError> {
+    master.sender.send(MasterEvent::External(MasterExternalEvent::MessageToMaster {
+        transaction_id: "1".to_string(),
+        session_id: session_id.to_string(),
+        data: data.to_string(),
+    })).unwrap();
+    match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::TransactionResult { transaction_id, success, error, join_code: _ })) => {
+            assert_eq!(transaction_id, "1");
+            assert_eq!(success, error.is_none());
+            error
+        }
+        _ => unreachable!()
+    }
+}
+
+#[test]
+pub fn main() {
+    let mut harness = RelayTestHarness::new();
+    let lobby = harness.create_master(metadata("Lobby", Vec::new()));
+    let game = harness.create_master(metadata("Game", vec!("Lobby".to_string())));
+
+    // The game accepts messages from the lobby
+    assert!(message_to_master(&lobby, "Game", "Player 0 is on the way").is_none());
+    match game.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::MessageFromMaster { session_id, data })) => {
+            assert_eq!(session_id, "Lobby");
+            assert_eq!(data, "Player 0 is on the way");
+        }
+        _ => unreachable!()
+    };
+
+    // ...but the lobby doesn't accept messages from anyone
+    assert_eq!(message_to_master(&game, "Lobby", "Game over").unwrap().error_code, ErrorCode::MasterMessageNotAllowed as i32);
+    assert!(lobby.receiver.try_recv().is_err());
+
+    // Unknown sessions are an error
+    assert_eq!(message_to_master(&lobby, "Nowhere", "Hello?").unwrap().error_code, ErrorCode::NoMatchingMasterId as i32);
+
+    // A master has to start its session before it can send anything
+    let uninitialized = harness.service().masters.spawn().unwrap();
+    assert_eq!(message_to_master(&uninitialized, "Game", "Hello?").unwrap().error_code, ErrorCode::MasterNotInitialized as i32);
+    assert!(game.receiver.try_recv().is_err());
+
+    // Wait for processing to finish
+    thread::sleep(Duration::from_millis(100));
+
+    // Halt everyone
+    lobby.sender.send(MasterEvent::Control(MasterControlEvent::Halt)).unwrap();
+    game.sender.send(MasterEvent::Control(MasterControlEvent::Halt)).unwrap();
+    uninitialized.sender.send(MasterEvent::Control(MasterControlEvent::Halt)).unwrap();
+
+    harness.complete();
+}
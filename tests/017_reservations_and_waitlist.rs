@@ -0,0 +1,191 @@
+use relay::RelayTestHarness;
+use relay_core::events::client_event::ClientControlEvent;
+use relay_core::events::client_event::ClientEvent;
+use relay_core::events::client_event::ClientExternalEvent;
+use relay_core::events::master_event::MasterControlEvent;
+use relay_core::events::master_event::MasterEvent;
+use relay_core::events::master_event::MasterExternalEvent;
+use relay_core::model::client_role::ClientRole;
+use relay_core::model::external_error::ErrorCode;
+use relay_core::model::external_error::ExternalError;
+use relay_core::model::master_metadata::MasterMetadata;
+use rust_isolate::IsolateChannel;
+use std::thread;
+use std::time::Duration;
+
+fn send_join(client: &IsolateChannel<ClientEvent>, waitlist: bool) {
+    send_join_to(client, "Hello World", waitlist);
+}
+
+fn send_join_to(client: &IsolateChannel<ClientEvent>, session_id: &str, waitlist: bool) {
+    client.sender.send(ClientEvent::External(ClientExternalEvent::Join {
+        transaction_id: "join".to_string(),
+        session_id: session_id.to_string(),
+        role: ClientRole::Player,
+        waitlist,
+    })).unwrap();
+}
+
+fn expect_result(client: &IsolateChannel<ClientEvent>) -> Option<ExternalError> {
+    match client.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::TransactionResult { transaction_id: _, success: _, error })) => error,
+        _ => unreachable!()
+    }
+}
+
+fn expect_position(client: &IsolateChannel<ClientEvent>, expected: u32) {
+    match client.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::WaitlistPosition { transaction_id, position })) => {
+            assert_eq!(transaction_id, "join");
+            assert_eq!(position, expected);
+        }
+        _ => unreachable!()
+    };
+}
+
+fn master_request(master: &IsolateChannel<MasterEvent>, event: MasterExternalEvent) -> Option<ExternalError> {
+    master.sender.send(MasterEvent::External(event)).unwrap();
+    match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::TransactionResult { transaction_id: _, success: _, error, join_code: _ })) => error,
+        _ => unreachable!()
+    }
+}
+
+fn expect_joined(master: &IsolateChannel<MasterEvent>, expected_name: &str) {
+    match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::ClientJoined { client_id: _, name, role: _ })) => {
+            assert_eq!(name, expected_name);
+        }
+        _ => unreachable!()
+    };
+}
+
+fn expect_join_requested(master: &IsolateChannel<MasterEvent>, expected_name: &str) -> String {
+    match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::ClientJoinRequested { client_id, name, metadata: _, role: _ })) => {
+            assert_eq!(name, expected_name);
+            client_id
+        }
+        _ => unreachable!()
+    }
+}
+
+fn expect_disconnected(master: &IsolateChannel<MasterEvent>) {
+    match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::ClientDisconnected { client_id: _, reason: _ })) => {}
+        _ => unreachable!()
+    };
+}
+
+#[test]
+pub fn main() {
+    let mut harness = RelayTestHarness::new();
+    let master = harness.create_master(MasterMetadata {
+        master_id: "Hello World".to_string(),
+        max_clients: 1,
+        ..Default::default()
+    });
+    let stranger = harness.create_client("Player 0");
+    let invited = harness.create_client("Player 1");
+    let first = harness.create_client("Player 2");
+    let second = harness.create_client("Player 3");
+
+    // Seats can't be held for longer than the relay allows
+    assert_eq!(master_request(&master, MasterExternalEvent::ReserveSeat {
+        transaction_id: "0".to_string(),
+        reservation: "Player 1".to_string(),
+        expires_ms: u64::MAX,
+    }).unwrap().error_code, ErrorCode::TimeoutOutOfRange as i32);
+
+    // The only seat is reserved, so only the invited client gets in
+    assert!(master_request(&master, MasterExternalEvent::ReserveSeat {
+        transaction_id: "1".to_string(),
+        reservation: "Player 1".to_string(),
+        expires_ms: 60000,
+    }).is_none());
+    send_join(&stranger, false);
+    assert_eq!(expect_result(&stranger).unwrap().error_code, ErrorCode::ClientLimitExceeded as i32);
+    send_join(&invited, false);
+    assert!(expect_result(&invited).is_none());
+    expect_joined(&master, "Player 1");
+
+    // Joining the reservation used it up
+    assert_eq!(master_request(&master, MasterExternalEvent::CancelReservation {
+        transaction_id: "2".to_string(),
+        reservation: "Player 1".to_string(),
+    }).unwrap().error_code, ErrorCode::NoMatchingReservation as i32);
+
+    // Clients that are willing to wait get a place in the queue
+    send_join(&first, true);
+    expect_position(&first, 1);
+    send_join(&second, true);
+    expect_position(&second, 2);
+
+    // A slot frees up, so the first waiting client gets in and the other moves up
+    invited.sender.send(ClientEvent::External(ClientExternalEvent::Leave { transaction_id: "leave".to_string() })).unwrap();
+    assert!(expect_result(&invited).is_none());
+    expect_disconnected(&master);
+    expect_joined(&master, "Player 2");
+    assert!(expect_result(&first).is_none());
+    expect_position(&second, 1);
+
+    // Leaving the waitlist fails the join
+    second.sender.send(ClientEvent::External(ClientExternalEvent::Leave { transaction_id: "leave".to_string() })).unwrap();
+    assert_eq!(expect_result(&second).unwrap().error_code, ErrorCode::LeftWaitlist as i32);
+    assert!(expect_result(&second).is_none());
+    expect_disconnected(&master);
+
+    // When the master vets joins, a join waiting for approval holds the seat, so the rest stay in the queue
+    let vetted = harness.create_master(MasterMetadata {
+        master_id: "Vetted World".to_string(),
+        max_clients: 1,
+        approve_joins: true,
+        ..Default::default()
+    });
+    let rejected = harness.create_client("Player 4");
+    let approved = harness.create_client("Player 5");
+    let queued = harness.create_client("Player 6");
+    send_join_to(&rejected, "Vetted World", true);
+    let rejected_id = expect_join_requested(&vetted, "Player 4");
+    send_join_to(&approved, "Vetted World", true);
+    expect_position(&approved, 1);
+    send_join_to(&queued, "Vetted World", true);
+    expect_position(&queued, 2);
+
+    // Rejecting the join frees the seat for the next client in line only
+    assert!(master_request(&vetted, MasterExternalEvent::RejectJoin {
+        transaction_id: "3".to_string(),
+        client_id: rejected_id,
+        reason: "No".to_string(),
+    }).is_none());
+    assert_eq!(expect_result(&rejected).unwrap().error_code, ErrorCode::JoinRejected as i32);
+    let approved_id = expect_join_requested(&vetted, "Player 5");
+    expect_position(&queued, 1);
+    vetted.sender.send(MasterEvent::External(MasterExternalEvent::ApproveJoin {
+        transaction_id: "4".to_string(),
+        client_id: approved_id,
+    })).unwrap();
+    expect_joined(&vetted, "Player 5");
+    match vetted.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::TransactionResult { transaction_id: _, success, error: _, join_code: _ })) => assert!(success),
+        _ => unreachable!()
+    };
+    assert!(expect_result(&approved).is_none());
+    assert!(queued.receiver.try_recv().is_err());
+
+    // Wait for processing to finish
+    thread::sleep(Duration::from_millis(100));
+
+    // Halt everyone
+    master.sender.send(MasterEvent::Control(MasterControlEvent::Halt)).unwrap();
+    stranger.sender.send(ClientEvent::Control(ClientControlEvent::Halt)).unwrap();
+    invited.sender.send(ClientEvent::Control(ClientControlEvent::Halt)).unwrap();
+    first.sender.send(ClientEvent::Control(ClientControlEvent::Halt)).unwrap();
+    second.sender.send(ClientEvent::Control(ClientControlEvent::Halt)).unwrap();
+    vetted.sender.send(MasterEvent::Control(MasterControlEvent::Halt)).unwrap();
+    rejected.sender.send(ClientEvent::Control(ClientControlEvent::Halt)).unwrap();
+    approved.sender.send(ClientEvent::Control(ClientControlEvent::Halt)).unwrap();
+    queued.sender.send(ClientEvent::Control(ClientControlEvent::Halt)).unwrap();
+
+    harness.complete();
+}
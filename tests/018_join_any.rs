@@ -0,0 +1,93 @@
+use relay::RelayTestHarness;
+use relay_core::events::client_event::ClientControlEvent;
+use relay_core::events::client_event::ClientEvent;
+use relay_core::events::client_event::ClientExternalEvent;
+use relay_core::events::master_event::MasterControlEvent;
+use relay_core::events::master_event::MasterEvent;
+use relay_core::events::master_event::MasterExternalEvent;
+use relay_core::model::client_role::ClientRole;
+use relay_core::model::external_error::ErrorCode;
+use relay_core::model::external_error::ExternalError;
+use relay_core::model::master_metadata::MasterMetadata;
+use relay_core::model::session_filter::SessionFilter;
+use rust_isolate::IsolateChannel;
+use std::collections::HashMap;
+use std::thread;
+use std::time::Duration;
+
+fn metadata(name: &str, region: &str) -> MasterMetadata {
+    let mut properties = HashMap::new();
+    properties.insert("region".to_string(), region.to_string());
+    MasterMetadata {
+        master_id: name.to_string(),
+        max_clients: 3,
+        tags: vec!("ranked".to_string()),
+        properties,
+        ..Default::default()
+    }
+}
+
+fn filter(tag: &str, region: &str, min_free_slots: u32) -> SessionFilter {
+    let mut properties = HashMap::new();
+    properties.insert("region".to_string(), region.to_string());
+    SessionFilter {
+        tags: vec!(tag.to_string()),
+        properties,
+        min_free_slots,
+    }
+}
+
+fn join_any(client: &IsolateChannel<ClientEvent>, filter: SessionFilter) -> Option<ExternalError> {
+    client.sender.send(ClientEvent::External(ClientExternalEvent::JoinAny {
+        transaction_id: "join".to_string(),
+        filter,
+        role: ClientRole::Player,
+    })).unwrap();
+    match client.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::TransactionResult { transaction_id: _, success: _, error })) => error,
+        _ => unreachable!()
+    }
+}
+
+fn expect_joined(master: &IsolateChannel<MasterEvent>, expected_name: &str) {
+    match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::ClientJoined { client_id: _, name, role: _ })) => {
+            assert_eq!(name, expected_name);
+        }
+        _ => unreachable!()
+    };
+}
+
+#[test]
+pub fn main() {
+    let mut harness = RelayTestHarness::new();
+    let first_eu = harness.create_master(metadata("EU-1", "eu"));
+    let second_eu = harness.create_master(metadata("EU-2", "eu"));
+    let us = harness.create_master(metadata("US-1", "us"));
+    let clients: Vec<IsolateChannel<ClientEvent>> = (0..4).map(|i| harness.create_client(&format!("Player {}", i))).collect();
+
+    // Both EU sessions are empty, so the tie goes to the first by name; the second client follows into the fuller one
+    assert!(join_any(&clients[0], filter("ranked", "eu", 0)).is_none());
+    expect_joined(&first_eu, "Player 0");
+    assert!(join_any(&clients[1], filter("ranked", "eu", 0)).is_none());
+    expect_joined(&first_eu, "Player 1");
+
+    // Asking for more room skips the fuller session
+    assert!(join_any(&clients[2], filter("ranked", "eu", 3)).is_none());
+    expect_joined(&second_eu, "Player 2");
+
+    // Nothing matches this
+    assert_eq!(join_any(&clients[3], filter("casual", "eu", 0)).unwrap().error_code, ErrorCode::NoMatchingSession as i32);
+    assert!(us.receiver.try_recv().is_err());
+
+    // Wait for processing to finish
+    thread::sleep(Duration::from_millis(100));
+
+    // Halt everyone
+    first_eu.sender.send(MasterEvent::Control(MasterControlEvent::Halt)).unwrap();
+    second_eu.sender.send(MasterEvent::Control(MasterControlEvent::Halt)).unwrap();
+    us.sender.send(MasterEvent::Control(MasterControlEvent::Halt)).unwrap();
+    clients.iter().for_each(|c| c.sender.send(ClientEvent::Control(ClientControlEvent::Halt)).unwrap());
+
+    harness.complete();
+}
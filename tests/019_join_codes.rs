@@ -0,0 +1,99 @@
+use relay::RelayTestHarness;
+use relay_core::events::client_event::ClientControlEvent;
+use relay_core::events::client_event::ClientEvent;
+use relay_core::events::client_event::ClientExternalEvent;
+use relay_core::events::master_event::MasterControlEvent;
+use relay_core::events::master_event::MasterEvent;
+use relay_core::events::master_event::MasterExternalEvent;
+use relay_core::model::client_role::ClientRole;
+use relay_core::model::external_error::ErrorCode;
+use relay_core::model::external_error::ExternalError;
+use relay_core::model::master_metadata::MasterMetadata;
+use rust_isolate::IsolateChannel;
+use std::thread;
+use std::time::Duration;
+
+fn join(client: &IsolateChannel<ClientEvent>, session_id: &str) -> Option<ExternalError> {
+    client.sender.send(ClientEvent::External(ClientExternalEvent::Join {
+        transaction_id: "join".to_string(),
+        session_id: session_id.to_string(),
+        role: ClientRole::Player,
+        waitlist: false,
+    })).unwrap();
+    match client.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::TransactionResult { transaction_id: _, success: _, error })) => error,
+        _ => unreachable!()
+    }
+}
+
+#[test]
+pub fn main() {
+    let mut harness = RelayTestHarness::new();
+    let master = harness.service().masters.spawn().unwrap();
+    let player = harness.create_client("Player 0");
+    let late = harness.create_client("Player 1");
+
+    // Ask for a join code when the session starts
+    master.sender.send(MasterEvent::External(MasterExternalEvent::InitializeMaster {
+        transaction_id: "init".to_string(),
+        metadata: MasterMetadata {
+            master_id: "A very long and unique session name".to_string(),
+            max_clients: 2,
+            generate_join_code: true,
+            ..Default::default()
+        },
+    })).unwrap();
+    let code = match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::TransactionResult { transaction_id: _, success, error: _, join_code })) => {
+            assert!(success);
+            join_code.unwrap()
+        }
+        _ => unreachable!()
+    };
+    assert_eq!(code.len(), 6);
+    assert!(code.chars().all(|c| "ABCDEFGHJKLMNPQRSTUVWXYZ23456789".contains(c)));
+
+    // No other session can take the code as its name, in any case
+    let impostor = harness.service().masters.spawn().unwrap();
+    impostor.sender.send(MasterEvent::External(MasterExternalEvent::InitializeMaster {
+        transaction_id: "init".to_string(),
+        metadata: MasterMetadata {
+            master_id: code.to_lowercase(),
+            max_clients: 2,
+            ..Default::default()
+        },
+    })).unwrap();
+    match impostor.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::TransactionResult { transaction_id: _, success, error, join_code: _ })) => {
+            assert!(!success);
+            assert_eq!(error.unwrap().error_code, ErrorCode::MasterIdConflict as i32);
+        }
+        _ => unreachable!()
+    };
+
+    // The code works in place of the session id, whatever the case
+    assert!(join(&player, &code.to_lowercase()).is_none());
+    match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::ClientJoined { client_id: _, name, role: _ })) => {
+            assert_eq!(name, "Player 0");
+        }
+        _ => unreachable!()
+    };
+
+    // Once the session is gone, so is the code
+    master.sender.send(MasterEvent::Control(MasterControlEvent::MasterDisconnected { reason: "Done".to_string() })).unwrap();
+    match player.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::MasterDisconnected { reason: _ })) => {}
+        _ => unreachable!()
+    };
+    assert_eq!(join(&late, &code).unwrap().error_code, ErrorCode::NoMatchingMasterId as i32);
+
+    // Wait for processing to finish
+    thread::sleep(Duration::from_millis(100));
+
+    // Halt the client that is left; the master and its client already stopped
+    late.sender.send(ClientEvent::Control(ClientControlEvent::Halt)).unwrap();
+    impostor.sender.send(MasterEvent::Control(MasterControlEvent::Halt)).unwrap();
+
+    harness.complete();
+}
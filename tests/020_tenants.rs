@@ -0,0 +1,88 @@
+use relay::RelayTestHarness;
+use relay_core::events::client_event::ClientControlEvent;
+use relay_core::events::client_event::ClientEvent;
+use relay_core::events::client_event::ClientExternalEvent;
+use relay_core::events::master_event::MasterControlEvent;
+use relay_core::events::master_event::MasterEvent;
+use relay_core::events::master_event::MasterExternalEvent;
+use relay_core::model::client_role::ClientRole;
+use relay_core::model::external_error::ErrorCode;
+use relay_core::model::external_error::ExternalError;
+use relay_core::model::master_metadata::MasterMetadata;
+use rust_isolate::IsolateChannel;
+use std::thread;
+use std::time::Duration;
+
+fn metadata() -> MasterMetadata {
+    MasterMetadata {
+        master_id: "Lobby".to_string(),
+        max_clients: 2,
+        ..Default::default()
+    }
+}
+
+fn list_sessions(client: &IsolateChannel<ClientEvent>) -> Vec<String> {
+    client.sender.send(ClientEvent::External(ClientExternalEvent::ListSessions {
+        transaction_id: "list".to_string(),
+    })).unwrap();
+    let sessions = match client.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::SessionList { transaction_id: _, sessions })) => sessions,
+        _ => unreachable!()
+    };
+    match client.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::TransactionResult { transaction_id: _, success, error: _ })) => assert!(success),
+        _ => unreachable!()
+    };
+    sessions
+}
+
+fn join(client: &IsolateChannel<ClientEvent>) -> Option<ExternalError> {
+    client.sender.send(ClientEvent::External(ClientExternalEvent::Join {
+        transaction_id: "join".to_string(),
+        session_id: "Lobby".to_string(),
+        role: ClientRole::Player,
+        waitlist: false,
+    })).unwrap();
+    match client.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::TransactionResult { transaction_id: _, success: _, error })) => error,
+        _ => unreachable!()
+    }
+}
+
+#[test]
+pub fn main() {
+    let mut harness = RelayTestHarness::new();
+
+    // Two tenants can use the same session name without colliding
+    let first = harness.create_master_in("tenant-a", metadata());
+    let second = harness.create_master_in("tenant-b", metadata());
+    assert_eq!(harness.factory.manager.list_sessions("tenant-a").ok().unwrap(), vec!("Lobby".to_string()));
+    assert_eq!(harness.factory.manager.list_sessions("tenant-b").ok().unwrap(), vec!("Lobby".to_string()));
+    assert!(harness.factory.manager.list_sessions("tenant-c").ok().unwrap().is_empty());
+
+    // Clients only ever see the sessions in their own tenant
+    let client_a = harness.create_client_in("tenant-a", "Player 0");
+    let client_c = harness.create_client_in("tenant-c", "Player 1");
+    assert_eq!(list_sessions(&client_a), vec!("Lobby".to_string()));
+    assert!(list_sessions(&client_c).is_empty());
+    assert!(join(&client_a).is_none());
+    match first.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::ClientJoined { client_id: _, name, role: _ })) => {
+            assert_eq!(name, "Player 0");
+        }
+        _ => unreachable!()
+    };
+    assert!(second.receiver.try_recv().is_err());
+    assert_eq!(join(&client_c).unwrap().error_code, ErrorCode::NoMatchingMasterId as i32);
+
+    // Wait for processing to finish
+    thread::sleep(Duration::from_millis(100));
+
+    // Halt everyone
+    first.sender.send(MasterEvent::Control(MasterControlEvent::Halt)).unwrap();
+    second.sender.send(MasterEvent::Control(MasterControlEvent::Halt)).unwrap();
+    client_a.sender.send(ClientEvent::Control(ClientControlEvent::Halt)).unwrap();
+    client_c.sender.send(ClientEvent::Control(ClientControlEvent::Halt)).unwrap();
+
+    harness.complete();
+}
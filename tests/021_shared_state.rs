@@ -0,0 +1,126 @@
+use relay::RelayTestHarness;
+use relay_core::events::client_event::ClientControlEvent;
+use relay_core::events::client_event::ClientEvent;
+use relay_core::events::client_event::ClientExternalEvent;
+use relay_core::events::master_event::MasterControlEvent;
+use relay_core::events::master_event::MasterEvent;
+use relay_core::events::master_event::MasterExternalEvent;
+use relay_core::model::client_role::ClientRole;
+use relay_core::model::external_error::ErrorCode;
+use relay_core::model::external_error::ExternalError;
+use relay_core::model::master_metadata::MasterMetadata;
+use relay_core::model::patch_format::PatchFormat;
+use rust_isolate::IsolateChannel;
+use serde_json::json;
+use serde_json::Value;
+use std::thread;
+use std::time::Duration;
+
+fn join(master: &IsolateChannel<MasterEvent>, client: &IsolateChannel<ClientEvent>) {
+    client.sender.send(ClientEvent::External(ClientExternalEvent::Join {
+        transaction_id: "join".to_string(),
+        session_id: "Hello World".to_string(),
+        role: ClientRole::Player,
+        waitlist: false,
+    })).unwrap();
+    assert!(expect_result(client).is_none());
+    match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::ClientJoined { client_id: _, name: _, role: _ })) => {}
+        _ => unreachable!()
+    };
+}
+
+fn expect_result(client: &IsolateChannel<ClientEvent>) -> Option<ExternalError> {
+    match client.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::TransactionResult { transaction_id: _, success: _, error })) => error,
+        _ => unreachable!()
+    }
+}
+
+fn expect_patch(client: &IsolateChannel<ClientEvent>, expected: Value, expected_version: u64) {
+    match client.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::StatePatch { format, patch, version })) => {
+            assert_eq!(format, PatchFormat::MergePatch);
+            assert_eq!(patch, expected);
+            assert_eq!(version, expected_version);
+        }
+        _ => unreachable!()
+    };
+}
+
+fn expect_snapshot(client: &IsolateChannel<ClientEvent>, expected: Value, expected_version: u64) {
+    match client.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::StateSnapshot { state, version })) => {
+            assert_eq!(state, expected);
+            assert_eq!(version, expected_version);
+        }
+        _ => unreachable!()
+    };
+}
+
+fn patch_state(master: &IsolateChannel<MasterEvent>, format: PatchFormat, patch: Value) -> Option<ExternalError> {
+    master.sender.send(MasterEvent::External(MasterExternalEvent::PatchState {
+        transaction_id: "patch".to_string(),
+        format,
+        patch,
+    })).unwrap();
+    match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::TransactionResult { transaction_id: _, success: _, error, join_code: _ })) => error,
+        _ => unreachable!()
+    }
+}
+
+#[test]
+pub fn main() {
+    let mut harness = RelayTestHarness::new();
+    let master = harness.create_master(MasterMetadata {
+        master_id: "Hello World".to_string(),
+        max_clients: 4,
+        ..Default::default()
+    });
+    let early = harness.create_client("Player 0");
+    let late = harness.create_client("Player 1");
+    join(&master, &early);
+
+    // Every connected client is sent the patch
+    assert!(patch_state(&master, PatchFormat::MergePatch, json!({"round": 1, "scores": {"Player 0": 0}})).is_none());
+    expect_patch(&early, json!({"round": 1, "scores": {"Player 0": 0}}), 1);
+
+    // A patch that can't be applied leaves the state alone
+    let error = patch_state(&master, PatchFormat::JsonPatch, json!([{"op": "remove", "path": "/missing"}]));
+    assert_eq!(error.unwrap().error_code, ErrorCode::InvalidStatePatch as i32);
+    assert!(patch_state(&master, PatchFormat::JsonPatch, json!([{"op": "replace", "path": "/scores/Player 0", "value": 5}])).is_none());
+    match early.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::StatePatch { format, patch: _, version })) => {
+            assert_eq!(format, PatchFormat::JsonPatch);
+            assert_eq!(version, 2);
+        }
+        _ => unreachable!()
+    };
+
+    // A late joiner gets the whole state after joining
+    join(&master, &late);
+    expect_snapshot(&late, json!({"round": 1, "scores": {"Player 0": 5}}), 2);
+
+    // Merge patches can remove keys
+    assert!(patch_state(&master, PatchFormat::MergePatch, json!({"round": 2, "scores": null})).is_none());
+    expect_patch(&early, json!({"round": 2, "scores": null}), 3);
+    expect_patch(&late, json!({"round": 2, "scores": null}), 3);
+
+    // A client can ask for the whole state again
+    late.sender.send(ClientEvent::External(ClientExternalEvent::RequestStateSnapshot {
+        transaction_id: "snapshot".to_string(),
+    })).unwrap();
+    expect_snapshot(&late, json!({"round": 2}), 3);
+    assert!(expect_result(&late).is_none());
+
+    // Wait for processing to finish
+    thread::sleep(Duration::from_millis(100));
+
+    // Halt everyone
+    master.sender.send(MasterEvent::Control(MasterControlEvent::Halt)).unwrap();
+    early.sender.send(ClientEvent::Control(ClientControlEvent::Halt)).unwrap();
+    late.sender.send(ClientEvent::Control(ClientControlEvent::Halt)).unwrap();
+
+    harness.complete();
+}
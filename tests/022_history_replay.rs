@@ -0,0 +1,90 @@
+use relay::RelayTestHarness;
+use relay_core::events::client_event::ClientControlEvent;
+use relay_core::events::client_event::ClientEvent;
+use relay_core::events::client_event::ClientExternalEvent;
+use relay_core::events::master_event::MasterControlEvent;
+use relay_core::events::master_event::MasterEvent;
+use relay_core::events::master_event::MasterExternalEvent;
+use relay_core::model::client_role::ClientRole;
+use relay_core::model::master_metadata::MasterMetadata;
+use rust_isolate::IsolateChannel;
+use std::thread;
+use std::time::Duration;
+
+fn broadcast(master: &IsolateChannel<MasterEvent>, data: &str, retain: bool) {
+    master.sender.send(MasterEvent::External(MasterExternalEvent::BroadcastToClients {
+        transaction_id: data.to_string(),
+        data: data.to_string(),
+        exclude: Vec::new(),
+        retain,
+    })).unwrap();
+    match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::TransactionResult { transaction_id: _, success, error: _, join_code: _ })) => {
+            assert!(success);
+        }
+        _ => unreachable!()
+    };
+}
+
+fn expect_message(client: &IsolateChannel<ClientEvent>, expected: &str) {
+    match client.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::MessageToClient { data, payload: _, sequence: _, ack: _ })) => {
+            assert_eq!(data, expected);
+        }
+        _ => unreachable!()
+    };
+}
+
+#[test]
+pub fn main() {
+    let mut harness = RelayTestHarness::new();
+    let (master, clients) = harness.create_session_with(MasterMetadata {
+        master_id: "Hello World".to_string(),
+        max_clients: 4,
+        history_max_messages: 2,
+        ..Default::default()
+    }, 1);
+    match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::ClientJoined { client_id: _, name: _, role: _ })) => {}
+        _ => unreachable!()
+    };
+
+    // Only the last two retained messages are kept
+    broadcast(&master, "one", true);
+    broadcast(&master, "two", false);
+    broadcast(&master, "three", true);
+    broadcast(&master, "four", true);
+    for data in vec!("one", "two", "three", "four") {
+        expect_message(&clients[0], data);
+    }
+
+    // A late joiner gets the history right after the join succeeds, then live traffic
+    let late = harness.create_client("Player 1");
+    late.sender.send(ClientEvent::External(ClientExternalEvent::Join {
+        transaction_id: "join".to_string(),
+        session_id: "Hello World".to_string(),
+        role: ClientRole::Player,
+        waitlist: false,
+    })).unwrap();
+    match late.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::TransactionResult { transaction_id: _, success, error: _ })) => {
+            assert!(success);
+        }
+        _ => unreachable!()
+    };
+    expect_message(&late, "three");
+    expect_message(&late, "four");
+    broadcast(&master, "five", false);
+    expect_message(&late, "five");
+    expect_message(&clients[0], "five");
+
+    // Wait for processing to finish
+    thread::sleep(Duration::from_millis(100));
+
+    // Halt everyone
+    master.sender.send(MasterEvent::Control(MasterControlEvent::Halt)).unwrap();
+    clients[0].sender.send(ClientEvent::Control(ClientControlEvent::Halt)).unwrap();
+    late.sender.send(ClientEvent::Control(ClientControlEvent::Halt)).unwrap();
+
+    harness.complete();
+}
@@ -0,0 +1,223 @@
+use relay::RelayTestHarness;
+use relay_core::events::client_event::ClientControlEvent;
+use relay_core::events::client_event::ClientEvent;
+use relay_core::events::client_event::ClientExternalEvent;
+use relay_core::events::master_event::MasterControlEvent;
+use relay_core::events::master_event::MasterEvent;
+use relay_core::events::master_event::MasterExternalEvent;
+use relay_core::model::client_role::ClientRole;
+use relay_core::model::external_error::ErrorCode;
+use relay_core::model::external_error::ExternalError;
+use relay_core::model::master_metadata::MasterMetadata;
+use rust_isolate::IsolateChannel;
+use std::thread;
+use std::time::Duration;
+
+fn send_to_client(master: &IsolateChannel<MasterEvent>, client_id: &str, transaction_id: &str) {
+    master.sender.send(MasterEvent::External(MasterExternalEvent::MessageToClient {
+        transaction_id: transaction_id.to_string(),
+        client_id: client_id.to_string(),
+        data: format!("Message {}", transaction_id),
+        payload: None,
+        ack: false,
+        unreliable: false,
+    })).unwrap();
+}
+
+fn expect_master_result(master: &IsolateChannel<MasterEvent>, expected_id: &str) -> Option<ExternalError> {
+    match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::TransactionResult { transaction_id, success: _, error, join_code: _ })) => {
+            assert_eq!(transaction_id, expected_id);
+            error
+        }
+        _ => unreachable!()
+    }
+}
+
+fn expect_client_result(client: &IsolateChannel<ClientEvent>) -> Option<ExternalError> {
+    match client.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::TransactionResult { transaction_id: _, success: _, error })) => error,
+        _ => unreachable!()
+    }
+}
+
+fn expect_disconnected(master: &IsolateChannel<MasterEvent>) {
+    match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::ClientDisconnected { client_id: _, reason: _ })) => {}
+        _ => unreachable!()
+    };
+}
+
+fn expect_client_message(client: &IsolateChannel<ClientEvent>, expected: &str, expected_sequence: u64) {
+    match client.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::MessageToClient { data, payload: _, sequence, ack: _ })) => {
+            assert_eq!(data, expected);
+            assert_eq!(sequence, expected_sequence);
+        }
+        _ => unreachable!()
+    };
+}
+
+fn send_to_master(client: &IsolateChannel<ClientEvent>, master: &IsolateChannel<MasterEvent>, expected_sequence: u64) {
+    client.sender.send(ClientEvent::External(ClientExternalEvent::MessageFromClient {
+        transaction_id: "to-master".to_string(),
+        data: "Hello master".to_string(),
+        payload: None,
+        ack: false,
+        unreliable: false,
+    })).unwrap();
+    assert!(expect_client_result(client).is_none());
+    match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::MessageFromClient { client_id: _, data: _, payload: _, sequence, ack: _ })) => {
+            assert_eq!(sequence, expected_sequence);
+        }
+        _ => unreachable!()
+    };
+}
+
+fn master_send(master: &IsolateChannel<MasterEvent>, event: MasterExternalEvent) {
+    master.sender.send(MasterEvent::External(event)).unwrap();
+}
+
+fn message_to_group(master: &IsolateChannel<MasterEvent>, transaction_id: &str) {
+    master_send(master, MasterExternalEvent::MessageToGroup {
+        transaction_id: transaction_id.to_string(),
+        group: "Team".to_string(),
+        data: "Team message".to_string(),
+    });
+}
+
+fn expect_groups(client: &IsolateChannel<ClientEvent>, expected: Vec<&str>) {
+    match client.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::GroupMembership { groups })) => assert_eq!(groups, expected),
+        _ => unreachable!()
+    };
+}
+
+fn resume(client: &IsolateChannel<ClientEvent>, resume_token: &str) {
+    client.sender.send(ClientEvent::External(ClientExternalEvent::Resume {
+        transaction_id: "resume".to_string(),
+        session_id: "Hello World".to_string(),
+        resume_token: resume_token.to_string(),
+    })).unwrap();
+}
+
+#[test]
+pub fn main() {
+    let mut harness = RelayTestHarness::new();
+    let (master, clients) = harness.create_session_with(MasterMetadata {
+        master_id: "Hello World".to_string(),
+        max_clients: 1,
+        offline_queue_limit: 4,
+        offline_queue_ttl_ms: 300,
+        ..Default::default()
+    }, 1);
+    let client_id = match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::ClientJoined { client_id, name: _, role: _ })) => client_id,
+        _ => unreachable!()
+    };
+    let resume_token = match clients[0].receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::ResumeToken { token })) => token,
+        _ => unreachable!()
+    };
+
+    // Messages are numbered both ways before the connection drops
+    send_to_client(&master, &client_id, "0");
+    expect_client_message(&clients[0], "Message 0", 1);
+    assert!(expect_master_result(&master, "0").is_none());
+    send_to_master(&clients[0], &master, 1);
+    master_send(&master, MasterExternalEvent::CreateGroup { transaction_id: "group".to_string(), group: "Team".to_string() });
+    assert!(expect_master_result(&master, "group").is_none());
+    master_send(&master, MasterExternalEvent::AddToGroup {
+        transaction_id: "add".to_string(),
+        group: "Team".to_string(),
+        client_id: client_id.clone(),
+    });
+    expect_groups(&clients[0], vec!("Team"));
+    assert!(expect_master_result(&master, "add").is_none());
+
+    // The connection drops, but the slot is held
+    clients[0].sender.send(ClientEvent::Control(ClientControlEvent::ClientDisconnected {
+        reason: format!("Test")
+    })).unwrap();
+    expect_disconnected(&master);
+    let stranger = harness.create_client("Player 9");
+    stranger.sender.send(ClientEvent::External(ClientExternalEvent::Join {
+        transaction_id: "join".to_string(),
+        session_id: "Hello World".to_string(),
+        role: ClientRole::Player,
+        waitlist: false,
+    })).unwrap();
+    assert_eq!(expect_client_result(&stranger).unwrap().error_code, ErrorCode::ClientLimitExceeded as i32);
+
+    // Group messages and broadcasts are queued too; they resolve straight away, as they would for a connected client
+    message_to_group(&master, "group-1");
+    assert!(expect_master_result(&master, "group-1").is_none());
+    master_send(&master, MasterExternalEvent::BroadcastToClients {
+        transaction_id: "everyone".to_string(),
+        data: "Everyone".to_string(),
+        exclude: Vec::new(),
+        retain: false,
+    });
+    assert!(expect_master_result(&master, "everyone").is_none());
+
+    // Messages are queued up to the limit
+    send_to_client(&master, &client_id, "1");
+    send_to_client(&master, &client_id, "2");
+    send_to_client(&master, &client_id, "3");
+    assert_eq!(expect_master_result(&master, "3").unwrap().error_code, ErrorCode::OfflineQueueFull as i32);
+
+    // The name alone isn't enough to take the slot
+    let impostor = harness.create_client("Player 0");
+    resume(&impostor, "");
+    assert_eq!(expect_client_result(&impostor).unwrap().error_code, ErrorCode::NoMatchingClientId as i32);
+    resume(&impostor, "not the token");
+    assert_eq!(expect_client_result(&impostor).unwrap().error_code, ErrorCode::NoMatchingClientId as i32);
+
+    // Resuming keeps the groups, delivers the queue in order, numbered on from what was already seen, and resolves the transactions
+    let resumed = harness.create_client("Player 0");
+    resume(&resumed, &resume_token);
+    assert!(expect_client_result(&resumed).is_none());
+    expect_groups(&resumed, vec!("Team"));
+    expect_client_message(&resumed, "Team message", 2);
+    expect_client_message(&resumed, "Everyone", 3);
+    expect_client_message(&resumed, "Message 1", 4);
+    expect_client_message(&resumed, "Message 2", 5);
+    let resumed_id = match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::ClientResumed { client_id: resumed_id, previous_client_id })) => {
+            assert_eq!(previous_client_id, client_id);
+            resumed_id
+        }
+        _ => unreachable!()
+    };
+    assert!(expect_master_result(&master, "1").is_none());
+    assert!(expect_master_result(&master, "2").is_none());
+    send_to_master(&resumed, &master, 2);
+
+    // The group now reaches the client on its new connection
+    message_to_group(&master, "group-2");
+    expect_client_message(&resumed, "Team message", 6);
+    assert!(expect_master_result(&master, "group-2").is_none());
+
+    // Clients that don't come back in time are dropped, and their messages expire
+    resumed.sender.send(ClientEvent::Control(ClientControlEvent::ClientDisconnected {
+        reason: format!("Test")
+    })).unwrap();
+    expect_disconnected(&master);
+    send_to_client(&master, &resumed_id, "4");
+    assert_eq!(expect_master_result(&master, "4").unwrap().error_code, ErrorCode::MessageExpired as i32);
+    let late = harness.create_client("Player 0");
+    resume(&late, &resume_token);
+    assert_eq!(expect_client_result(&late).unwrap().error_code, ErrorCode::NoMatchingClientId as i32);
+
+    // Wait for processing to finish
+    thread::sleep(Duration::from_millis(100));
+
+    // Halt everyone
+    master.sender.send(MasterEvent::Control(MasterControlEvent::Halt)).unwrap();
+    stranger.sender.send(ClientEvent::Control(ClientControlEvent::Halt)).unwrap();
+    impostor.sender.send(ClientEvent::Control(ClientControlEvent::Halt)).unwrap();
+    late.sender.send(ClientEvent::Control(ClientControlEvent::Halt)).unwrap();
+
+    harness.complete();
+}
@@ -0,0 +1,132 @@
+use relay::RelayTestHarness;
+use relay_core::events::client_event::ClientControlEvent;
+use relay_core::events::client_event::ClientEvent;
+use relay_core::events::client_event::ClientExternalEvent;
+use relay_core::events::master_event::MasterControlEvent;
+use relay_core::events::master_event::MasterEvent;
+use relay_core::events::master_event::MasterExternalEvent;
+use relay_core::model::external_error::ErrorCode;
+use relay_core::model::external_error::ExternalError;
+use rust_isolate::IsolateChannel;
+use std::thread;
+use std::time::Duration;
+
+fn send_to_master(client: &IsolateChannel<ClientEvent>, transaction_id: &str, ack: bool) {
+    client.sender.send(ClientEvent::External(ClientExternalEvent::MessageFromClient {
+        transaction_id: transaction_id.to_string(),
+        data: format!("Message {}", transaction_id),
+        payload: None,
+        ack,
+        unreliable: false,
+    })).unwrap();
+}
+
+fn send_to_client(master: &IsolateChannel<MasterEvent>, client_id: &str, transaction_id: &str, ack: bool) {
+    master.sender.send(MasterEvent::External(MasterExternalEvent::MessageToClient {
+        transaction_id: transaction_id.to_string(),
+        client_id: client_id.to_string(),
+        data: format!("Message {}", transaction_id),
+        payload: None,
+        ack,
+        unreliable: false,
+    })).unwrap();
+}
+
+fn expect_master_result(master: &IsolateChannel<MasterEvent>, expected_id: &str) -> Option<ExternalError> {
+    match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::TransactionResult { transaction_id, success: _, error, join_code: _ })) => {
+            assert_eq!(transaction_id, expected_id);
+            error
+        }
+        _ => unreachable!()
+    }
+}
+
+fn expect_client_result(client: &IsolateChannel<ClientEvent>, expected_id: &str) -> Option<ExternalError> {
+    match client.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::TransactionResult { transaction_id, success: _, error })) => {
+            assert_eq!(transaction_id, expected_id);
+            error
+        }
+        _ => unreachable!()
+    }
+}
+
+fn expect_from_client(master: &IsolateChannel<MasterEvent>, expected_sequence: u64, expected_ack: bool) {
+    match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::MessageFromClient { client_id: _, data: _, payload: _, sequence, ack })) => {
+            assert_eq!(sequence, expected_sequence);
+            assert_eq!(ack, expected_ack);
+        }
+        _ => unreachable!()
+    };
+}
+
+fn expect_to_client(client: &IsolateChannel<ClientEvent>, expected_sequence: u64, expected_ack: bool) {
+    match client.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::MessageToClient { data: _, payload: _, sequence, ack })) => {
+            assert_eq!(sequence, expected_sequence);
+            assert_eq!(ack, expected_ack);
+        }
+        _ => unreachable!()
+    };
+}
+
+fn master_ack(master: &IsolateChannel<MasterEvent>, client_id: &str, sequence: u64) {
+    master.sender.send(MasterEvent::External(MasterExternalEvent::AckMessage {
+        transaction_id: "ack".to_string(),
+        client_id: client_id.to_string(),
+        sequence,
+    })).unwrap();
+}
+
+#[test]
+pub fn main() {
+    let mut harness = RelayTestHarness::new();
+    let (master, clients) = harness.create_session("Hello World", 1, 2);
+    let client = &clients[0];
+    let client_id = match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::ClientJoined { client_id, name: _, role: _ })) => client_id,
+        _ => unreachable!()
+    };
+
+    // Without an ack the transaction resolves as soon as the master isolate has the message
+    send_to_master(client, "1", false);
+    expect_from_client(&master, 1, false);
+    assert!(expect_client_result(client, "1").is_none());
+
+    // With an ack it waits for the master application
+    send_to_master(client, "2", true);
+    expect_from_client(&master, 2, true);
+    thread::sleep(Duration::from_millis(50));
+    assert!(client.receiver.try_recv().is_err());
+    master_ack(&master, &client_id, 2);
+    assert!(expect_master_result(&master, "ack").is_none());
+    assert!(expect_client_result(client, "2").is_none());
+    master_ack(&master, &client_id, 2);
+    assert_eq!(expect_master_result(&master, "ack").unwrap().error_code, ErrorCode::NoMatchingMessage as i32);
+
+    // The same the other way around
+    send_to_client(&master, &client_id, "3", true);
+    expect_to_client(client, 1, true);
+    thread::sleep(Duration::from_millis(50));
+    assert!(master.receiver.try_recv().is_err());
+    client.sender.send(ClientEvent::External(ClientExternalEvent::AckMessage {
+        transaction_id: "ack".to_string(),
+        sequence: 1,
+    })).unwrap();
+    assert!(expect_client_result(client, "ack").is_none());
+    assert!(expect_master_result(&master, "3").is_none());
+    send_to_client(&master, &client_id, "4", false);
+    expect_to_client(client, 2, false);
+    assert!(expect_master_result(&master, "4").is_none());
+
+    // Wait for processing to finish
+    thread::sleep(Duration::from_millis(100));
+
+    // Halt everyone
+    master.sender.send(MasterEvent::Control(MasterControlEvent::Halt)).unwrap();
+    client.sender.send(ClientEvent::Control(ClientControlEvent::Halt)).unwrap();
+
+    harness.complete();
+}
@@ -0,0 +1,108 @@
+use relay::RelayTestHarness;
+use relay_core::events::client_event::ClientControlEvent;
+use relay_core::events::client_event::ClientEvent;
+use relay_core::events::client_event::ClientExternalEvent;
+use relay_core::events::master_event::MasterControlEvent;
+use relay_core::events::master_event::MasterEvent;
+use relay_core::events::master_event::MasterExternalEvent;
+use relay_core::model::external_error::ErrorCode;
+use relay_core::model::external_error::ExternalError;
+use rust_isolate::IsolateChannel;
+use std::thread;
+use std::time::Duration;
+
+fn request(client: &IsolateChannel<ClientEvent>, request_id: &str, timeout_ms: Option<u64>) -> Option<ExternalError> {
+    client.sender.send(ClientEvent::External(ClientExternalEvent::RequestToMaster {
+        transaction_id: request_id.to_string(),
+        request_id: request_id.to_string(),
+        data: format!("Request {}", request_id),
+        timeout_ms,
+    })).unwrap();
+    match client.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::TransactionResult { transaction_id, success: _, error })) => {
+            assert_eq!(transaction_id, request_id);
+            error
+        }
+        _ => unreachable!()
+    }
+}
+
+fn respond(master: &IsolateChannel<MasterEvent>, transaction_id: &str, client_id: &str, request_id: &str) -> Option<ExternalError> {
+    master.sender.send(MasterEvent::External(MasterExternalEvent::ResponseToClient {
+        transaction_id: transaction_id.to_string(),
+        client_id: client_id.to_string(),
+        request_id: request_id.to_string(),
+        data: format!("Response {}", request_id),
+    })).unwrap();
+    match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::TransactionResult { transaction_id: _, success: _, error, join_code: _ })) => error,
+        _ => unreachable!()
+    }
+}
+
+fn expect_request(master: &IsolateChannel<MasterEvent>, expected_id: &str) {
+    match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::RequestFromClient { client_id: _, request_id, data })) => {
+            assert_eq!(request_id, expected_id);
+            assert_eq!(data, format!("Request {}", expected_id));
+        }
+        _ => unreachable!()
+    };
+}
+
+fn expect_response(client: &IsolateChannel<ClientEvent>, expected_id: &str) -> Result<String, ExternalError> {
+    match client.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::ResponseToClient { request_id, data, error })) => {
+            assert_eq!(request_id, expected_id);
+            match error {
+                Some(e) => Err(e),
+                None => Ok(data),
+            }
+        }
+        _ => unreachable!()
+    }
+}
+
+#[test]
+pub fn main() {
+    let mut harness = RelayTestHarness::new();
+    let (master, clients) = harness.create_session("Hello World", 1, 2);
+    let client = &clients[0];
+    let client_id = match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::ClientJoined { client_id, name: _, role: _ })) => client_id,
+        _ => unreachable!()
+    };
+
+    // The master answers a request
+    assert!(request(client, "1", None).is_none());
+    expect_request(&master, "1");
+    assert!(respond(&master, "respond 1", &client_id, "1").is_none());
+    assert_eq!(expect_response(client, "1").unwrap(), "Response 1");
+
+    // A request can only be answered once
+    let error = respond(&master, "respond 2", &client_id, "1");
+    assert_eq!(error.unwrap().error_code, ErrorCode::NoMatchingRequest as i32);
+
+    // A request nobody answers times out
+    assert!(request(client, "2", Some(50)).is_none());
+    expect_request(&master, "2");
+    let error = expect_response(client, "2").unwrap_err();
+    assert_eq!(error.error_code, ErrorCode::RequestTimeout as i32);
+    let error = respond(&master, "respond 3", &client_id, "2");
+    assert_eq!(error.unwrap().error_code, ErrorCode::NoMatchingRequest as i32);
+
+    // Requests can't wait forever, and never reach the master if they ask to
+    let error = request(client, "3", Some(u64::MAX));
+    assert_eq!(error.unwrap().error_code, ErrorCode::TimeoutOutOfRange as i32);
+    assert!(request(client, "4", None).is_none());
+    expect_request(&master, "4");
+
+    // Wait for processing to finish
+    thread::sleep(Duration::from_millis(100));
+
+    // Halt everyone
+    master.sender.send(MasterEvent::Control(MasterControlEvent::Halt)).unwrap();
+    client.sender.send(ClientEvent::Control(ClientControlEvent::Halt)).unwrap();
+
+    harness.complete();
+}
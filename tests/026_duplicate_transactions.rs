@@ -0,0 +1,121 @@
+use relay::RelayTestHarness;
+use relay_core::events::client_event::ClientControlEvent;
+use relay_core::events::client_event::ClientEvent;
+use relay_core::events::client_event::ClientExternalEvent;
+use relay_core::events::master_event::MasterControlEvent;
+use relay_core::events::master_event::MasterEvent;
+use relay_core::events::master_event::MasterExternalEvent;
+use rust_isolate::IsolateChannel;
+use std::thread;
+use std::time::Duration;
+
+fn send_to_master(client: &IsolateChannel<ClientEvent>, transaction_id: &str, ack: bool) {
+    client.sender.send(ClientEvent::External(ClientExternalEvent::MessageFromClient {
+        transaction_id: transaction_id.to_string(),
+        data: format!("Message {}", transaction_id),
+        payload: None,
+        ack,
+        unreliable: false,
+    })).unwrap();
+}
+
+fn send_to_client(master: &IsolateChannel<MasterEvent>, client_id: &str, transaction_id: &str) {
+    master.sender.send(MasterEvent::External(MasterExternalEvent::MessageToClient {
+        transaction_id: transaction_id.to_string(),
+        client_id: client_id.to_string(),
+        data: format!("Message {}", transaction_id),
+        payload: None,
+        ack: false,
+        unreliable: false,
+    })).unwrap();
+}
+
+fn expect_client_result(client: &IsolateChannel<ClientEvent>, expected_id: &str) {
+    match client.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::TransactionResult { transaction_id, success, error: _ })) => {
+            assert_eq!(transaction_id, expected_id);
+            assert!(success);
+        }
+        _ => unreachable!()
+    };
+}
+
+fn expect_master_result(master: &IsolateChannel<MasterEvent>, expected_id: &str) {
+    match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::TransactionResult { transaction_id, success, error: _, join_code: _ })) => {
+            assert_eq!(transaction_id, expected_id);
+            assert!(success);
+        }
+        _ => unreachable!()
+    };
+}
+
+fn expect_from_client(master: &IsolateChannel<MasterEvent>, expected: &str) -> u64 {
+    match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::MessageFromClient { client_id: _, data, payload: _, sequence, ack: _ })) => {
+            assert_eq!(data, expected);
+            sequence
+        }
+        _ => unreachable!()
+    }
+}
+
+#[test]
+pub fn main() {
+    let mut harness = RelayTestHarness::new();
+    let (master, clients) = harness.create_session("Hello World", 1, 2);
+    let client = &clients[0];
+    let client_id = match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::ClientJoined { client_id, name: _, role: _ })) => client_id,
+        _ => unreachable!()
+    };
+
+    // A retried message is answered again, but only delivered once
+    send_to_master(client, "1", false);
+    expect_from_client(&master, "Message 1");
+    expect_client_result(client, "1");
+    send_to_master(client, "1", false);
+    expect_client_result(client, "1");
+    thread::sleep(Duration::from_millis(50));
+    assert!(master.receiver.try_recv().is_err());
+
+    // A retry while the first attempt is still waiting for an ack is dropped
+    send_to_master(client, "2", true);
+    let sequence = expect_from_client(&master, "Message 2");
+    send_to_master(client, "2", true);
+    thread::sleep(Duration::from_millis(50));
+    assert!(master.receiver.try_recv().is_err());
+    assert!(client.receiver.try_recv().is_err());
+    master.sender.send(MasterEvent::External(MasterExternalEvent::AckMessage {
+        transaction_id: "ack".to_string(),
+        client_id: client_id.clone(),
+        sequence,
+    })).unwrap();
+    expect_master_result(&master, "ack");
+    expect_client_result(client, "2");
+    thread::sleep(Duration::from_millis(50));
+    assert!(client.receiver.try_recv().is_err());
+
+    // The same from the master
+    send_to_client(&master, &client_id, "3");
+    expect_master_result(&master, "3");
+    send_to_client(&master, &client_id, "3");
+    expect_master_result(&master, "3");
+    match client.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::MessageToClient { data, payload: _, sequence: _, ack: _ })) => {
+            assert_eq!(data, "Message 3");
+        }
+        _ => unreachable!()
+    };
+    thread::sleep(Duration::from_millis(50));
+    assert!(client.receiver.try_recv().is_err());
+
+    // Wait for processing to finish
+    thread::sleep(Duration::from_millis(100));
+
+    // Halt everyone
+    master.sender.send(MasterEvent::Control(MasterControlEvent::Halt)).unwrap();
+    client.sender.send(ClientEvent::Control(ClientControlEvent::Halt)).unwrap();
+
+    harness.complete();
+}
@@ -0,0 +1,79 @@
+use relay::RelayTestHarness;
+use relay_core::events::client_event::ClientControlEvent;
+use relay_core::events::client_event::ClientEvent;
+use relay_core::events::client_event::ClientExternalEvent;
+use relay_core::events::master_event::MasterControlEvent;
+use relay_core::events::master_event::MasterEvent;
+use relay_core::events::master_event::MasterExternalEvent;
+use rust_isolate::IsolateChannel;
+use std::thread;
+use std::time::Duration;
+
+fn send_to_master(client: &IsolateChannel<ClientEvent>, transaction_id: &str, ack: bool) {
+    client.sender.send(ClientEvent::External(ClientExternalEvent::MessageFromClient {
+        transaction_id: transaction_id.to_string(),
+        data: format!("Position {}", transaction_id),
+        payload: None,
+        ack,
+        unreliable: true,
+    })).unwrap();
+}
+
+fn send_to_client(master: &IsolateChannel<MasterEvent>, client_id: &str, transaction_id: &str) {
+    master.sender.send(MasterEvent::External(MasterExternalEvent::MessageToClient {
+        transaction_id: transaction_id.to_string(),
+        client_id: client_id.to_string(),
+        data: format!("Position {}", transaction_id),
+        payload: None,
+        ack: false,
+        unreliable: true,
+    })).unwrap();
+}
+
+#[test]
+pub fn main() {
+    let mut harness = RelayTestHarness::new();
+    let (master, clients) = harness.create_session("Hello World", 1, 2);
+    let client = &clients[0];
+    let client_id = match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::ClientJoined { client_id, name: _, role: _ })) => client_id,
+        _ => unreachable!()
+    };
+
+    // The master gets the message, but the client never hears back; an ack is ignored
+    send_to_master(client, "1", false);
+    send_to_master(client, "2", true);
+    for expected in vec!("Position 1", "Position 2") {
+        match master.receiver.recv() {
+            Ok(MasterEvent::External(MasterExternalEvent::MessageFromClient { client_id: _, data, payload: _, sequence: _, ack })) => {
+                assert_eq!(data, expected);
+                assert!(!ack);
+            }
+            _ => unreachable!()
+        };
+    }
+    thread::sleep(Duration::from_millis(50));
+    assert!(client.receiver.try_recv().is_err());
+
+    // The same from the master, and a message to a client that doesn't exist just disappears
+    send_to_client(&master, &client_id, "3");
+    send_to_client(&master, "not-a-client", "4");
+    match client.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::MessageToClient { data, payload: _, sequence: _, ack: _ })) => {
+            assert_eq!(data, "Position 3");
+        }
+        _ => unreachable!()
+    };
+    thread::sleep(Duration::from_millis(50));
+    assert!(master.receiver.try_recv().is_err());
+    assert!(client.receiver.try_recv().is_err());
+
+    // Wait for processing to finish
+    thread::sleep(Duration::from_millis(100));
+
+    // Halt everyone
+    master.sender.send(MasterEvent::Control(MasterControlEvent::Halt)).unwrap();
+    client.sender.send(ClientEvent::Control(ClientControlEvent::Halt)).unwrap();
+
+    harness.complete();
+}
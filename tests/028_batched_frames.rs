@@ -0,0 +1,79 @@
+use relay::server::server_frame;
+use relay_core::events::client_event::ClientExternalEvent;
+use relay_core::events::master_event::MasterExternalEvent;
+use relay_core::model::wire_encoding::WireEncoding;
+use ws::Message;
+use std::thread;
+use std::time::Duration;
+
+fn message_to_master(transaction_id: &str) -> ClientExternalEvent {
+    ClientExternalEvent::MessageFromClient {
+        transaction_id: transaction_id.to_string(),
+        data: format!("Message {}", transaction_id),
+        payload: None,
+        ack: false,
+        unreliable: false,
+    }
+}
+
+fn as_text(frame: Message) -> String {
+    match frame {
+        Message::Text(text) => text,
+        _ => unreachable!()
+    }
+}
+
+fn expect_transaction_ids(events: &Vec<ClientExternalEvent>, expected: Vec<&str>) {
+    let ids: Vec<&str> = events.iter().filter_map(|e| e.transaction_id()).collect();
+    assert_eq!(ids, expected);
+}
+
+#[test]
+pub fn main() {
+    // A single event and an array of events are both valid frames, and order is kept
+    let single = serde_json::to_string(&message_to_master("1")).unwrap();
+    let events = server_frame::parse_frame::<ClientExternalEvent>(WireEncoding::Json, single.as_bytes()).unwrap();
+    expect_transaction_ids(&events, vec!("1"));
+    let batch = serde_json::to_string(&vec!(message_to_master("2"), message_to_master("3"))).unwrap();
+    let events = server_frame::parse_frame::<ClientExternalEvent>(WireEncoding::Json, batch.as_bytes()).unwrap();
+    expect_transaction_ids(&events, vec!("2", "3"));
+
+    // A batch with one bad event is rejected as a whole
+    let broken = format!("[{}, {{\"object_type\": \"nope\"}}]", single);
+    assert!(server_frame::parse_frame::<ClientExternalEvent>(WireEncoding::Json, broken.as_bytes()).is_err());
+    assert!(server_frame::parse_frame::<MasterExternalEvent>(WireEncoding::Json, batch.as_bytes()).is_err());
+
+    // Events with a bad transaction id are picked out of a batch, and the rest still go through
+    let batch = vec!(message_to_master("12"), message_to_master(""), message_to_master("13"));
+    let (events, rejected) = server_frame::check_transaction_ids(batch, |e| e.transaction_id(), |id| !id.is_empty());
+    expect_transaction_ids(&events, vec!("12", "13"));
+    assert_eq!(rejected, vec!("".to_string()));
+
+    // One outbound event goes out on its own, several go out as an array
+    let frame = as_text(server_frame::serialize_frame(WireEncoding::Json, &vec!(message_to_master("4"))).unwrap());
+    assert!(frame.starts_with('{'));
+    let frame = as_text(server_frame::serialize_frame(WireEncoding::Json, &vec!(message_to_master("5"), message_to_master("6"))).unwrap());
+    assert!(frame.starts_with('['));
+    expect_transaction_ids(&server_frame::parse_frame::<ClientExternalEvent>(WireEncoding::Json, frame.as_bytes()).unwrap(), vec!("5", "6"));
+
+    // Events arriving inside the window are collected, anything else is discarded
+    let (sender, receiver) = crossbeam::unbounded::<Option<ClientExternalEvent>>();
+    sender.send(Some(message_to_master("8"))).unwrap();
+    sender.send(None).unwrap();
+    sender.send(Some(message_to_master("9"))).unwrap();
+    let mut batch = vec!(message_to_master("7"));
+    let open = server_frame::coalesce(&receiver, Duration::from_millis(50), &mut batch, |m| m.ok_or(None), |_| {});
+    assert!(open);
+    expect_transaction_ids(&batch, vec!("7", "8", "9"));
+
+    // A closed channel ends the batch early
+    let sender_thread = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(10));
+        sender.send(Some(message_to_master("11"))).unwrap();
+    });
+    let mut batch = vec!(message_to_master("10"));
+    let open = server_frame::coalesce(&receiver, Duration::from_millis(1000), &mut batch, |m| m.ok_or(None), |_| {});
+    sender_thread.join().unwrap();
+    assert!(!open);
+    expect_transaction_ids(&batch, vec!("10", "11"));
+}
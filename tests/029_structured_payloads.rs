@@ -0,0 +1,96 @@
+use relay::RelayTestHarness;
+use relay_core::events::client_event::ClientControlEvent;
+use relay_core::events::client_event::ClientEvent;
+use relay_core::events::client_event::ClientExternalEvent;
+use relay_core::events::master_event::MasterControlEvent;
+use relay_core::events::master_event::MasterEvent;
+use relay_core::events::master_event::MasterExternalEvent;
+use relay_core::model::raw_payload;
+use relay_core::model::wire_encoding::WireEncoding;
+use serde_json::json;
+use serde_json::value::RawValue;
+use serde_json::Value;
+use std::thread;
+use std::time::Duration;
+
+fn as_value(payload: Option<Box<RawValue>>) -> Value {
+    serde_json::from_str(payload.unwrap().get()).unwrap()
+}
+
+#[test]
+pub fn main() {
+    let mut harness = RelayTestHarness::new();
+    let (master, clients) = harness.create_session("Hello World", 1, 2);
+    let client = &clients[0];
+    let client_id = match master.receiver.recv() {
+        Ok(MasterEvent::External(MasterExternalEvent::ClientJoined { client_id, name: _, role: _ })) => client_id,
+        _ => unreachable!()
+    };
+
+    // A client can leave out data entirely and send a structured payload
+    let raw = r#"{"object_type": "MessageFromClient", "transaction_id": "1", "payload": {"move": [1, 2]}}"#;
+    let message = WireEncoding::Json.decode_event::<ClientExternalEvent>(raw.as_bytes()).unwrap();
+    client.sender.send(ClientEvent::External(message)).unwrap();
+    match master.receiver.recv() {
+        Ok(MasterEvent::External(event)) => {
+            // The payload is embedded exactly as it arrived, not escaped into a string or written out again
+            let serialized = serde_json::to_string(&event).unwrap();
+            assert!(serialized.contains(r#""payload":{"move": [1, 2]}"#));
+            match event {
+                MasterExternalEvent::MessageFromClient { client_id: _, data, payload, sequence: _, ack: _ } => {
+                    assert_eq!(data, "");
+                    assert_eq!(as_value(payload), json!({"move": [1, 2]}));
+                }
+                _ => unreachable!()
+            }
+        }
+        _ => unreachable!()
+    };
+    match client.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::TransactionResult { transaction_id: _, success, error: _ })) => assert!(success),
+        _ => unreachable!()
+    };
+
+    // The same from the master
+    master.sender.send(MasterEvent::External(MasterExternalEvent::MessageToClient {
+        transaction_id: "2".to_string(),
+        client_id,
+        data: String::new(),
+        payload: Some(raw_payload::to_raw(&json!({"score": [1, 0]})).unwrap()),
+        ack: false,
+        unreliable: false,
+    })).unwrap();
+    match client.receiver.recv() {
+        Ok(ClientEvent::External(ClientExternalEvent::MessageToClient { data: _, payload, sequence: _, ack: _ })) => {
+            assert_eq!(as_value(payload), json!({"score": [1, 0]}));
+        }
+        _ => unreachable!()
+    };
+
+    // Batches keep their payloads raw too, and other events in them are read as usual
+    let batch = r#"[{"object_type": "MessageFromClient", "transaction_id": "3", "payload": [ 1 ]}, {"object_type": "Leave", "transaction_id": "4"}]"#;
+    let events = WireEncoding::Json.decode_events::<ClientExternalEvent>(batch.as_bytes()).unwrap();
+    match &events[0] {
+        ClientExternalEvent::MessageFromClient { payload: Some(payload), .. } => assert_eq!(payload.get(), "[ 1 ]"),
+        _ => unreachable!()
+    }
+    assert_eq!(events[1].transaction_id(), Some("4"));
+
+    // Messages without a payload don't mention it on the wire at all
+    let serialized = serde_json::to_string(&ClientExternalEvent::MessageToClient {
+        data: "Hello".to_string(),
+        payload: None,
+        sequence: 1,
+        ack: false,
+    }).unwrap();
+    assert!(!serialized.contains("payload"));
+
+    // Wait for processing to finish
+    thread::sleep(Duration::from_millis(100));
+
+    // Halt everyone
+    master.sender.send(MasterEvent::Control(MasterControlEvent::Halt)).unwrap();
+    client.sender.send(ClientEvent::Control(ClientControlEvent::Halt)).unwrap();
+
+    harness.complete();
+}
@@ -0,0 +1,81 @@
+use relay::server::server_frame;
+use relay_auth::AuthRequest;
+use relay_core::events::client_event::ClientExternalEvent;
+use relay_core::events::master_event::MasterExternalEvent;
+use relay_core::model::wire_encoding::WireEncoding;
+use relay_core::model::raw_payload;
+use serde_json::json;
+use serde_json::Value;
+use ws::Message;
+
+fn message_to_master(transaction_id: &str) -> ClientExternalEvent {
+    ClientExternalEvent::MessageFromClient {
+        transaction_id: transaction_id.to_string(),
+        data: format!("Message {}", transaction_id),
+        payload: Some(raw_payload::to_raw(&json!({"move": [1, 2], "fast": true})).unwrap()),
+        ack: true,
+        unreliable: false,
+    }
+}
+
+fn as_binary(frame: Message) -> Vec<u8> {
+    match frame {
+        Message::Binary(raw) => raw,
+        _ => unreachable!()
+    }
+}
+
+fn check_encoding(encoding: WireEncoding) {
+    // Tagged events, including structured payloads, survive the trip
+    let raw = encoding.encode(&message_to_master("1")).unwrap();
+    assert!(!encoding.is_array(&raw));
+    match encoding.decode::<ClientExternalEvent>(&raw).unwrap() {
+        ClientExternalEvent::MessageFromClient { transaction_id, data, payload, ack, unreliable } => {
+            assert_eq!(transaction_id, "1");
+            assert_eq!(data, "Message 1");
+            assert_eq!(serde_json::from_str::<Value>(payload.unwrap().get()).unwrap(), json!({"move": [1, 2], "fast": true}));
+            assert!(ack);
+            assert!(!unreliable);
+        }
+        _ => unreachable!()
+    }
+    assert!(encoding.decode::<MasterExternalEvent>(&raw).is_err());
+
+    // The payload is a native map in the encoding, not a JSON string inside it
+    let generic = encoding.decode::<Value>(&raw).unwrap();
+    assert_eq!(generic["payload"]["fast"], json!(true));
+
+    // Single events and batches both go out as binary frames
+    let frame = as_binary(server_frame::serialize_frame(encoding, &vec!(message_to_master("2"))).unwrap());
+    let events = server_frame::parse_frame::<ClientExternalEvent>(encoding, &frame).unwrap();
+    assert_eq!(events.len(), 1);
+    let frame = as_binary(server_frame::serialize_frame(encoding, &vec!(message_to_master("3"), message_to_master("4"))).unwrap());
+    assert!(encoding.is_array(&frame));
+    let events = server_frame::parse_frame::<ClientExternalEvent>(encoding, &frame).unwrap();
+    let ids: Vec<&str> = events.iter().filter_map(|e| e.transaction_id()).collect();
+    assert_eq!(ids, vec!("3", "4"));
+
+    // So does the auth request in the connection token
+    let request = AuthRequest { expires: 123, key: "key".to_string(), hash: Some("hash".to_string()) };
+    let decoded = encoding.decode::<AuthRequest>(&encoding.encode(&request).unwrap()).unwrap();
+    assert_eq!(decoded.expires, 123);
+    assert_eq!(decoded.key, "key");
+    assert_eq!(decoded.hash, Some("hash".to_string()));
+}
+
+#[test]
+pub fn main() {
+    // Connections pick an encoding by name, and JSON is the default
+    assert_eq!(WireEncoding::default(), WireEncoding::Json);
+    assert_eq!(WireEncoding::from_name("msgpack"), Some(WireEncoding::MessagePack));
+    assert_eq!(WireEncoding::from_name("cbor"), Some(WireEncoding::Cbor));
+    assert_eq!(WireEncoding::from_name("xml"), None);
+    assert!(!WireEncoding::Json.is_binary());
+
+    check_encoding(WireEncoding::MessagePack);
+    check_encoding(WireEncoding::Cbor);
+
+    // A binary frame is never mistaken for JSON
+    let raw = WireEncoding::MessagePack.encode(&message_to_master("5")).unwrap();
+    assert!(server_frame::parse_frame::<ClientExternalEvent>(WireEncoding::Json, &raw).is_err());
+}
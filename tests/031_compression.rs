@@ -0,0 +1,122 @@
+use relay_core::model::wire_compression::{WireCompression, WireCompressionError, DEFAULT_COMPRESSION_LEVEL, DEFAULT_MAX_MESSAGE_BYTES};
+
+const TEXT: u8 = 1;
+const BINARY: u8 = 2;
+const CONTINUE: u8 = 0;
+
+fn negotiate(threshold: usize, max_message_bytes: usize) -> (WireCompression, WireCompression) {
+    let mut server = WireCompression::new(threshold, DEFAULT_COMPRESSION_LEVEL, max_message_bytes);
+    let mut client = WireCompression::new(threshold, DEFAULT_COMPRESSION_LEVEL, max_message_bytes);
+    let response = server.accept(&[client.offer()]).unwrap();
+    client.confirm(&[response]);
+    assert!(server.is_negotiated());
+    assert!(client.is_negotiated());
+    (server, client)
+}
+
+fn large_message(id: u32) -> Vec<u8> {
+    format!("{{\"object_type\": \"MessageFromClient\", \"transaction_id\": \"{}\", \"data\": \"{}\"}}", id, "Hello World ".repeat(100)).into_bytes()
+}
+
+#[test]
+pub fn main() {
+    // Nothing is compressed until both sides agree
+    let mut idle = WireCompression::new(0, DEFAULT_COMPRESSION_LEVEL, DEFAULT_MAX_MESSAGE_BYTES);
+    assert!(!idle.is_negotiated());
+    assert!(idle.compress(&large_message(0)).is_none());
+
+    // Offers that limit the server window are turned down, and so is anything that isn't permessage-deflate
+    let mut server = WireCompression::new(0, DEFAULT_COMPRESSION_LEVEL, DEFAULT_MAX_MESSAGE_BYTES);
+    assert!(server.accept(&["permessage-deflate; server_max_window_bits=10"]).is_none());
+    assert!(server.accept(&["x-webkit-deflate-frame"]).is_none());
+    assert!(!server.is_negotiated());
+    let mut client = WireCompression::new(0, DEFAULT_COMPRESSION_LEVEL, DEFAULT_MAX_MESSAGE_BYTES);
+    client.confirm(&[]);
+    assert!(!client.is_negotiated());
+
+    // Large messages shrink, and come back out the same on the other side
+    let (mut server, mut client) = negotiate(256, DEFAULT_MAX_MESSAGE_BYTES);
+    let message = large_message(1);
+    let compressed = client.compress(&message).unwrap();
+    assert!(compressed.len() < message.len());
+    match server.read(TEXT, &compressed, true) {
+        Some(Ok((opcode, data))) => {
+            assert_eq!(opcode, TEXT);
+            assert_eq!(data, message);
+        }
+        _ => unreachable!()
+    }
+
+    // Small messages go out as they are
+    assert!(client.compress(b"{\"object_type\": \"Ping\"}").is_none());
+
+    // Each message stands alone, so several in a row all read back
+    for id in 2..5 {
+        let message = large_message(id);
+        let compressed = server.compress(&message).unwrap();
+        match client.read(BINARY, &compressed, true) {
+            Some(Ok((opcode, data))) => {
+                assert_eq!(opcode, BINARY);
+                assert_eq!(data, message);
+            }
+            _ => unreachable!()
+        }
+    }
+
+    // A message split over several frames is only returned after the last one
+    let message = large_message(5);
+    let compressed = client.compress(&message).unwrap();
+    let (first, second) = compressed.split_at(compressed.len() / 2);
+    assert!(server.read(TEXT, first, false).is_none());
+    assert!(server.is_reading());
+    match server.read(CONTINUE, second, true) {
+        Some(Ok((opcode, data))) => {
+            assert_eq!(opcode, TEXT);
+            assert_eq!(data, message);
+        }
+        _ => unreachable!()
+    }
+    assert!(!server.is_reading());
+
+    // Byte counts only cover what was compressed
+    assert_eq!(client.raw_bytes(), (large_message(1).len() + large_message(5).len()) as u64);
+    assert!(client.sent_bytes() < client.raw_bytes());
+    assert!(server.sent_bytes() < server.raw_bytes());
+    assert_eq!(client.ratio_percent(), Some(client.sent_bytes() * 100 / client.raw_bytes()));
+    assert!(client.ratio_percent().unwrap() < 100);
+    assert_eq!(idle.ratio_percent(), None);
+
+    // Garbage is reported, not returned
+    let (mut server, _) = negotiate(0, DEFAULT_MAX_MESSAGE_BYTES);
+    match server.read(TEXT, &[0xff, 0xff, 0xff, 0xff, 0xff], true) {
+        Some(Err(WireCompressionError::Corrupt(_))) => {}
+        _ => unreachable!()
+    }
+
+    // A small message can't inflate past the size limit
+    let (mut server, mut client) = negotiate(0, 4096);
+    let bomb = client.compress(&vec![b' '; 1024 * 1024]).unwrap();
+    assert!(bomb.len() < 4096);
+    match server.read(TEXT, &bomb, true) {
+        Some(Err(WireCompressionError::MessageTooLarge)) => {}
+        _ => unreachable!()
+    }
+
+    // Messages at the limit still read back
+    let (mut server, mut client) = negotiate(0, 4096);
+    let message = vec![b' '; 4096];
+    let compressed = client.compress(&message).unwrap();
+    match server.read(TEXT, &compressed, true) {
+        Some(Ok((_, data))) => assert_eq!(data, message),
+        _ => unreachable!()
+    }
+
+    // Nor can a message split over frames be buffered past the limit
+    let (mut server, _) = negotiate(0, 4096);
+    assert!(server.read(TEXT, &[0; 3000], false).is_none());
+    match server.read(CONTINUE, &[0; 3000], false) {
+        Some(Err(WireCompressionError::MessageTooLarge)) => {}
+        _ => unreachable!()
+    }
+    assert!(!server.is_reading());
+}